@@ -0,0 +1,95 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+/// Responses stored on disk, each for `ttl` after it was received.
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    /// Returns the response stored for `request` if it has not expired.
+    pub fn get(&self, request: &str) -> Option<String> {
+        let path = self.path(request);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age >= self.ttl {
+            return None;
+        }
+        let response = fs::read_to_string(&path).ok()?;
+        debug!("using the cached response in {}", path.display());
+        Some(response)
+    }
+
+    /// Stores `response` for `request`. Failing to do so only costs a
+    /// request later, so it is a warning rather than an error.
+    pub fn put(&self, request: &str, response: &str) {
+        let path = self.path(request);
+        let stored = fs::create_dir_all(&self.dir).and_then(|()| fs::write(&path, response));
+        if let Err(e) = stored {
+            warn!("cannot cache the response in {}: {e}", path.display());
+        }
+    }
+
+    /// Removes the expired responses, which would otherwise pile up when the
+    /// same requests are rarely made again. Failing to remove one is only
+    /// logged.
+    pub fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let expired = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= self.ttl);
+            if expired {
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    debug!("cannot remove {}: {e}", entry.path().display());
+                }
+            }
+        }
+    }
+
+    fn path(&self, request: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        request.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[test]
+    fn expired_responses_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf(), Duration::from_secs(3600));
+        cache.put("old", "1");
+        cache.put("new", "2");
+        File::options()
+            .write(true)
+            .open(cache.path("old"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(7200))
+            .unwrap();
+
+        cache.prune();
+        assert!(!cache.path("old").exists());
+        assert_eq!(cache.get("new").as_deref(), Some("2"));
+    }
+}
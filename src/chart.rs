@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use crate::issue::DailyIssues;
+
+/// The height of the bars of a chart, in pixels.
+const HEIGHT: usize = 80;
+/// The width of a bar, in pixels.
+const BAR_WIDTH: usize = 4;
+/// The space between the bars of adjacent days, in pixels.
+const GAP: usize = 2;
+/// The height of the legend under the bars, in pixels.
+const LEGEND_HEIGHT: usize = 20;
+
+const CREATED_COLOR: &str = "#0969da";
+const CLOSED_COLOR: &str = "#1a7f37";
+
+/// Draws the issues created and closed each day as pairs of bars in an SVG
+/// image, to be embedded in an HTML report without any external resources.
+/// Each bar has a title giving its date and count.
+#[must_use]
+pub fn issue_chart(days: &[DailyIssues]) -> String {
+    let max = days
+        .iter()
+        .map(|day| day.created.max(day.closed))
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    let width = (days.len() * (2 * BAR_WIDTH + GAP)).max(200);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{}\" \
+         role=\"img\" aria-label=\"Issues created and closed per day\">",
+        HEIGHT + LEGEND_HEIGHT
+    );
+    for (i, day) in days.iter().enumerate() {
+        let x = i * (2 * BAR_WIDTH + GAP);
+        bar(&mut svg, x, day.created, max, CREATED_COLOR, day, "created");
+        bar(
+            &mut svg,
+            x + BAR_WIDTH,
+            day.closed,
+            max,
+            CLOSED_COLOR,
+            day,
+            "closed",
+        );
+    }
+    let y = HEIGHT + LEGEND_HEIGHT - 5;
+    let _ = write!(
+        svg,
+        "<text x=\"0\" y=\"{y}\" font-size=\"12\" fill=\"{CREATED_COLOR}\">created</text>\
+         <text x=\"60\" y=\"{y}\" font-size=\"12\" fill=\"{CLOSED_COLOR}\">closed</text>\
+         <text x=\"120\" y=\"{y}\" font-size=\"12\" fill=\"#57606a\">max {max}/day</text></svg>"
+    );
+    svg
+}
+
+/// Appends a bar of `count` out of `max` at `x`, titled with the date of
+/// `day` and what is counted.
+fn bar(
+    svg: &mut String,
+    x: usize,
+    count: usize,
+    max: usize,
+    color: &str,
+    day: &DailyIssues,
+    what: &str,
+) {
+    let height = count * HEIGHT / max;
+    let _ = write!(
+        svg,
+        "<rect x=\"{x}\" y=\"{}\" width=\"{BAR_WIDTH}\" height=\"{height}\" fill=\"{color}\">\
+         <title>{}: {count} {what}</title></rect>",
+        HEIGHT - height,
+        day.date
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn each_day_has_a_bar_per_series() {
+        let first = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let days = (0..5)
+            .map(|i| DailyIssues {
+                date: first + chrono::Days::new(i),
+                created: usize::try_from(i).unwrap(),
+                closed: 2,
+            })
+            .collect::<Vec<_>>();
+
+        let svg = issue_chart(&days);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect ").count(), 10);
+        assert!(svg.contains("<title>2024-01-05: 4 created</title>"));
+        assert!(svg.contains("<title>2024-01-01: 2 closed</title>"));
+        assert!(svg.contains("<rect x=\"40\" y=\"0\" width=\"4\" height=\"80\" fill=\"#0969da\">"));
+    }
+}
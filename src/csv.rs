@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use crate::issue::IndividualStats;
+use crate::report::Report;
+
+/// The columns following the username, as the header and how to get each.
+#[allow(clippy::type_complexity)]
+const COLUMNS: [(&str, fn(&IndividualStats) -> String); 15] = [
+    ("bugs_reported", |s| s.bugs_reported.to_string()),
+    ("issues_completed", |s| s.issues_completed.to_string()),
+    ("issues_opened", |s| s.issues_opened.to_string()),
+    ("pull_requests_merged", |s| {
+        s.merged_merge_requests_opened.to_string()
+    }),
+    ("pull_request_comments", |s| {
+        s.merge_request_notes.to_string()
+    }),
+    ("reviews_given", |s| s.reviews_given.to_string()),
+    ("issue_comments", |s| s.issue_comments.to_string()),
+    ("lines_contributed", |s| s.lines_contributed.to_string()),
+    ("test_lines_contributed", |s| {
+        s.test_lines_contributed.to_string()
+    }),
+    ("lines_removed", |s| s.lines_removed.to_string()),
+    ("median_issues_completed", |s| {
+        s.median_per_day.issues_completed.to_string()
+    }),
+    ("median_issues_opened", |s| {
+        s.median_per_day.issues_opened.to_string()
+    }),
+    ("median_bugs_reported", |s| {
+        s.median_per_day.bugs_reported.to_string()
+    }),
+    ("median_pull_requests_merged", |s| {
+        s.median_per_day.merged_merge_requests_opened.to_string()
+    }),
+    ("median_reviews_given", |s| {
+        s.median_per_day.reviews_given.to_string()
+    }),
+];
+
+/// Writes the individual statistics in `report` as CSV, one row per user,
+/// for spreadsheets.
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails.
+pub fn write_csv(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    write!(out, "username")?;
+    for (name, _) in COLUMNS {
+        write!(out, ",{name}")?;
+    }
+    write!(out, "\r\n")?;
+    for (username, stats) in &report.individual_stats {
+        write!(out, "{}", escape(username))?;
+        for (_, value) in COLUMNS {
+            write!(out, ",{}", value(stats))?;
+        }
+        write!(out, "\r\n")?;
+    }
+    Ok(())
+}
+
+/// Quotes `field` if it contains a character that would otherwise end it.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,206 +1,2196 @@
-use std::collections::{BTreeMap, HashMap};
-use std::env;
-use std::io;
-use std::path::Path;
-use std::process::Command;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::iter;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
+use indicatif::ProgressBar;
+use log::{debug, warn};
 use regex::RegexSet;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
+use crate::cache::Cache;
+use crate::proxy::ProxyConfig;
+
+/// The git executable set by `use_git`, if any, in place of the one on
+/// `PATH`.
+static PROGRAM: OnceLock<PathBuf> = OnceLock::new();
+
+/// The directory under the git directory of a repository that blamed lines
+/// are cached in. As the checkouts live under the cache directory of
+/// pbmetric, so does this, and it goes away along with its checkout.
+const BLAME_CACHE_DIR: &str = "pbmetric-blame";
+
+/// How long to keep blamed lines. They are keyed on the window, so they only
+/// help runs for the same window, such as while tuning the configuration;
+/// those of a scheduled run are useless by the next one.
+const BLAME_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The file at the root of a repository conventionally listing the commits
+/// for blame to ignore.
+const IGNORE_REVS_FILE: &str = ".git-blame-ignore-revs";
+
+/// How long to wait before retrying a git command that failed because of the
+/// network.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
 #[derive(Deserialize)]
 pub struct Repo {
-    url: String,
+    pub url: String,
     pub exclude: Option<Vec<String>>,
+    /// Branches to count lines on besides the default one, as of the same
+    /// date. A line on more than one of them is counted once, recognized by
+    /// the commit that introduced it and its position in that commit.
+    #[serde(default)]
+    pub branches: Vec<String>,
+    #[serde(flatten)]
+    pub blame: BlameOptions,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BlameOptions {
+    /// Ignores whitespace-only changes so that reformatting a file does not
+    /// take credit away from the authors of its lines. Enabled by default;
+    /// note that turning it on or off changes the numbers for past windows
+    /// as well.
+    pub ignore_whitespace: bool,
+    /// Detects lines moved or copied within and across files, crediting
+    /// them to their original authors. This makes blaming considerably
+    /// slower.
+    pub detect_moves: bool,
+    /// Splits the credit for each line evenly among the commit author and the
+    /// co-authors listed in its `Co-authored-by` trailers.
+    pub credit_co_authors: bool,
+    /// Gives no credit for lines last changed by a merge commit, which are
+    /// those written to resolve conflicts, so that whoever merges is not
+    /// credited for work done on the merged branch. This keeps the numbers
+    /// of repositories merging branches comparable to those of repositories
+    /// rebasing them. Enabled by default.
+    pub ignore_merges: bool,
+    /// Scales the credit for each line down the longer ago it was committed,
+    /// so that the numbers reflect recent momentum. Every line in the window
+    /// counts fully if not set, which is the default.
+    pub recency: Option<RecencyWeight>,
+    /// Which date of a commit decides whether its lines fall within the
+    /// window. The author date, the default, stays the same when a commit is
+    /// rebased or cherry-picked, so that lines written long ago are not
+    /// credited again when their branch is finally rebased and merged. The
+    /// committer date changes with every rebase, crediting lines to the
+    /// window in which they landed instead.
+    pub date: CommitDate,
+    /// Commits, such as bulk imports or vendoring, whose lines are credited
+    /// to whoever last changed them before instead. Lines only such a commit
+    /// wrote are credited to no one, and its removals are not counted.
+    pub ignore_commits: Vec<String>,
+    /// A file listing more commits to ignore, one per line with `#` starting
+    /// a comment, as `.git-blame-ignore-revs` does, relative to the root of
+    /// the repository.
+    pub ignore_revs_file: Option<PathBuf>,
+    /// Reads `.git-blame-ignore-revs` at the root of the repository, if there
+    /// is one, when no `ignore_revs_file` is given, as setting
+    /// `blame.ignoreRevsFile` in git does. Enabled by default.
+    pub detect_ignore_revs_file: bool,
+}
+
+impl Default for BlameOptions {
+    fn default() -> Self {
+        Self {
+            ignore_whitespace: true,
+            detect_moves: false,
+            credit_co_authors: false,
+            ignore_merges: true,
+            recency: None,
+            date: CommitDate::Author,
+            ignore_commits: Vec::new(),
+            ignore_revs_file: None,
+            detect_ignore_revs_file: true,
+        }
+    }
+}
+
+/// A date recorded in a commit.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitDate {
+    /// When the change was first made.
+    #[default]
+    Author,
+    /// When the commit was last made, such as by a rebase.
+    Committer,
+}
+
+impl CommitDate {
+    /// Returns the key of this date in the output of `git blame --porcelain`.
+    fn porcelain_key(self) -> &'static str {
+        match self {
+            Self::Author => "author-time ",
+            Self::Committer => "committer-time ",
+        }
+    }
+
+    /// Returns the placeholder of this date as a Unix timestamp in the
+    /// format of `git log`.
+    fn log_placeholder(self) -> &'static str {
+        match self {
+            Self::Author => "%at",
+            Self::Committer => "%ct",
+        }
+    }
+}
+
+/// How the credit for a line decays with the age of its commit.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(tag = "decay", rename_all = "snake_case")]
+pub enum RecencyWeight {
+    /// Falls linearly from full credit for a line committed at the end of the
+    /// window to none at its start.
+    Linear,
+    /// Halves every `half_life_days`.
+    Exponential { half_life_days: f64 },
+}
+
+impl RecencyWeight {
+    /// Returns the share of credit for a line committed at `time` within
+    /// `[since, asof]`.
+    fn weight(self, time: &DateTime<Utc>, since: &DateTime<Utc>, asof: &DateTime<Utc>) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let age = (*asof - *time).num_seconds() as f64;
+        match self {
+            Self::Linear => {
+                #[allow(clippy::cast_precision_loss)]
+                let window = (*asof - *since).num_seconds() as f64;
+                if window <= 0. {
+                    1.
+                } else {
+                    1. - age / window
+                }
+            }
+            Self::Exponential { half_life_days } => 0.5_f64.powf(age / (half_life_days * 86_400.)),
+        }
+    }
+}
+
+/// The lines credited to an author, split by whether they are in production
+/// or test code, and the lines they removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineCount {
+    pub production: f64,
+    pub test: f64,
+    /// Lines deleted by the author's commits in the window, which are not
+    /// part of the total.
+    pub removed: f64,
+}
+
+impl LineCount {
+    #[must_use]
+    pub fn total(self) -> f64 {
+        self.production + self.test
+    }
+}
+
+impl AddAssign for LineCount {
+    fn add_assign(&mut self, other: Self) {
+        self.production += other.production;
+        self.test += other.test;
+        self.removed += other.removed;
+    }
+}
+
+/// Makes git commands run `program`, such as one outside `PATH`, after
+/// checking that it runs. Returns the version it reports.
+///
+/// # Errors
+///
+/// Returns an error if `program` cannot run or another program has already
+/// been set.
+pub fn use_git<P: AsRef<Path>>(program: P) -> io::Result<String> {
+    let program = program.as_ref();
+    let version = output(Command::new(program).arg("--version"))
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {e}", program.display())))?;
+    if PROGRAM.get_or_init(|| program.to_path_buf()) != program {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "git is already set to another program",
+        ));
+    }
+    Ok(version)
+}
+
+/// Returns a command running the git executable set by `use_git`.
+fn git_command() -> Command {
+    Command::new(PROGRAM.get().map_or(Path::new("git"), PathBuf::as_path))
+}
+
+/// A tag and the time it was made.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub date: DateTime<Utc>,
+}
+
+/// Returns the most recent tag reachable from the checkout of the repository
+/// at `path`, as found by `git describe`, with the commit date of the commit
+/// it points to, or `None` if there is no such tag.
+///
+/// # Errors
+///
+/// Returns an error if a git command fails.
+pub fn last_tag<P: AsRef<Path>>(path: P) -> io::Result<Option<Tag>> {
+    let path = path.as_ref();
+    let described = git(path)
+        .args(["describe", "--tags", "--abbrev=0"])
+        .stderr(Stdio::null())
+        .output()?;
+    // `git describe` fails when no tag is reachable.
+    if !described.status.success() {
+        return Ok(None);
+    }
+    let name = String::from_utf8_lossy(&described.stdout)
+        .trim()
+        .to_string();
+    let date =
+        output(git(path).args(["log", "-1", "--format=%cI", &format!("{name}^{{commit}}")]))?;
+    let date = DateTime::parse_from_rfc3339(&date)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Some(Tag {
+        name,
+        date: date.with_timezone(&Utc),
+    }))
+}
+
+/// Returns whether any commit on the checkout of the repository at `path`,
+/// or on `branches` as of `asof`, was made within `[since, asof]` by `date`.
+/// A repository without one has no lines to credit, so it need not be
+/// blamed.
+///
+/// # Errors
+///
+/// Returns an error if a git command fails.
+pub fn has_commits_between<P: AsRef<Path>>(
+    path: P,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    branches: &[String],
+    date: CommitDate,
+) -> io::Result<bool> {
+    let path = path.as_ref();
+    let mut revisions = vec!["HEAD".to_string()];
+    for branch in branches {
+        revisions.extend(branch_commit(path, branch, asof)?);
+    }
+    // A commit is committed no earlier than it is authored, so `--since`
+    // keeps every commit authored within the window.
+    let output = output(
+        git(path)
+            .arg("log")
+            .arg(format!("--format={}", date.log_placeholder()))
+            .arg(format!("--since={}", since.to_rfc3339()))
+            .args(&revisions)
+            .arg("--"),
+    )?;
+    Ok(output.lines().any(|line| {
+        line.parse()
+            .ok()
+            .and_then(|time| DateTime::from_timestamp(time, 0))
+            .is_some_and(|time| *since <= time && time <= *asof)
+    }))
+}
+
+/// Returns the tags made within `[since, asof]` in the repository at `path`,
+/// oldest first. An annotated tag is dated when it was tagged, and a
+/// lightweight one by the commit it points to.
+///
+/// # Errors
+///
+/// Returns an error if a git command fails.
+pub fn tags_between<P: AsRef<Path>>(
+    path: P,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> io::Result<Vec<Tag>> {
+    let refs = output(git(path.as_ref()).args([
+        "for-each-ref",
+        "--sort=creatordate",
+        "--format=%(creatordate:iso-strict) %(refname:short)",
+        "refs/tags",
+    ]))?;
+    let mut tags = Vec::new();
+    for line in refs.lines() {
+        let Some((date, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let Ok(date) = DateTime::parse_from_rfc3339(date) else {
+            warn!(r#"invalid date of tag {name}: "{date}""#);
+            continue;
+        };
+        let date = date.with_timezone(&Utc);
+        if *since <= date && date <= *asof {
+            tags.push(Tag {
+                name: name.to_string(),
+                date,
+            });
+        }
+    }
+    Ok(tags)
 }
 
+/// Clones the repositories under `root` that are missing and checks out each
+/// as of `asof`, fetching first unless `offline` is set. Cloning and fetching
+/// are retried up to `retries` times when they fail because of the network.
+///
+/// # Errors
+///
+/// Returns an error if a git command fails.
 pub fn update_all<P: AsRef<Path>>(
     root: P,
     repos: &BTreeMap<String, Repo>,
     asof: &DateTime<Utc>,
     offline: bool,
+    retries: u32,
+    proxy: Option<&ProxyConfig>,
 ) -> io::Result<()> {
     let mut path = root.as_ref().to_path_buf();
     for (name, repo) in repos {
         path.push(name);
+        let proxy = proxy.and_then(|proxy| proxy.for_url(&repo.url));
         if !path.exists() {
-            clone(&repo.url, &path)?;
+            clone(&repo.url, &path, retries, proxy)?;
         }
-        update(&path, asof, offline)?;
+        update(&path, asof, offline, retries, proxy)?;
         path.pop();
     }
     Ok(())
 }
 
+/// Counts the lines each author committed within `[since, asof]` that are
+/// still in the repository at `path`, leaving out files matching `exclude`
+/// and counting those matching `test_paths` as test code. `progress` tracks
+/// the files blamed.
+///
+/// Lines on `branches` are counted as well, as of `asof`. Since a line may
+/// be on more than one branch, lines are told apart by the commit that
+/// introduced them and their original file and line number in it, and each
+/// is credited only once.
+///
+/// # Errors
+///
+/// Returns an error if a pattern is invalid or a git command fails.
+#[allow(clippy::too_many_arguments)]
 pub fn blame_stats<P, I, S>(
     path: P,
     since: &DateTime<Utc>,
     asof: &DateTime<Utc>,
     exclude: I,
-) -> io::Result<HashMap<String, usize>>
+    test_paths: &[String],
+    branches: &[String],
+    options: &BlameOptions,
+    progress: &ProgressBar,
+) -> io::Result<HashMap<String, LineCount>>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
     I: IntoIterator<Item = S>,
 {
-    let exclude = match RegexSet::new(exclude) {
-        Ok(set) => set,
-        Err(e) => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("invalid exclude pattern: {e}"),
-            ))
+    let exclude = pattern_set(exclude, "exclude")?;
+    let test_paths = pattern_set(test_paths, "test path")?;
+
+    let path = path.as_ref();
+    let mut revisions = Vec::new();
+    for branch in branches {
+        if let Some(commit) = branch_commit(path, branch, asof)? {
+            revisions.push(commit);
+        } else {
+            warn!("{}: no commit in {branch} before {asof}", path.display());
         }
+    }
+    let mut merges = HashSet::new();
+    if options.ignore_merges {
+        for revision in iter::once("HEAD").chain(revisions.iter().map(String::as_str)) {
+            merges.extend(merge_commits(path, revision, since, asof)?);
+        }
+    }
+    let ignored = ignored_commits(path, options)?;
+    let mut credits = Credits {
+        repo: path,
+        since,
+        asof,
+        options,
+        merges,
+        ignored: &ignored,
+        co_authors: HashMap::new(),
+        seen: HashSet::new(),
+        new: Vec::new(),
+        total_loc: HashMap::new(),
+        cache: Cache::new(git_dir(path)?.join(BLAME_CACHE_DIR), BLAME_CACHE_TTL),
     };
+    credits.cache.prune();
+
+    // Listing the files first gives the progress its length.
+    let mut scans = vec![(None, checkout_files(path, since, &exclude)?)];
+    for commit in &revisions {
+        scans.push((
+            Some(commit.as_str()),
+            revision_files(path, commit, since, &exclude)?,
+        ));
+    }
+    progress.set_length(scans.iter().map(|(_, files)| files.len() as u64).sum());
+    for (commit, files) in scans {
+        if let Some(commit) = commit {
+            debug!("{}: counting lines on {commit}", path.display());
+        }
+        for pathstr in files {
+            debug!("  {pathstr}");
+            credits.add(commit, &pathstr, test_paths.is_match(&pathstr))?;
+            progress.inc(1);
+        }
+        credits.finish_revision();
+    }
+    let mut total_loc = credits.total_loc;
+    let revisions = iter::once("HEAD").chain(revisions.iter().map(String::as_str));
+    for (email, removed) in removed_lines(
+        path,
+        revisions,
+        since,
+        asof,
+        options.date,
+        &exclude,
+        &ignored,
+    )? {
+        total_loc.entry(email).or_default().removed += removed;
+    }
+    Ok(total_loc)
+}
 
-    let mut total_loc = HashMap::new();
-    let orig_dir = env::current_dir()?;
-    env::set_current_dir(&path)?;
-    for entry in WalkDir::new(".") {
+/// Returns the paths of the files in the checkout of the repository at
+/// `path` to blame: those changed since `since`, other than binary,
+/// generated, or `exclude`d ones.
+fn checkout_files(
+    path: &Path,
+    since: &DateTime<Utc>,
+    exclude: &RegexSet,
+) -> io::Result<Vec<String>> {
+    let touched = touched_files(path, "HEAD", since)?;
+    let walker = WalkDir::new(path).into_iter().filter_entry(|entry| {
+        if !entry.file_type().is_dir() || entry.depth() == 0 {
+            return true;
+        }
+        if entry.depth() == 1 && entry.file_name() == ".git" {
+            return false;
+        }
+        // Prunes excluded directories, such as vendored code, without
+        // descending into them.
+        relative_path(path, entry.path())
+            .map_or(true, |pathstr| !exclude.is_match(&format!("{pathstr}/")))
+    });
+    let mut files = Vec::new();
+    for entry in walker {
         let entry = match entry {
             Ok(entry) => entry,
-            Err(e) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("cannot traverse repo: {e}"),
-                ))
-            }
+            Err(e) => return Err(io::Error::other(format!("cannot traverse repo: {e}"))),
         };
         if entry.file_type().is_dir() || entry.path_is_symlink() {
             continue;
         }
-        let pathstr = match entry.path().to_str() {
-            Some(pathstr) => {
-                if pathstr.len() < 2 {
-                    continue;
+        let Some(pathstr) = relative_path(path, entry.path()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid file name: {}", entry.path().display()),
+            ));
+        };
+        // A file not touched in the window has no lines authored in it.
+        if !touched.contains(pathstr)
+            || exclude.is_match(pathstr)
+            || is_binary(entry.path())?
+            || is_generated(path, pathstr)?
+        {
+            continue;
+        }
+        files.push(pathstr.to_string());
+    }
+    Ok(files)
+}
+
+/// Returns the paths of the files in `commit` to blame, as `checkout_files`
+/// does for the checkout.
+fn revision_files(
+    path: &Path,
+    commit: &str,
+    since: &DateTime<Utc>,
+    exclude: &RegexSet,
+) -> io::Result<Vec<String>> {
+    let touched = touched_files(path, commit, since)?;
+    let mut files = Vec::new();
+    for pathstr in tracked_files(path, commit)? {
+        // Attributes are looked up in the checkout, as git cannot read them
+        // from another commit before version 2.40.
+        if !touched.contains(&pathstr)
+            || exclude.is_match(&pathstr)
+            || is_binary_blob(path, commit, &pathstr)?
+            || is_generated(path, &pathstr)?
+        {
+            continue;
+        }
+        files.push(pathstr);
+    }
+    Ok(files)
+}
+
+/// The credit for lines accumulated across the revisions of a repository.
+struct Credits<'a> {
+    repo: &'a Path,
+    since: &'a DateTime<Utc>,
+    asof: &'a DateTime<Utc>,
+    options: &'a BlameOptions,
+    merges: HashSet<String>,
+    /// The commits blame skips over, by their full names.
+    ignored: &'a BTreeSet<String>,
+    co_authors: HashMap<String, Vec<String>>,
+    /// The lines credited in the revisions already finished.
+    seen: HashSet<LineOrigin>,
+    /// The lines credited in the current revision.
+    new: Vec<LineOrigin>,
+    total_loc: HashMap<String, LineCount>,
+    /// The lines blamed in earlier scans.
+    cache: Cache,
+}
+
+impl Credits<'_> {
+    /// Credits the authors of the lines of `filename` in the checkout, or in
+    /// `commit` if given, skipping those credited in an earlier revision.
+    fn add(&mut self, commit: Option<&str>, filename: &str, is_test: bool) -> io::Result<()> {
+        for line in self.lines(commit, filename)? {
+            if self.merges.contains(&line.commit)
+                || self.ignored.contains(&line.commit)
+                || self.seen.contains(&line.origin())
+            {
+                continue;
+            }
+            self.new.push(line.origin());
+            let weight = self.options.recency.map_or(1., |recency| {
+                recency.weight(&line.time, self.since, self.asof)
+            });
+            let credit = |share: f64| {
+                if is_test {
+                    LineCount {
+                        test: share,
+                        ..LineCount::default()
+                    }
+                } else {
+                    LineCount {
+                        production: share,
+                        ..LineCount::default()
+                    }
                 }
-                &pathstr[2..]
+            };
+            if !self.options.credit_co_authors {
+                let entry = self.total_loc.entry(line.email).or_default();
+                *entry += credit(weight);
+                continue;
             }
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("invalid file name: {}", entry.path().display()),
-                ))
+            if !self.co_authors.contains_key(&line.commit) {
+                self.co_authors.insert(
+                    line.commit.clone(),
+                    commit_co_authors(self.repo, &line.commit)?,
+                );
             }
+            let mut authors = vec![&line.email];
+            for co_author in &self.co_authors[&line.commit] {
+                if !authors.contains(&co_author) {
+                    authors.push(co_author);
+                }
+            }
+            #[allow(clippy::cast_precision_loss)]
+            let share = weight / authors.len() as f64;
+            for author in authors {
+                let entry = self.total_loc.entry(author.clone()).or_default();
+                *entry += credit(share);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the lines of `filename` in the checkout, or in `commit` if
+    /// given, that were committed within the window.
+    ///
+    /// The lines are cached by the contents of the file and the window, so
+    /// that scanning again, such as after changing `email_map`, does not
+    /// blame unchanged files again. The history of a file is assumed not to
+    /// change while its contents stay the same.
+    fn lines(&self, commit: Option<&str>, filename: &str) -> io::Result<Vec<BlameLine>> {
+        let blob = match commit {
+            Some(commit) => {
+                output(git(self.repo).args(["rev-parse", &format!("{commit}:{filename}")]))?
+            }
+            None => output(git(self.repo).args(["hash-object", "--", filename]))?,
         };
-        if exclude.is_match(pathstr) {
-            continue;
+        let key = format!(
+            "{filename}\0{blob}\0{}\0{}\0{}\0{}\0{:?}\0{}",
+            self.since.to_rfc3339(),
+            self.asof.to_rfc3339(),
+            self.options.ignore_whitespace,
+            self.options.detect_moves,
+            self.options.date,
+            self.ignored
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        if let Some(lines) = self
+            .cache
+            .get(&key)
+            .and_then(|cached| serde_json::from_str(&cached).ok())
+        {
+            return Ok(lines);
         }
-        println!("  {pathstr}");
-        let blameout = blame(pathstr)?;
-        for (email, loc) in parse_blame(&blameout, since, asof) {
-            let entry = total_loc.entry(email).or_insert(0);
-            *entry += loc;
+        let blameout = blame(self.repo, commit, filename, self.options, self.ignored)?;
+        let lines = parse_blame(&blameout, self.since, self.asof, self.options.date);
+        match serde_json::to_string(&lines) {
+            Ok(serialized) => self.cache.put(&key, &serialized),
+            Err(e) => warn!("cannot cache the blame of {filename}: {e}"),
         }
+        Ok(lines)
+    }
+
+    /// Marks the lines credited so far as seen, so that the same lines on
+    /// another revision are not credited again. Lines are not checked against
+    /// others in the same revision, so copies of a line within it still
+    /// count separately.
+    fn finish_revision(&mut self) {
+        self.seen.extend(self.new.drain(..));
     }
-    env::set_current_dir(orig_dir)?;
-    Ok(total_loc)
 }
 
-fn blame(filename: &str) -> io::Result<String> {
-    let output = Command::new("git")
-        .args(["blame", "-e", "--date=iso", filename])
+/// Compiles `patterns`, reporting an invalid one as a `kind` pattern.
+fn pattern_set<I, S>(patterns: I, kind: &str) -> io::Result<RegexSet>
+where
+    S: AsRef<str>,
+    I: IntoIterator<Item = S>,
+{
+    RegexSet::new(patterns).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {kind} pattern: {e}"),
+        )
+    })
+}
+
+/// Returns the paths of the files changed by commits since `since` reachable
+/// from `revision`, including those whose merge conflicts were resolved in
+/// the window.
+///
+/// Commits are picked by their committer date, which is never before their
+/// author date, so that the files cover the window by either date.
+fn touched_files(
+    repo: &Path,
+    revision: &str,
+    since: &DateTime<Utc>,
+) -> io::Result<HashSet<String>> {
+    let output = git(repo)
+        .args(["log", "--cc", "--name-only", "-z", "--format="])
+        .arg(format!("--since={}", since.to_rfc3339()))
+        .arg(revision)
         .output()?;
     if !output.status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+        return Err(io::Error::other("git operation failed"));
     }
-    let outstr = String::from_utf8_lossy(&output.stdout);
-    Ok(outstr.to_string())
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-fn parse_blame(blame: &str, since: &DateTime<Utc>, asof: &DateTime<Utc>) -> HashMap<String, usize> {
-    let mut loc = HashMap::new();
-    for line in blame.split('\n') {
-        if line.is_empty() {
-            continue; // skip the last line
-        }
-        let email_start = if let Some(cur) = line.find("(<") {
-            cur + 2
-        } else {
-            eprintln!("Warning: cannot find where email address begins: {line}");
+/// Returns the number of lines each author deleted in the commits made
+/// within `[since, asof]` by `date` reachable from `revisions`, other than
+/// merges and `ignored` commits, leaving out binary and `exclude`d files. A
+/// commit reachable from more than one revision is counted once, and moving
+/// a file does not count as deleting its lines.
+fn removed_lines<'a>(
+    repo: &Path,
+    revisions: impl IntoIterator<Item = &'a str>,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    date: CommitDate,
+    exclude: &RegexSet,
+    ignored: &BTreeSet<String>,
+) -> io::Result<HashMap<String, f64>> {
+    let output = git(repo)
+        .args(["log", "-z", "-M", "--no-merges", "--numstat"])
+        .arg(format!("--format=commit %H %aE {}", date.log_placeholder()))
+        .arg(format!("--since={}", since.to_rfc3339()))
+        .args(revisions)
+        .arg("--")
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    let mut removed = HashMap::new();
+    let mut author = None;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout
+        .split('\0')
+        .map(|field| field.trim_start_matches('\n'));
+    while let Some(field) = fields.next() {
+        if let Some(commit) = field.strip_prefix("commit ") {
+            author = commit
+                .split_once(' ')
+                .filter(|(hash, _)| !ignored.contains(*hash))
+                .and_then(|(_, commit)| commit.rsplit_once(' '))
+                .and_then(|(email, time)| {
+                    let time = DateTime::from_timestamp(time.parse().ok()?, 0)?;
+                    (*since <= time && time <= *asof).then(|| normalize_email(email))
+                });
             continue;
-        };
-        let email_end = if let Some(cur) = line[email_start + 1..].find('>') {
-            cur + email_start + 1
-        } else {
-            eprintln!("Warning: cannot find where email address ends: {line}");
+        }
+        // Binary files have "-" in place of the numbers of lines.
+        let mut numstat = field.splitn(3, '\t');
+        let (Some(_), Some(deleted), Some(mut pathstr)) =
+            (numstat.next(), numstat.next(), numstat.next())
+        else {
             continue;
         };
-        let email = &line[email_start..email_end];
-        let timestamp_end = if let Some(cur) = line[email_end + 1..].find(')') {
-            if let Some(cur) = line[email_end + 1..=cur + email_end].rfind(' ') {
-                cur + email_end + 1
-            } else {
-                eprintln!("Warning: cannot find where timestamp ends: {line}");
-                continue;
-            }
-        } else {
-            eprintln!("Warning: cannot find where blame info ends: {line}");
+        // A renamed file has an empty path, followed by its old and new
+        // paths, and only the lines deleted from it besides the move.
+        if pathstr.is_empty() {
+            let _ = fields.next();
+            pathstr = fields.next().unwrap_or_default();
+        }
+        let (Some(author), Ok(deleted)) = (&author, deleted.parse::<u32>()) else {
             continue;
         };
-        let timestamp_str = line[email_end + 1..timestamp_end].trim();
-        let Ok(timestamp) = DateTime::parse_from_str(timestamp_str, "%F %T %z") else {
-            eprintln!(r#"Warning: invalid timestamp format: "{timestamp_str}""#);
+        if exclude.is_match(pathstr) {
             continue;
-        };
-        if timestamp < since.with_timezone(&timestamp.timezone())
-            || asof.with_timezone(&timestamp.timezone()) < timestamp
-        {
+        }
+        *removed.entry(author.clone()).or_default() += f64::from(deleted);
+    }
+    Ok(removed)
+}
+
+/// Returns the full names of the commits to ignore in `options`, both those
+/// listed in it and those in its `ignore_revs_file`, or the repository's
+/// `.git-blame-ignore-revs` if detected. A commit not in the repository,
+/// which `git blame` would reject, is left out with a warning.
+fn ignored_commits(repo: &Path, options: &BlameOptions) -> io::Result<BTreeSet<String>> {
+    let mut revs = options.ignore_commits.clone();
+    let detected = (options.detect_ignore_revs_file && repo.join(IGNORE_REVS_FILE).is_file())
+        .then(|| PathBuf::from(IGNORE_REVS_FILE));
+    if let Some(file) = options.ignore_revs_file.as_ref().or(detected.as_ref()) {
+        let contents = fs::read_to_string(repo.join(file)).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot read {}: {e}", file.display()))
+        })?;
+        revs.extend(
+            contents
+                .lines()
+                .map(|line| line.split_once('#').map_or(line, |(rev, _)| rev).trim())
+                .filter(|rev| !rev.is_empty())
+                .map(str::to_string),
+        );
+    }
+    let mut ignored = BTreeSet::new();
+    for rev in revs {
+        let output = git(repo)
+            .args(["rev-parse", "--verify", "--quiet"])
+            .arg(format!("{rev}^{{commit}}"))
+            .output()?;
+        if output.status.success() {
+            ignored.insert(String::from_utf8_lossy(&output.stdout).trim().to_string());
+        } else {
+            warn!("{}: no commit {rev} to ignore", repo.display());
+        }
+    }
+    Ok(ignored)
+}
+
+/// Returns the merge commits made within `[since, asof]` reachable from
+/// `revision`.
+fn merge_commits(
+    repo: &Path,
+    revision: &str,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> io::Result<HashSet<String>> {
+    let output = git(repo)
+        .args(["rev-list", "--merges"])
+        .arg(format!("--since={}", since.to_rfc3339()))
+        .arg(format!("--until={}", asof.to_rfc3339()))
+        .arg(revision)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Returns the last commit on `branch` before `asof`, following its first
+/// parents, or `None` if there is none. The branch is looked up on `origin`
+/// first, and then among the local branches.
+fn branch_commit(repo: &Path, branch: &str, asof: &DateTime<Utc>) -> io::Result<Option<String>> {
+    let before_arg = format!("--before={}", asof.to_rfc3339());
+    for reference in [
+        format!("refs/remotes/origin/{branch}"),
+        format!("refs/heads/{branch}"),
+    ] {
+        let exists = git(repo)
+            .args(["rev-parse", "--verify", "--quiet", &reference])
+            .stdout(Stdio::null())
+            .status()?
+            .success();
+        if !exists {
             continue;
         }
-        let entry = loc.entry(email.to_string()).or_insert(0);
-        *entry += 1;
+        let commit = output(git(repo).args([
+            "rev-list",
+            "-n",
+            "1",
+            "--first-parent",
+            &before_arg,
+            &reference,
+        ]))?;
+        return Ok(Some(commit).filter(|commit| !commit.is_empty()));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no branch named {branch}"),
+    ))
+}
+
+/// Returns the paths of the files in `commit`.
+fn tracked_files(repo: &Path, commit: &str) -> io::Result<Vec<String>> {
+    let output = git(repo)
+        .args(["ls-tree", "-r", "-z", "--name-only", commit])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
     }
-    loc
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Normalizes an email address so that differently capitalized spellings of
+/// the same address are counted together.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Returns `path` relative to `root` as a string.
+fn relative_path<'a>(root: &Path, path: &'a Path) -> Option<&'a str> {
+    path.strip_prefix(root).ok()?.to_str()
+}
+
+/// Returns the absolute path of the git directory of the repository at
+/// `repo`.
+fn git_dir(repo: &Path) -> io::Result<PathBuf> {
+    output(git(repo).args(["rev-parse", "--absolute-git-dir"])).map(PathBuf::from)
 }
 
-fn clone<P: AsRef<Path>>(url: &str, path: P) -> io::Result<()> {
+/// Creates a `git` command that runs in the repository at `repo`.
+fn git<P: AsRef<Path>>(repo: P) -> Command {
+    let mut cmd = git_command();
+    cmd.arg("-C").arg(repo.as_ref());
+    cmd
+}
+
+/// Tells whether a file looks binary, using the same heuristic as git: a NUL
+/// byte within the first 8000 bytes.
+fn is_binary(filename: &Path) -> io::Result<bool> {
+    let mut buf = Vec::with_capacity(8000);
+    File::open(filename)?.take(8000).read_to_end(&mut buf)?;
+    Ok(buf.contains(&0))
+}
+
+/// Tells whether a file in `commit` looks binary, as `is_binary` does for a
+/// file in the checkout.
+fn is_binary_blob(repo: &Path, commit: &str, filename: &str) -> io::Result<bool> {
+    let mut child = git(repo)
+        .args(["cat-file", "blob", &format!("{commit}:{filename}")])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut buf = Vec::with_capacity(8000);
+    if let Some(stdout) = child.stdout.take() {
+        stdout.take(8000).read_to_end(&mut buf)?;
+    }
+    // Reading stops early for a large file, so its exit status is not
+    // checked.
+    child.wait()?;
+    Ok(buf.contains(&0))
+}
+
+/// Tells whether a file is marked as generated with the `linguist-generated`
+/// attribute in `.gitattributes`.
+fn is_generated(repo: &Path, filename: &str) -> io::Result<bool> {
+    let output = git(repo)
+        .args(["check-attr", "linguist-generated", "--", filename])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    let outstr = String::from_utf8_lossy(&output.stdout);
+    Ok(outstr
+        .trim_end()
+        .rsplit(": ")
+        .next()
+        .is_some_and(|value| value == "set" || value == "true"))
+}
+
+/// Runs `git blame` on a file in the checkout, or in `commit` if given,
+/// skipping over the `ignored` commits.
+fn blame(
+    repo: &Path,
+    commit: Option<&str>,
+    filename: &str,
+    options: &BlameOptions,
+    ignored: &BTreeSet<String>,
+) -> io::Result<String> {
+    let mut cmd = git(repo);
+    cmd.args(["blame", "--line-porcelain"]);
+    if options.ignore_whitespace {
+        cmd.arg("-w");
+    }
+    if options.detect_moves {
+        cmd.args(["-C", "-C", "-M"]);
+    }
+    for rev in ignored {
+        cmd.args(["--ignore-rev", rev]);
+    }
+    cmd.args(commit);
+    let output = cmd.arg("--").arg(filename).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    let outstr = String::from_utf8_lossy(&output.stdout);
+    Ok(outstr.to_string())
+}
+
+/// Returns the email addresses in the `Co-authored-by` trailers of a commit.
+fn commit_co_authors(repo: &Path, commit: &str) -> io::Result<Vec<String>> {
+    let output = git(repo)
+        .args([
+            "log",
+            "-1",
+            "--format=%(trailers:key=Co-authored-by,valueonly,unfold)",
+            commit,
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    let outstr = String::from_utf8_lossy(&output.stdout);
+    Ok(outstr
+        .lines()
+        .filter_map(|line| {
+            let start = line.rfind('<')?;
+            let end = line[start..].find('>')? + start;
+            Some(normalize_email(&line[start + 1..end]))
+        })
+        .collect())
+}
+
+/// A line attributed by `git blame`.
+#[derive(Debug, Deserialize, Serialize)]
+struct BlameLine {
+    commit: String,
+    /// The file the line was introduced in by `commit`.
+    filename: String,
+    /// The number of the line in `filename` as of `commit`.
+    line: usize,
+    email: String,
+    time: DateTime<Utc>,
+}
+
+/// Where a line was introduced, which stays the same on every branch the line
+/// is on.
+type LineOrigin = (String, String, usize);
+
+impl BlameLine {
+    fn origin(&self) -> LineOrigin {
+        (self.commit.clone(), self.filename.clone(), self.line)
+    }
+}
+
+/// Returns the lines of the commits dated within `[since, asof]` by `date`.
+///
+/// `blame` is the output of `git blame --line-porcelain`, in which each line
+/// is described by its own header block of `key value` pairs followed by the
+/// line content prefixed with a tab. Relying on those keys, rather than the
+/// human-readable annotation, keeps author names containing parentheses or
+/// angle brackets from confusing the parser.
+fn parse_blame(
+    blame: &str,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    date: CommitDate,
+) -> Vec<BlameLine> {
+    let mut lines = Vec::new();
+    let mut commit = None;
+    let mut filename = None;
+    let mut email = None;
+    let mut timestamp = None;
+    for line in blame.split('\n') {
+        if line.starts_with('\t') {
+            let (Some((commit, number)), Some(filename), Some(email), Some(timestamp)) = (
+                commit.take(),
+                filename.take(),
+                email.take(),
+                timestamp.take(),
+            ) else {
+                warn!("incomplete blame information for line: {line}");
+                continue;
+            };
+            if timestamp < *since || *asof < timestamp {
+                continue;
+            }
+            lines.push(BlameLine {
+                commit,
+                filename,
+                line: number,
+                email,
+                time: timestamp,
+            });
+        } else if let Some(mail) = line.strip_prefix("author-mail ") {
+            let mail = mail
+                .strip_prefix('<')
+                .and_then(|mail| mail.strip_suffix('>'))
+                .unwrap_or(mail);
+            email = Some(normalize_email(mail));
+        } else if let Some(name) = line.strip_prefix("filename ") {
+            filename = Some(name.to_string());
+        } else if let Some(time) = line.strip_prefix(date.porcelain_key()) {
+            let Some(time) = time
+                .parse::<i64>()
+                .ok()
+                .and_then(|time| DateTime::from_timestamp(time, 0))
+            else {
+                warn!(r#"invalid timestamp format: "{time}""#);
+                continue;
+            };
+            timestamp = Some(time);
+        } else if commit.is_none() {
+            let mut fields = line.split(' ');
+            if let (Some(sha), Some(number)) = (fields.next(), fields.next()) {
+                if sha.len() >= 40 && sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    if let Ok(number) = number.parse() {
+                        commit = Some((sha.to_string(), number));
+                    }
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Adds the configuration for git to go through `proxy`, if any, to `cmd`.
+fn with_proxy<'a>(cmd: &'a mut Command, proxy: Option<&str>) -> &'a mut Command {
+    if let Some(proxy) = proxy {
+        cmd.arg("-c").arg(format!("http.proxy={proxy}"));
+    }
+    cmd
+}
+
+fn clone<P: AsRef<Path>>(url: &str, path: P, retries: u32, proxy: Option<&str>) -> io::Result<()> {
     let Some(path) = path.as_ref().to_str() else {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "invalid repository path",
         ));
     };
-    let status = Command::new("git").args(["clone", url, path]).status()?;
+    run_with_retries(
+        with_proxy(&mut git_command(), proxy).args(["clone", url, path]),
+        retries,
+        RETRY_DELAY,
+    )
+}
+
+fn update<P: AsRef<Path>>(
+    path: P,
+    asof: &DateTime<Utc>,
+    offline: bool,
+    retries: u32,
+    proxy: Option<&str>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    if !offline {
+        run_with_retries(
+            with_proxy(&mut git(path), proxy).args(["fetch", "origin"]),
+            retries,
+            RETRY_DELAY,
+        )?;
+    }
+    if !output(git(path).args(["status", "--porcelain"]))?.is_empty() {
+        warn!("{}: stashing uncommitted changes", path.display());
+        run(git(path).args([
+            "-c",
+            "user.name=pbmetric",
+            "-c",
+            "user.email=pbmetric@localhost",
+            "stash",
+            "--include-untracked",
+        ]))?;
+    }
+
+    // The remote's default branch, whatever the local branch is, so that a
+    // checkout left over from an earlier run does not matter.
+    let branch = output(git(path).args(["rev-parse", "--abbrev-ref", "origin/HEAD"]))
+        .unwrap_or_else(|_| "main".to_string());
+    let before_arg = format!("--before={}", asof.to_rfc3339());
+    let commit = output(git(path).args([
+        "rev-list",
+        "-n",
+        "1",
+        "--first-parent",
+        &before_arg,
+        &branch,
+    ]))?;
+    if commit.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no commit in {branch} before {}", asof.to_rfc3339()),
+        ));
+    }
+    debug!("{}: checking out {commit} from {branch}", path.display());
+    run(git(path).args(["checkout", "-q", "--detach", &commit]))
+}
+
+/// Runs a git command and returns its output without surrounding whitespace.
+fn output(cmd: &mut Command) -> io::Result<String> {
+    let output = cmd.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("git operation failed"));
+    }
+    String::from_utf8(output.stdout)
+        .map(|stdout| stdout.trim().to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Runs a git command, sending its output to stderr so that stdout is left
+/// for the report.
+fn run(cmd: &mut Command) -> io::Result<()> {
+    let status = cmd.stdout(io::stderr()).status()?;
     if !status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+        return Err(io::Error::other("git operation failed"));
     }
     Ok(())
 }
 
-fn update<P: AsRef<Path>>(path: P, asof: &DateTime<Utc>, offline: bool) -> io::Result<()> {
-    let orig_dir = env::current_dir()?;
-    env::set_current_dir(path)?;
-    if !offline {
-        let status = Command::new("git").args(["fetch", "origin"]).status()?;
-        if !status.success() {
-            return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+/// Runs a git command that talks to a remote. If it fails for a reason that
+/// may go away, such as a network error, it is retried up to `retries` times,
+/// waiting `delay` before the first retry and twice as long before each next.
+fn run_with_retries(cmd: &mut Command, retries: u32, mut delay: Duration) -> io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        let output = cmd.stdout(io::stderr()).stderr(Stdio::piped()).output()?;
+        io::stderr().write_all(&output.stderr)?;
+        if output.status.success() {
+            return Ok(());
         }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if attempt == retries || !is_transient(&stderr) {
+            return Err(io::Error::other(format!(
+                "git operation failed: {}",
+                stderr.trim()
+            )));
+        }
+        attempt += 1;
+        warn!("git operation failed; retrying in {delay:?} ({attempt}/{retries})");
+        thread::sleep(delay);
+        delay *= 2;
     }
-    let status = Command::new("git").args(["checkout", "main"]).status()?;
-    if !status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+}
+
+/// Tells whether git failed, according to its error output, because of the
+/// network rather than, for example, authentication or a missing repository.
+fn is_transient(stderr: &str) -> bool {
+    const TRANSIENT: [&str; 9] = [
+        "could not resolve host",
+        "connection refused",
+        "connection reset",
+        "connection timed out",
+        "operation timed out",
+        "early eof",
+        "the remote end hung up unexpectedly",
+        "rpc failed",
+        "temporary failure",
+    ];
+    let stderr = stderr.to_lowercase();
+    TRANSIENT.iter().any(|message| stderr.contains(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+
+    use log::{Level, LevelFilter};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::report::exclude_patterns;
+
+    struct Fixture {
+        dir: TempDir,
     }
-    if !offline {
-        let status = Command::new("git")
-            .args(["reset", "--hard", "origin/main"])
-            .status()?;
-        if !status.success() {
-            return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = TempDir::new().unwrap();
+            let fixture = Self { dir };
+            fixture.git(&["init", "-q", "-b", "main"]);
+            fixture
+        }
+
+        fn path(&self) -> &Path {
+            self.dir.path()
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            let path = self.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        fn commit(&self, email: &str, date: &str) {
+            self.commit_with_message(email, date, "update");
+        }
+
+        fn commit_with_message(&self, email: &str, date: &str, message: &str) {
+            self.commit_dated(email, date, date, message);
+        }
+
+        fn commit_dated(
+            &self,
+            email: &str,
+            author_date: &str,
+            committer_date: &str,
+            message: &str,
+        ) {
+            self.git(&["add", "-A"]);
+            let status = git(self.path())
+                .args(["-c", "commit.gpgsign=false", "commit", "-q", "-m", message])
+                .env("GIT_AUTHOR_NAME", email)
+                .env("GIT_AUTHOR_EMAIL", email)
+                .env("GIT_AUTHOR_DATE", author_date)
+                .env("GIT_COMMITTER_NAME", email)
+                .env("GIT_COMMITTER_EMAIL", email)
+                .env("GIT_COMMITTER_DATE", committer_date)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        fn git(&self, args: &[&str]) {
+            let status = git(self.path()).args(args).status().unwrap();
+            assert!(status.success());
+        }
+
+        fn blame_stats(&self, options: &BlameOptions) -> HashMap<String, f64> {
+            self.blame_stats_excluding(&[], options)
+        }
+
+        fn blame_stats_excluding(
+            &self,
+            exclude: &[&str],
+            options: &BlameOptions,
+        ) -> HashMap<String, f64> {
+            // Authors who only removed lines have no lines credited.
+            self.line_counts(exclude, &[], options)
+                .into_iter()
+                .filter(|(_, lines)| lines.total() > 0.)
+                .map(|(email, lines)| (email, lines.total()))
+                .collect()
+        }
+
+        fn line_counts(
+            &self,
+            exclude: &[&str],
+            test_paths: &[String],
+            options: &BlameOptions,
+        ) -> HashMap<String, LineCount> {
+            self.line_counts_on(exclude, test_paths, &[], options)
+        }
+
+        fn line_counts_on(
+            &self,
+            exclude: &[&str],
+            test_paths: &[String],
+            branches: &[String],
+            options: &BlameOptions,
+        ) -> HashMap<String, LineCount> {
+            let (since, asof) = window();
+            blame_stats(
+                self.path(),
+                &since,
+                &asof,
+                exclude,
+                test_paths,
+                branches,
+                options,
+                &ProgressBar::hidden(),
+            )
+            .unwrap()
         }
     }
-    let before_arg = format!(r#"--before="{}""#, asof.to_rfc3339());
-    let output = Command::new("git")
-        .args(["rev-list", "-n", "1", "--first-parent", &before_arg, "main"])
-        .output()?;
-    let gitref = String::from_utf8(output.stdout).unwrap();
-    let status = Command::new("git")
-        .args(["checkout", gitref.trim()])
-        .status()?;
-    if !status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "git operation failed"));
+
+    const BLAME: &str = "\
+0123456789abcdef0123456789abcdef01234567 1 1 1
+author Foo (Bar)
+author-mail <x@y>
+author-time 1704067200
+author-tz +0900
+committer Foo (Bar)
+committer-mail <x@y>
+committer-time 1704067200
+committer-tz +0900
+summary Initial commit
+boundary
+filename src/lib.rs
+\tfn main() {}
+89abcdef0123456789abcdef0123456789abcdef 2 2 1
+author a < b
+author-mail <a@b>
+author-time 1704153600
+author-tz +0000
+committer a < b
+committer-mail <a@b>
+committer-time 1704153600
+committer-tz +0000
+summary Add a line (with parentheses)
+filename src/lib.rs
+\t// (<not an email>)
+";
+
+    fn window() -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            DateTime::parse_from_rfc3339("2023-12-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        )
+    }
+
+    #[test]
+    fn parse_blame_author_with_parentheses() {
+        let (since, asof) = window();
+        let lines = parse_blame(BLAME, &since, &asof, CommitDate::Author);
+        assert_eq!(lines[0].email, "x@y");
+        assert_eq!(lines[0].commit, "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(lines[0].time.timestamp(), 1_704_067_200);
+    }
+
+    #[test]
+    fn parse_blame_author_with_angle_bracket() {
+        let (since, asof) = window();
+        let lines = parse_blame(BLAME, &since, &asof, CommitDate::Author);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].email, "a@b");
+    }
+
+    #[test]
+    fn parse_blame_outside_window() {
+        let (since, _) = window();
+        let asof = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let lines = parse_blame(BLAME, &since, &asof, CommitDate::Author);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].email, "x@y");
+    }
+
+    thread_local! {
+        static LOGGED: RefCell<Vec<(Level, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Keeps the messages logged by each test thread apart from the others.
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED.with(|logged| {
+                logged
+                    .borrow_mut()
+                    .push((record.level(), record.args().to_string()));
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn logged_by(f: impl FnOnce()) -> Vec<(Level, String)> {
+        static CAPTURE: Capture = Capture;
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Trace);
+        f();
+        LOGGED.with(RefCell::take)
+    }
+
+    /// Writes a script standing in for git that records each call in
+    /// `calls` and fails with `stderr` until it has been called `failures`
+    /// times.
+    #[cfg(unix)]
+    fn git_shim(dir: &Path, failures: usize, stderr: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("git");
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\n\
+                 echo \"$@\" >> \"$0.calls\"\n\
+                 if [ \"$(wc -l < \"$0.calls\")\" -le {failures} ]; then\n\
+                 echo \"{stderr}\" >&2\n\
+                 exit 128\n\
+                 fi\n"
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn proxy_is_passed_to_git() {
+        let mut cmd = git("repo");
+        with_proxy(&mut cmd, Some("http://proxy.example.com:3128")).args(["fetch", "origin"]);
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            [
+                "-C",
+                "repo",
+                "-c",
+                "http.proxy=http://proxy.example.com:3128",
+                "fetch",
+                "origin"
+            ]
+        );
+
+        let mut cmd = git("repo");
+        with_proxy(&mut cmd, None).args(["fetch", "origin"]);
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            ["-C", "repo", "fetch", "origin"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn network_errors_are_retried() {
+        let dir = TempDir::new().unwrap();
+        let shim = git_shim(
+            dir.path(),
+            1,
+            "fatal: unable to access 'https://github.com/petabi/pbmetric/': \
+             Could not resolve host: github.com",
+        );
+        run_with_retries(
+            Command::new(&shim).args(["fetch", "origin"]),
+            3,
+            Duration::ZERO,
+        )
+        .unwrap();
+        let calls = fs::read_to_string(dir.path().join("git.calls")).unwrap();
+        assert_eq!(calls, "fetch origin\nfetch origin\n");
+
+        let dir = TempDir::new().unwrap();
+        let shim = git_shim(dir.path(), 5, "fatal: early EOF");
+        assert!(run_with_retries(&mut Command::new(&shim), 2, Duration::ZERO).is_err());
+        let calls = fs::read_to_string(dir.path().join("git.calls")).unwrap();
+        assert_eq!(calls.lines().count(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn authentication_errors_are_not_retried() {
+        let dir = TempDir::new().unwrap();
+        let shim = git_shim(
+            dir.path(),
+            1,
+            "remote: Invalid username or password.\n\
+             fatal: Authentication failed for 'https://github.com/petabi/pbmetric/'",
+        );
+        let err = run_with_retries(&mut Command::new(&shim), 3, Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("Authentication failed"));
+        let calls = fs::read_to_string(dir.path().join("git.calls")).unwrap();
+        assert_eq!(calls.lines().count(), 1);
+    }
+
+    fn head(path: &Path) -> String {
+        output(git(path).args(["rev-parse", "HEAD"])).unwrap()
+    }
+
+    #[test]
+    fn update_resolves_asof_from_any_state() {
+        let origin = Fixture::new();
+        origin.write("a.txt", "one\n");
+        origin.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        let first = head(origin.path());
+        origin.write("a.txt", "one\ntwo\n");
+        origin.commit("alice@example.com", "2024-01-10T00:00:00Z");
+        let second = head(origin.path());
+
+        let cache = TempDir::new().unwrap();
+        let clone_path = cache.path().join("repo");
+        clone(origin.path().to_str().unwrap(), &clone_path, 0, None).unwrap();
+        let asof = |time: &str| {
+            DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        update(&clone_path, &asof("2024-01-05T00:00:00Z"), false, 0, None).unwrap();
+        assert_eq!(head(&clone_path), first);
+
+        // Left detached, with changes, by an earlier run.
+        fs::write(clone_path.join("a.txt"), "edited\n").unwrap();
+        fs::write(clone_path.join("b.txt"), "untracked\n").unwrap();
+        origin.write("a.txt", "one\ntwo\nthree\n");
+        origin.commit("alice@example.com", "2024-01-20T00:00:00Z");
+
+        update(&clone_path, &asof("2024-01-15T00:00:00Z"), false, 0, None).unwrap();
+        assert_eq!(head(&clone_path), second);
+        assert!(output(git(&clone_path).args(["status", "--porcelain"]))
+            .unwrap()
+            .is_empty());
+        update(&clone_path, &asof("2024-01-15T00:00:00Z"), true, 0, None).unwrap();
+        assert_eq!(head(&clone_path), second);
+
+        assert!(update(&clone_path, &asof("2023-12-01T00:00:00Z"), true, 0, None).is_err());
+    }
+
+    #[test]
+    fn only_files_touched_in_window_are_blamed() {
+        let fixture = Fixture::new();
+        for i in 0..10 {
+            fixture.write(&format!("src/old{i}.rs"), "fn old() {}\n");
+        }
+        fixture.commit("alice@example.com", "2023-06-01T00:00:00Z");
+        fixture.write("src/old3.rs", "fn old() {}\nfn new() {}\n");
+        fixture.write("src/new.rs", "fn new() {}\n");
+        fixture.commit("bob@example.com", "2024-01-10T00:00:00Z");
+
+        let (since, _) = window();
+        let touched = touched_files(fixture.path(), "HEAD", &since).unwrap();
+        assert_eq!(
+            touched,
+            HashSet::from(["src/old3.rs".to_string(), "src/new.rs".to_string()])
+        );
+
+        let mut loc = HashMap::new();
+        let logged = logged_by(|| loc = fixture.blame_stats(&BlameOptions::default()));
+        let mut blamed = logged
+            .into_iter()
+            .filter(|(level, _)| *level == Level::Debug)
+            .map(|(_, message)| message.trim().to_string())
+            .collect::<Vec<_>>();
+        blamed.sort();
+        assert_eq!(blamed, ["src/new.rs", "src/old3.rs"]);
+        assert_eq!(loc, HashMap::from([("bob@example.com".to_string(), 2.)]));
+    }
+
+    #[test]
+    fn unchanged_files_are_not_blamed_again() {
+        let fixture = Fixture::new();
+        fixture.write("a.rs", "fn a() {}\n");
+        fixture.write("b.rs", "fn b() {}\nfn c() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+
+        let cached = |f: &mut dyn FnMut()| {
+            logged_by(f)
+                .into_iter()
+                .filter(|(_, message)| message.starts_with("using the cached response"))
+                .count()
+        };
+        let mut loc = HashMap::new();
+        assert_eq!(
+            cached(&mut || loc = fixture.blame_stats(&BlameOptions::default())),
+            0
+        );
+        assert_eq!(loc.get("alice@example.com"), Some(&3.));
+
+        let mut again = HashMap::new();
+        assert_eq!(
+            cached(&mut || again = fixture.blame_stats(&BlameOptions::default())),
+            2
+        );
+        assert_eq!(again, loc);
+
+        fixture.write("b.rs", "fn b() {}\nfn c() {}\nfn d() {}\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+        assert_eq!(
+            cached(&mut || loc = fixture.blame_stats(&BlameOptions::default())),
+            1
+        );
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+
+        // A different window is blamed anew.
+        let (since, asof) = window();
+        let logged = logged_by(|| {
+            blame_stats(
+                fixture.path(),
+                &(since + Duration::from_secs(1)),
+                &asof,
+                Vec::<String>::new(),
+                &[],
+                &[],
+                &BlameOptions::default(),
+                &ProgressBar::hidden(),
+            )
+            .unwrap();
+        });
+        assert!(!logged
+            .iter()
+            .any(|(_, message)| message.starts_with("using the cached response")));
+    }
+
+    #[test]
+    fn malformed_blame_is_a_warning() {
+        let (since, asof) = window();
+        let blame = BLAME.replace("author-time 1704153600", "author-time yesterday");
+        let logged = logged_by(|| {
+            assert_eq!(
+                parse_blame(&blame, &since, &asof, CommitDate::Author).len(),
+                1
+            );
+        });
+        assert_eq!(
+            logged,
+            [
+                (
+                    Level::Warn,
+                    r#"invalid timestamp format: "yesterday""#.to_string()
+                ),
+                (
+                    Level::Warn,
+                    "incomplete blame information for line: \t// (<not an email>)".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reindented_lines_keep_their_author() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn f() {\nlet a = 1;\nlet b = 2;\n}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write("lib.rs", "fn f() {\n    let a = 1;\n    let b = 2;\n}\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&4.));
+        assert_eq!(loc.get("bob@example.com"), None);
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            ignore_whitespace: false,
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("alice@example.com"), Some(&2.));
+        assert_eq!(loc.get("bob@example.com"), Some(&2.));
+    }
+
+    #[test]
+    fn moved_lines_keep_their_author_when_detecting_moves() {
+        const BLOCK: &str = "\
+let first_variable_with_a_long_name = compute_something_interesting(1);
+let second_variable_with_a_long_name = compute_something_interesting(2);
+let third_variable_with_a_long_name = compute_something_interesting(3);
+";
+        let fixture = Fixture::new();
+        fixture.write("a.rs", BLOCK);
+        fixture.write("b.rs", "fn main() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write("a.rs", "");
+        fixture.write("b.rs", &format!("fn main() {{}}\n{BLOCK}"));
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            detect_moves: true,
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("alice@example.com"), Some(&4.));
+        assert_eq!(loc.get("bob@example.com"), None);
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), Some(&3.));
+    }
+
+    #[test]
+    fn binary_files_are_not_blamed() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn main() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fs::write(fixture.path().join("blob.bin"), b"\x00\x01\n\x02\n\xff\n").unwrap();
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), None);
+    }
+
+    #[test]
+    fn recent_lines_weigh_more() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\n");
+        fixture.commit("alice@example.com", "2024-01-01T00:00:00Z");
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.commit("bob@example.com", "2024-01-31T00:00:00Z");
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&2.));
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+
+        // Alice's lines are 30 days old, one half-life.
+        let loc = fixture.blame_stats(&BlameOptions {
+            recency: Some(RecencyWeight::Exponential {
+                half_life_days: 30.,
+            }),
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+
+        // They are 30 days into the 61-day window.
+        let loc = fixture.blame_stats(&BlameOptions {
+            recency: Some(RecencyWeight::Linear),
+            ..BlameOptions::default()
+        });
+        assert!((loc["alice@example.com"] - 2. * 31. / 61.).abs() < 1e-9);
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+    }
+
+    #[test]
+    fn removed_lines_are_credited_to_whoever_removed_them() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.write("vendor/dep.rs", "fn d() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write("lib.rs", "fn a() {}\n");
+        fixture.write("vendor/dep.rs", "");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.line_counts(&["^vendor/"], &[], &BlameOptions::default());
+        assert_eq!(
+            loc.get("bob@example.com"),
+            Some(&LineCount {
+                removed: 2.,
+                ..LineCount::default()
+            })
+        );
+        assert!((loc["alice@example.com"].total() - 1.).abs() < f64::EPSILON);
+        assert!(loc["alice@example.com"].removed.abs() < f64::EPSILON);
+
+        fixture.git(&["mv", "lib.rs", "core.rs"]);
+        fixture.commit("carol@example.com", "2024-01-04T00:00:00Z");
+
+        let loc = fixture.line_counts(&["^vendor/"], &[], &BlameOptions::default());
+        assert!((loc["bob@example.com"].removed - 2.).abs() < f64::EPSILON);
+        assert!(
+            loc.get("carol@example.com")
+                .map_or(0., |lines| lines.removed)
+                .abs()
+                < f64::EPSILON
+        );
+    }
+
+    #[test]
+    fn lines_in_test_paths_are_test_code() {
+        let fixture = Fixture::new();
+        fixture.write("src/lib.rs", "fn a() {}\nfn b() {}\n");
+        fixture.write("tests/lib.rs", "#[test]\nfn a() {}\n#[test]\nfn b() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+
+        let test_paths = vec!["(^|/)tests/".to_string()];
+        let loc = fixture.line_counts(&[], &test_paths, &BlameOptions::default());
+        assert_eq!(
+            loc.get("alice@example.com"),
+            Some(&LineCount {
+                production: 2.,
+                test: 4.,
+                removed: 0.,
+            })
+        );
+
+        let loc = fixture.line_counts(&[], &[], &BlameOptions::default());
+        assert_eq!(
+            loc.get("alice@example.com"),
+            Some(&LineCount {
+                production: 6.,
+                test: 0.,
+                removed: 0.,
+            })
+        );
+    }
+
+    #[test]
+    fn lines_on_several_branches_are_counted_once() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.git(&["checkout", "-q", "-b", "release"]);
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.write("fix.rs", "fn fix() {}\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.write("main.rs", "fn main() {}\n");
+        fixture.commit("carol@example.com", "2024-01-04T00:00:00Z");
+
+        let branches = vec!["release".to_string()];
+        let loc: HashMap<_, _> = fixture
+            .line_counts_on(&[], &[], &branches, &BlameOptions::default())
+            .into_iter()
+            .map(|(email, lines)| (email, lines.total()))
+            .collect();
+        assert_eq!(loc.get("alice@example.com"), Some(&2.));
+        assert_eq!(loc.get("bob@example.com"), Some(&2.));
+        assert_eq!(loc.get("carol@example.com"), Some(&1.));
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("bob@example.com"), None);
+    }
+
+    #[test]
+    fn last_tag_starts_the_release_window() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\n");
+        fixture.commit("alice@example.com", "2023-11-20T00:00:00Z");
+        fixture.git(&["tag", "v0.1.0"]);
+        assert_eq!(
+            last_tag(fixture.path()).unwrap(),
+            Some(Tag {
+                name: "v0.1.0".to_string(),
+                date: "2023-11-20T00:00:00Z".parse().unwrap(),
+            })
+        );
+
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\n");
+        fixture.commit("alice@example.com", "2024-01-10T00:00:00Z");
+        let status = git(fixture.path())
+            .args([
+                "-c",
+                "tag.gpgsign=false",
+                "tag",
+                "-a",
+                "v0.2.0",
+                "-m",
+                "v0.2.0",
+            ])
+            .env("GIT_COMMITTER_NAME", "alice")
+            .env("GIT_COMMITTER_EMAIL", "alice@example.com")
+            .env("GIT_COMMITTER_DATE", "2024-01-12T00:00:00Z")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.commit("alice@example.com", "2024-01-15T00:00:00Z");
+
+        // The window starts at the commit, not when it was tagged.
+        assert_eq!(
+            last_tag(fixture.path()).unwrap(),
+            Some(Tag {
+                name: "v0.2.0".to_string(),
+                date: "2024-01-10T00:00:00Z".parse().unwrap(),
+            })
+        );
+        let (since, asof) = window();
+        assert_eq!(
+            tags_between(fixture.path(), &since, &asof).unwrap(),
+            vec![Tag {
+                name: "v0.2.0".to_string(),
+                date: "2024-01-12T00:00:00Z".parse().unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn last_tag_is_none_without_tags() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        assert_eq!(last_tag(fixture.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn selected_commit_date_decides_the_window() {
+        let fixture = Fixture::new();
+        // Written before the window, and rebased within it.
+        fixture.write("old.rs", "fn a() {}\nfn b() {}\n");
+        fixture.commit_dated(
+            "alice@example.com",
+            "2023-11-15T00:00:00Z",
+            "2024-01-05T00:00:00Z",
+            "update",
+        );
+        // Written within the window, and rebased after it.
+        fixture.write("new.rs", "fn c() {}\n");
+        fixture.commit_dated(
+            "bob@example.com",
+            "2024-01-20T00:00:00Z",
+            "2024-02-05T00:00:00Z",
+            "update",
+        );
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), None);
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            date: CommitDate::Committer,
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("alice@example.com"), Some(&2.));
+        assert_eq!(loc.get("bob@example.com"), None);
+    }
+
+    #[test]
+    fn co_authors_share_line_credit() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.commit_with_message(
+            "alice@example.com",
+            "2024-01-02T00:00:00Z",
+            "Pair on lib\n\n\
+             Co-authored-by: Bob <bob@example.com>\n\
+             Co-authored-by: Carol (QA) <carol@example.com>\n",
+        );
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            credit_co_authors: true,
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+        assert_eq!(loc.get("carol@example.com"), Some(&1.));
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&3.));
+        assert_eq!(loc.get("bob@example.com"), None);
+    }
+
+    #[test]
+    fn merger_is_not_credited_for_conflict_resolution() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.git(&["checkout", "-q", "-b", "side"]);
+        fixture.write("lib.rs", "fn b() {}\n");
+        fixture.write("side.rs", "fn side() {}\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.write("lib.rs", "fn c() {}\n");
+        fixture.commit("alice@example.com", "2024-01-04T00:00:00Z");
+        let status = git(fixture.path())
+            .args([
+                "-c",
+                "user.name=carol",
+                "-c",
+                "user.email=carol@example.com",
+            ])
+            .args(["merge", "-q", "side"])
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+        fixture.write("lib.rs", "fn bc() {}\n");
+        fixture.commit_with_message("carol@example.com", "2024-01-05T00:00:00Z", "Merge side");
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+        assert_eq!(loc.get("carol@example.com"), None);
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            ignore_merges: false,
+            ..BlameOptions::default()
+        });
+        assert_eq!(loc.get("carol@example.com"), Some(&1.));
+    }
+
+    #[test]
+    fn ignored_commits_are_skipped_over() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write("lib.rs", "fn a() { }\nfn b() { }\nfn c() { }\n");
+        fixture.write("vendor.rs", "fn v() {}\nfn w() {}\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+        let import = output(git(fixture.path()).args(["rev-parse", "HEAD"])).unwrap();
+
+        let options = BlameOptions {
+            ignore_whitespace: false,
+            ..BlameOptions::default()
+        };
+        let loc = fixture.blame_stats(&options);
+        assert_eq!(loc.get("bob@example.com"), Some(&5.));
+        assert_eq!(loc.get("alice@example.com"), None);
+        let removed = fixture.line_counts(&[], &[], &options)["bob@example.com"].removed;
+        assert!((removed - 3.).abs() < f64::EPSILON);
+
+        let options = BlameOptions {
+            ignore_whitespace: false,
+            ignore_commits: vec![import[..10].to_string(), "0000000".to_string()],
+            ..BlameOptions::default()
+        };
+        let loc = fixture.blame_stats(&options);
+        assert_eq!(loc.get("alice@example.com"), Some(&3.));
+        assert_eq!(loc.get("bob@example.com"), None);
+        let loc = fixture.line_counts(&[], &[], &options);
+        assert!(!loc.contains_key("bob@example.com"));
+
+        fixture.write("ignored-revs", &format!("# Bulk import\n{import}\n"));
+        let options = BlameOptions {
+            ignore_whitespace: false,
+            ignore_revs_file: Some(PathBuf::from("ignored-revs")),
+            ..BlameOptions::default()
+        };
+        let loc = fixture.blame_stats(&options);
+        assert_eq!(loc.get("alice@example.com"), Some(&3.));
+        assert_eq!(loc.get("bob@example.com"), None);
+    }
+
+    #[test]
+    fn git_blame_ignore_revs_is_honored() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\nfn b() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write("lib.rs", "fn a() { }\nfn b() { }\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+        let reformat = output(git(fixture.path()).args(["rev-parse", "HEAD"])).unwrap();
+        fixture.write(
+            ".git-blame-ignore-revs",
+            &format!("# Reformat with spaces\n{reformat}\n"),
+        );
+        fixture.commit("carol@example.com", "2024-01-04T00:00:00Z");
+
+        let options = BlameOptions {
+            ignore_whitespace: false,
+            ..BlameOptions::default()
+        };
+        let loc = fixture.blame_stats(&options);
+        assert_eq!(loc.get("alice@example.com"), Some(&2.));
+        assert_eq!(loc.get("bob@example.com"), None);
+
+        let loc = fixture.blame_stats(&BlameOptions {
+            detect_ignore_revs_file: false,
+            ..options
+        });
+        assert_eq!(loc.get("alice@example.com"), None);
+        assert_eq!(loc.get("bob@example.com"), Some(&2.));
+    }
+
+    #[test]
+    fn commits_in_the_window_are_detected() {
+        let (since, asof) = window();
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn a() {}\n");
+        fixture.commit("alice@example.com", "2023-06-01T00:00:00Z");
+        fixture.git(&["checkout", "-q", "-b", "release"]);
+        fixture.write("lib.rs", "fn b() {}\n");
+        fixture.commit_dated(
+            "bob@example.com",
+            "2023-11-01T00:00:00Z",
+            "2024-01-02T00:00:00Z",
+            "Cherry-pick b",
+        );
+        fixture.git(&["checkout", "-q", "main"]);
+
+        let date = CommitDate::Committer;
+        assert!(!has_commits_between(fixture.path(), &since, &asof, &[], date).unwrap());
+        let branches = ["release".to_string()];
+        assert!(has_commits_between(fixture.path(), &since, &asof, &branches, date).unwrap());
+        let date = CommitDate::Author;
+        assert!(!has_commits_between(fixture.path(), &since, &asof, &branches, date).unwrap());
+    }
+
+    #[test]
+    fn excluded_directories_are_pruned() {
+        let fixture = Fixture::new();
+        fixture.write("lib.rs", "fn main() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write(
+            "web/node_modules/left-pad/index.js",
+            "module.exports = 1;\n",
+        );
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.blame_stats_excluding(&[r"(^|/)node_modules/"], &BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), None);
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+    }
+
+    #[test]
+    fn generated_files_are_not_blamed() {
+        let fixture = Fixture::new();
+        fixture.write(".gitattributes", "src/bindings.rs linguist-generated\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+        fixture.write(
+            "src/bindings.rs",
+            "pub const A: u32 = 1;\npub const B: u32 = 2;\n",
+        );
+        fixture.write("src/lib.rs", "mod bindings;\n");
+        fixture.commit("bob@example.com", "2024-01-03T00:00:00Z");
+
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(loc.get("bob@example.com"), Some(&1.));
+    }
+
+    #[test]
+    fn overriding_exclude_defaults_counts_excluded_files() {
+        let fixture = Fixture::new();
+        fixture.write("Cargo.lock", "version = 3\n\n[[package]]\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+
+        for (override_defaults, expected) in [(false, None), (true, Some(&3.))] {
+            let patterns = exclude_patterns(&[], override_defaults);
+            let patterns = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+            let loc = fixture.blame_stats_excluding(&patterns, &BlameOptions::default());
+            assert_eq!(loc.get("alice@example.com"), expected);
+        }
+    }
+
+    #[test]
+    fn blame_stats_keeps_working_directory() {
+        let fixture = Fixture::new();
+        fixture.write("src/lib.rs", "fn main() {}\n");
+        fixture.commit("alice@example.com", "2024-01-02T00:00:00Z");
+
+        let cwd = std::env::current_dir().unwrap();
+        let loc = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(loc.get("alice@example.com"), Some(&1.));
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+    }
+
+    #[test]
+    fn emails_differing_in_case_are_one_contributor() {
+        let fixture = Fixture::new();
+        fixture.write("a.txt", "one\n");
+        fixture.commit("Jane@Corp.com", "2024-01-10T00:00:00Z");
+        fixture.write("a.txt", "one\ntwo\n");
+        fixture.commit("jane@corp.com", "2024-01-11T00:00:00Z");
+
+        let stats = fixture.blame_stats(&BlameOptions::default());
+        assert_eq!(stats.len(), 1);
+        assert!((stats["jane@corp.com"] - 2.).abs() < f64::EPSILON);
     }
-    env::set_current_dir(orig_dir)?;
-    Ok(())
 }
@@ -1,7 +1,17 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::Result;
-use graphql_client::GraphQLQuery;
+use anyhow::{anyhow, Result};
+use graphql_client::{GraphQLQuery, Response};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::github_app::GithubApp;
+use crate::proxy::ProxyConfig;
 
 type DateTime = String;
 
@@ -21,6 +31,9 @@ struct AssignedIssues;
 )]
 struct RecentIssues;
 
+/// Only the fragment of this query is used, to query many repositories at a
+/// time in `Client::open_pull_requests`.
+#[allow(dead_code)]
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/github.graphql",
@@ -36,70 +49,431 @@ struct OpenPullRequests;
 )]
 struct MergedPullRequests;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/github.graphql",
+    query_path = "src/pull_request_reviews.graphql"
+)]
+struct PullRequestReviews;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/github.graphql",
+    query_path = "src/issue_comments.graphql"
+)]
+struct IssueComments;
+
+const GRAPHQL_URL: &str = "https://api.github.com/graphql";
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+/// The owner of repositories configured without one.
+pub const DEFAULT_OWNER: &str = "petabi";
+/// The key of the token used for owners without their own token.
+const DEFAULT_TOKEN_KEY: &str = "default";
+
+/// GitHub tokens keyed by repository owner.
+///
+/// The token under `default` is used for owners without their own token. A
+/// single string, as in `token = "..."`, is taken as the default token.
+#[derive(Clone, Default, Deserialize)]
+#[serde(from = "TokenConfig")]
+pub struct Tokens(HashMap<String, String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenConfig {
+    Single(String),
+    PerOwner(HashMap<String, String>),
+}
+
+impl From<TokenConfig> for Tokens {
+    fn from(config: TokenConfig) -> Self {
+        match config {
+            TokenConfig::Single(token) => {
+                Self(HashMap::from([(DEFAULT_TOKEN_KEY.to_string(), token)]))
+            }
+            TokenConfig::PerOwner(tokens) => Self(tokens),
+        }
+    }
+}
+
+impl From<String> for Tokens {
+    fn from(token: String) -> Self {
+        TokenConfig::Single(token).into()
+    }
+}
+
+impl Tokens {
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.0.values_mut()
+    }
+
+    /// Returns whether no token is configured, or only empty ones.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.values().all(String::is_empty)
+    }
+
+    /// Returns the owners of `repos` with neither their own token nor a
+    /// default one, each once.
+    #[must_use]
+    pub fn owners_without_token<'a>(&self, repos: &'a [String]) -> Vec<&'a str> {
+        owners_without_token(&self.0, repos)
+    }
+}
+
+/// Returns the owners of `repos` without a nonempty token in `tokens`, under
+/// their own name or `DEFAULT_TOKEN_KEY`, in the order they first appear.
+fn owners_without_token<'a>(tokens: &HashMap<String, String>, repos: &'a [String]) -> Vec<&'a str> {
+    let has_token = |owner: &str| tokens.get(owner).is_some_and(|token| !token.is_empty());
+    let mut owners = Vec::new();
+    for repo in repos {
+        let owner = RepoSlug::from(repo.as_str()).owner();
+        if !has_token(owner) && !has_token(DEFAULT_TOKEN_KEY) && !owners.contains(&owner) {
+            owners.push(owner);
+        }
+    }
+    owners
+}
+
+/// Finds a token to use when none is configured: `GH_TOKEN` or
+/// `GITHUB_TOKEN`, or else the one the `gh` CLI stored for github.com in its
+/// `hosts.yml`. `var` looks up environment variables.
+pub fn fallback_token(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    if let Some(token) = var("GH_TOKEN").or_else(|| var("GITHUB_TOKEN")) {
+        return Some(token);
+    }
+    let config_dir = var("GH_CONFIG_DIR")
+        .map(PathBuf::from)
+        .or_else(|| var("XDG_CONFIG_HOME").map(|dir| PathBuf::from(dir).join("gh")))
+        .or_else(|| var("HOME").map(|dir| PathBuf::from(dir).join(".config").join("gh")))?;
+    let hosts = std::fs::read_to_string(config_dir.join("hosts.yml")).ok()?;
+    gh_token(&hosts, "github.com")
+}
+
+/// Extracts the `oauth_token` of `host` from the `hosts.yml` of the `gh`
+/// CLI, which has a top-level key for each host.
+fn gh_token(hosts: &str, host: &str) -> Option<String> {
+    let mut in_host = false;
+    for line in hosts.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            in_host = line.trim_end() == format!("{host}:");
+        } else if in_host {
+            if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+                let token = token.trim().trim_matches(|c| c == '"' || c == '\'');
+                return Some(token.to_string()).filter(|token| !token.is_empty());
+            }
+        }
+    }
+    None
+}
+
+fn http_client_builder() -> reqwest::blocking::ClientBuilder {
+    reqwest::blocking::ClientBuilder::new().user_agent(USER_AGENT)
+}
+
+/// A repository given as `owner/name`, or just `name` for a repository owned
+/// by petabi.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepoSlug<'a> {
+    owner: &'a str,
+    name: &'a str,
+}
+
+impl<'a> RepoSlug<'a> {
+    /// Parses `repo`, checking that its owner and name are ones GitHub allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `repo` does not have exactly one `/`, or its owner
+    /// or name is empty or has a character GitHub does not allow.
+    pub fn parse(repo: &'a str) -> Result<Self> {
+        let invalid = || anyhow!("invalid repository \"{repo}\", expected \"owner/name\"");
+        let (owner, name) = repo.split_once('/').ok_or_else(invalid)?;
+        let slug = Self { owner, name };
+        let is_valid = |part: &str, punctuation: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || punctuation.contains(c))
+        };
+        if is_valid(slug.owner, "") && is_valid(slug.name, "._") {
+            Ok(slug)
+        } else {
+            Err(invalid())
+        }
+    }
+
+    #[must_use]
+    pub fn owner(&self) -> &'a str {
+        self.owner
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+}
+
+impl<'a> From<&'a str> for RepoSlug<'a> {
+    /// Splits `repo` without checking it, as done for repositories already
+    /// validated with the configuration.
+    fn from(repo: &'a str) -> Self {
+        let (owner, name) = repo.split_once('/').unwrap_or((DEFAULT_OWNER, repo));
+        Self { owner, name }
+    }
+}
+
+impl fmt::Display for RepoSlug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
 pub struct Client {
-    token: String,
+    token: HashMap<String, String>,
+    app: Option<GithubApp>,
+    url: String,
     inner: reqwest::blocking::Client,
+    cache: Option<Cache>,
 }
 
 impl Client {
-    pub fn new(token: &str) -> Self {
+    /// Creates a client that authenticates with `tokens`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend cannot be initialized.
+    #[must_use]
+    pub fn new(tokens: &Tokens) -> Self {
+        Self::with_base_url(tokens, GRAPHQL_URL)
+    }
+
+    /// Creates a client that sends its queries to the GraphQL endpoint at
+    /// `url` instead of GitHub's, such as that of GitHub Enterprise Server or
+    /// a mock server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend cannot be initialized.
+    #[must_use]
+    pub fn with_base_url(tokens: &Tokens, url: &str) -> Self {
+        let inner = http_client_builder().build().unwrap();
+        Self::with_http_client(tokens, url, inner)
+    }
+
+    /// Creates a client that sends its queries to `url` through `inner`,
+    /// which controls timeouts, proxies, and the like.
+    #[must_use]
+    pub fn with_http_client(tokens: &Tokens, url: &str, inner: reqwest::blocking::Client) -> Self {
         Self {
-            token: token.to_string(),
-            inner: reqwest::blocking::ClientBuilder::new()
-                .user_agent(USER_AGENT)
-                .build()
-                .unwrap(),
+            token: tokens.0.clone(),
+            app: None,
+            url: url.to_string(),
+            inner,
+            cache: None,
         }
     }
 
-    pub fn assigned_stale_issues(
+    /// Makes the client reuse a response received within `ttl` for the same
+    /// query, storing responses in `dir`.
+    #[must_use]
+    pub fn with_cache(mut self, dir: PathBuf, ttl: Duration) -> Self {
+        self.cache = Some(Cache::new(dir, ttl));
+        self
+    }
+
+    /// Makes the client send its requests through `proxy`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL of the proxy is invalid or the TLS backend
+    /// cannot be initialized.
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Result<Self> {
+        self.inner = proxy.apply(http_client_builder())?.build()?;
+        Ok(self)
+    }
+
+    /// Makes the client authenticate as an installation of `app` instead of
+    /// with its tokens.
+    #[must_use]
+    pub fn with_app(mut self, app: GithubApp) -> Self {
+        self.app = Some(app);
+        self
+    }
+
+    /// Checks that there is a token for the owner of each of `repos`, unless
+    /// authenticating as an app, so that a missing one is reported before
+    /// any query is sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the owners without a token.
+    pub fn check_tokens(&self, repos: &[String]) -> Result<()> {
+        if self.app.is_some() {
+            return Ok(());
+        }
+        let owners = owners_without_token(&self.token, repos);
+        if owners.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "no GitHub token configured for {}",
+                owners.join(", ")
+            ))
+        }
+    }
+
+    /// Returns the token for repositories owned by `owner`: that of the app
+    /// installation if any, or else the owner's, falling back to the default
+    /// token.
+    fn token(&self, owner: &str) -> Result<String> {
+        if let Some(app) = &self.app {
+            return app.token(&self.inner);
+        }
+        self.token
+            .get(owner)
+            .or_else(|| self.token.get(DEFAULT_TOKEN_KEY))
+            .cloned()
+            .ok_or_else(|| anyhow!("no GitHub token configured for {owner}"))
+    }
+
+    /// Sends a GraphQL query about `owner/name` and returns the data in its
+    /// response.
+    fn query<Q: GraphQLQuery>(
         &self,
-        repos: &[String],
-        asof: &chrono::DateTime<chrono::Utc>,
-    ) -> Result<Vec<Issue>> {
+        owner: &str,
+        name: &str,
+        variables: Q::Variables,
+    ) -> Result<Q::ResponseData> {
+        self.post(
+            owner,
+            &format!("{owner}/{name}"),
+            &Q::build_query(variables),
+        )
+    }
+
+    /// Posts `query` with the token for `owner` and returns the data in its
+    /// response, which may come from the cache. `repos` names the
+    /// repositories queried in errors.
+    fn post<T: DeserializeOwned>(
+        &self,
+        owner: &str,
+        repos: &str,
+        query: &impl Serialize,
+    ) -> Result<T> {
+        let body = serde_json::to_string(query)?;
+        // The token is left out, but the owner determines which one is used.
+        let request = format!("{}\n{owner}\n{body}", self.url);
+        if let Some(response) = self.cache.as_ref().and_then(|cache| cache.get(&request)) {
+            return response_data(repos, serde_json::from_str(&response)?);
+        }
+
+        let res = self
+            .inner
+            .post(&self.url)
+            .bearer_auth(self.token(owner)?)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()?;
+        let status = res.status();
+        if !status.is_success() {
+            return Err(http_error(repos, status, &res.text().unwrap_or_default()));
+        }
+        let response = res.text()?;
+        let data = response_data(repos, serde_json::from_str(&response)?)?;
+        if let Some(cache) = &self.cache {
+            cache.put(&request, &response);
+        }
+        Ok(data)
+    }
+
+    /// Sends one GraphQL query about all repositories in `batch`, which are
+    /// owned by `owner`, selecting `selection` from each, and returns the
+    /// data for each under its alias. `fragments` defines the fragments used
+    /// in `selection`.
+    fn batch_query<T: DeserializeOwned>(
+        &self,
+        owner: &str,
+        batch: &[(&str, &str)],
+        selection: &str,
+        fragments: &str,
+    ) -> Result<HashMap<String, Option<T>>> {
+        let mut params = Vec::new();
+        let mut fields = Vec::new();
+        let mut variables = serde_json::Map::new();
+        for (i, (name, _)) in batch.iter().enumerate() {
+            params.push(format!("$owner{i}: String!, $name{i}: String!"));
+            fields.push(format!(
+                "{}: repository(owner: $owner{i}, name: $name{i}) {{ {selection} }}",
+                alias(i)
+            ));
+            variables.insert(format!("owner{i}"), owner.into());
+            variables.insert(format!("name{i}"), (*name).into());
+        }
+        let query = serde_json::json!({
+            "query": format!(
+                "query Batch({}) {{\n{}\n}}\n{fragments}",
+                params.join(", "),
+                fields.join("\n")
+            ),
+            "variables": variables,
+        });
+        let repos = batch
+            .iter()
+            .map(|(_, repo)| *repo)
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.post(owner, &repos, &query)
+    }
+
+    /// Returns the open issues with assignees in `repos`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn assigned_issues(&self, repos: &[String]) -> Result<Vec<Issue>> {
         let mut issues = Vec::new();
         for repo in repos {
-            let query = AssignedIssues::build_query(assigned_issues::Variables {
-                owner: "petabi".to_string(),
-                name: repo.to_string(),
-            });
-            let res = self
-                .inner
-                .post("https://api.github.com/graphql")
-                .bearer_auth(&self.token)
-                .json(&query)
-                .send()?;
-
-            let body: graphql_client::Response<assigned_issues::ResponseData> = res.json()?;
-            if let Some(data) = body.data {
-                if let Some(repository) = data.repository {
-                    if let Some(nodes) = repository.issues.nodes {
-                        for node in nodes {
-                            let Some(node) = node else {
-                                continue;
-                            };
-                            let updated_at =
-                                chrono::DateTime::parse_from_rfc3339(&node.updated_at)?;
-                            if updated_at
-                                > *asof
-                                    - chrono::Duration::try_days(1).expect("valid constant value")
-                            {
-                                continue;
-                            }
-                            issues.push(Issue {
-                                title: node.title,
-                                number: node.number,
-                                repo: repo.to_string(),
-                                assignees: node.assignees.nodes.map_or_else(Vec::new, |nodes| {
-                                    nodes
-                                        .into_iter()
-                                        .filter_map(|v| v.map(|node| node.login))
-                                        .collect()
-                                }),
-                            });
-                        }
+            let slug = RepoSlug::from(repo.as_str());
+            let (owner, name) = (slug.owner(), slug.name());
+            let data = self.query::<AssignedIssues>(
+                owner,
+                name,
+                assigned_issues::Variables {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                },
+            )?;
+            if let Some(repository) = data.repository {
+                if let Some(nodes) = repository.issues.nodes {
+                    for node in nodes {
+                        let Some(node) = node else {
+                            continue;
+                        };
+                        let updated_at = chrono::DateTime::parse_from_rfc3339(&node.updated_at)?;
+                        let labels = node.labels.map_or_else(Vec::new, |labels| {
+                            labels.nodes.map_or_else(Vec::new, |nodes| {
+                                nodes
+                                    .into_iter()
+                                    .filter_map(|v| v.map(|v| v.name))
+                                    .collect()
+                            })
+                        });
+                        issues.push(Issue {
+                            title: node.title,
+                            number: node.number,
+                            repo: repo.clone(),
+                            assignees: node.assignees.nodes.map_or_else(Vec::new, |nodes| {
+                                nodes
+                                    .into_iter()
+                                    .filter_map(|v| v.map(|node| node.login))
+                                    .collect()
+                            }),
+                            labels,
+                            updated_at,
+                        });
                     }
                 }
             }
@@ -107,6 +481,11 @@ impl Client {
         Ok(issues)
     }
 
+    /// Returns the issues in `repos` updated since `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
     pub fn issue_metadata_since(
         &self,
         repos: &[String],
@@ -115,55 +494,52 @@ impl Client {
         let mut issues = Vec::new();
         let rfc3339_since = since.to_rfc3339();
         for repo in repos {
-            let query = RecentIssues::build_query(recent_issues::Variables {
-                owner: "petabi".to_string(),
-                name: repo.to_string(),
-                since: rfc3339_since.clone(),
-            });
-            let res = self
-                .inner
-                .post("https://api.github.com/graphql")
-                .bearer_auth(&self.token)
-                .json(&query)
-                .send()?;
-
-            let body: graphql_client::Response<recent_issues::ResponseData> = res.json()?;
-            if let Some(data) = body.data {
-                if let Some(repository) = data.repository {
-                    if let Some(nodes) = repository.issues.nodes {
-                        for node in nodes.into_iter().flatten() {
-                            let author = node
-                                .author
-                                .map_or_else(|| "unknown".to_string(), |v| v.login);
-                            let created_at =
-                                chrono::DateTime::parse_from_rfc3339(&node.created_at)?;
-                            let labels = node.labels.map_or_else(Vec::new, |labels| {
-                                labels.nodes.map_or_else(Vec::new, |nodes| {
-                                    nodes
-                                        .into_iter()
-                                        .filter_map(|v| v.map(|v| v.name))
-                                        .collect()
-                                })
-                            });
-                            let closed_at = if let Some(closed_at) = node.closed_at {
-                                Some(chrono::DateTime::parse_from_rfc3339(&closed_at)?)
-                            } else {
-                                None
-                            };
-                            let assignees = node.assignees.nodes.map_or_else(Vec::new, |nodes| {
+            let slug = RepoSlug::from(repo.as_str());
+            let (owner, name) = (slug.owner(), slug.name());
+            let data = self.query::<RecentIssues>(
+                owner,
+                name,
+                recent_issues::Variables {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                    since: rfc3339_since.clone(),
+                },
+            )?;
+            if let Some(repository) = data.repository {
+                if let Some(nodes) = repository.issues.nodes {
+                    for node in nodes.into_iter().flatten() {
+                        let author = node.author.map(|v| v.login);
+                        let created_at = chrono::DateTime::parse_from_rfc3339(&node.created_at)?;
+                        let labels = node.labels.map_or_else(Vec::new, |labels| {
+                            labels.nodes.map_or_else(Vec::new, |nodes| {
                                 nodes
                                     .into_iter()
-                                    .filter_map(|v| v.map(|v| v.login))
+                                    .filter_map(|v| v.map(|v| v.name))
                                     .collect()
-                            });
-                            issues.push(IssueMetadata {
-                                author,
-                                labels,
-                                assignees,
-                                created_at,
-                                closed_at,
-                            });
-                        }
+                            })
+                        });
+                        let closed_at = if let Some(closed_at) = node.closed_at {
+                            Some(chrono::DateTime::parse_from_rfc3339(&closed_at)?)
+                        } else {
+                            None
+                        };
+                        let assignees = node.assignees.nodes.map_or_else(Vec::new, |nodes| {
+                            nodes
+                                .into_iter()
+                                .filter_map(|v| v.map(|v| v.login))
+                                .collect()
+                        });
+                        issues.push(IssueMetadata {
+                            author,
+                            labels,
+                            assignees,
+                            created_at,
+                            closed_at,
+                            repo: repo.clone(),
+                            number: node.number,
+                            title: node.title,
+                            reactions: node.reactions.total_count,
+                        });
                     }
                 }
             }
@@ -171,214 +547,937 @@ impl Client {
         Ok(issues)
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn recent_issues_per_login(
+    /// Returns the open pull requests in `repos`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn open_pull_requests(&self, repos: &[String]) -> Result<Vec<PullRequest>> {
+        let mut by_repo = HashMap::new();
+        for (owner, batch) in batches(repos) {
+            let mut data = self.batch_query::<open_pull_requests::OpenPullRequestsOfRepository>(
+                owner,
+                &batch,
+                "...OpenPullRequestsOfRepository",
+                fragments(open_pull_requests::QUERY),
+            )?;
+            for (i, (_, repo)) in batch.iter().enumerate() {
+                if let Some(Some(repository)) = data.remove(&alias(i)) {
+                    by_repo.insert(*repo, open_pull_requests_of(repo, repository)?);
+                }
+            }
+        }
+        Ok(repos
+            .iter()
+            .filter_map(|repo| by_repo.remove(repo.as_str()))
+            .flatten()
+            .collect())
+    }
+
+    /// Returns the pull requests in `repos` merged since `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn merged_pull_requests(
         &self,
         repos: &[String],
         since: &chrono::DateTime<chrono::Utc>,
-        recent_since: &chrono::DateTime<chrono::Utc>,
-    ) -> Result<HashMap<String, (usize, usize, f32, usize, f32)>> {
-        let mut counter = HashMap::new();
-        let rfc3339_since = since.to_rfc3339();
+    ) -> Result<Vec<MergedPullRequest>> {
+        let mut prs = Vec::new();
         for repo in repos {
-            let query = RecentIssues::build_query(recent_issues::Variables {
-                owner: "petabi".to_string(),
-                name: repo.to_string(),
-                since: rfc3339_since.clone(),
-            });
-            let res = self
-                .inner
-                .post("https://api.github.com/graphql")
-                .bearer_auth(&self.token)
-                .json(&query)
-                .send()?;
-
-            let body: graphql_client::Response<recent_issues::ResponseData> = res.json()?;
-            if let Some(data) = body.data {
-                if let Some(repository) = data.repository {
-                    if let Some(nodes) = repository.issues.nodes {
-                        for node in nodes.into_iter().flatten() {
-                            let created_at =
-                                chrono::DateTime::parse_from_rfc3339(&node.created_at)?;
-                            if *since <= created_at {
-                                let author = node
-                                    .author
-                                    .map_or_else(|| "unknown".to_string(), |v| v.login);
-                                let stat = counter.entry(author).or_insert((0, 0, 0.0, 0, 0.0));
-                                if let Some(labels) = node.labels {
-                                    if let Some(nodes) = labels.nodes {
-                                        let is_bug = nodes
-                                            .into_iter()
-                                            .any(|v| v.is_some_and(|v| v.name == "bug"));
-                                        if is_bug {
-                                            stat.1 += 1;
-                                        }
-                                    } else {
-                                        stat.0 += 1;
-                                    }
-                                } else {
-                                    stat.0 += 1;
-                                };
-
-                                if *recent_since < created_at {
-                                    stat.3 += 1;
-                                }
-                            }
-                            if let Some(closed_at) = node.closed_at {
-                                let closed_at = chrono::DateTime::parse_from_rfc3339(&closed_at)?;
-                                if let Some(nodes) = node.assignees.nodes {
-                                    let mut total_assignees = 0.0;
-                                    for node in &nodes {
-                                        if node.is_some() {
-                                            total_assignees += 1.0;
-                                        }
-                                    }
-                                    for node in nodes {
-                                        let Some(node) = node else {
-                                            continue;
-                                        };
-                                        let stat = counter
-                                            .entry(node.login)
-                                            .or_insert((0, 0, 0.0, 0, 0.0));
-                                        stat.2 += 1.0 / total_assignees;
-
-                                        if *recent_since < closed_at {
-                                            stat.4 += 1.0 / total_assignees;
-                                        }
-                                    }
-                                }
-                            }
+            let slug = RepoSlug::from(repo.as_str());
+            let (owner, name) = (slug.owner(), slug.name());
+            let data = self.query::<MergedPullRequests>(
+                owner,
+                name,
+                merged_pull_requests::Variables {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                },
+            )?;
+            if let Some(repository) = data.repository {
+                if let Some(nodes) = repository.pull_requests.nodes {
+                    for node in nodes.into_iter().flatten() {
+                        let login = if let Some(author) = node.author {
+                            author.login
+                        } else {
+                            continue;
+                        };
+                        // A pull request cannot be merged after its last
+                        // update, so the rest were all merged before `since`.
+                        let updated_at = chrono::DateTime::parse_from_rfc3339(&node.updated_at)?;
+                        if updated_at < *since {
+                            break;
                         }
+                        let Some(merged_at) = node.merged_at else {
+                            continue;
+                        };
+                        prs.push(MergedPullRequest {
+                            author: login,
+                            repo: repo.clone(),
+                            merged_at: chrono::DateTime::parse_from_rfc3339(&merged_at)?,
+                            comments: node.comments.total_count,
+                        });
                     }
                 }
             }
         }
-        Ok(counter)
+        Ok(prs)
     }
 
-    pub fn open_pull_requests(&self, repos: &[String]) -> Result<Vec<PullRequest>> {
-        let mut prs = Vec::new();
+    /// Returns the reviews submitted in `repos` since `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn reviews(
+        &self,
+        repos: &[String],
+        since: &chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Review>> {
+        let mut reviews = Vec::new();
         for repo in repos {
-            let query = OpenPullRequests::build_query(open_pull_requests::Variables {
-                owner: "petabi".to_string(),
-                name: repo.to_string(),
-            });
-            let res = self
-                .inner
-                .post("https://api.github.com/graphql")
-                .bearer_auth(&self.token)
-                .json(&query)
-                .send()?;
-
-            let body: graphql_client::Response<open_pull_requests::ResponseData> = res.json()?;
-            if let Some(data) = body.data {
-                if let Some(repository) = data.repository {
-                    if let Some(nodes) = repository.pull_requests.nodes {
-                        prs.extend(nodes.into_iter().filter_map(|v| {
-                            v.map(|node| PullRequest {
-                                title: node.title,
-                                number: node.number,
-                                repo: repo.to_string(),
-                                reviewers: node.review_requests.map_or(Vec::new(), |rr| {
-                                    rr.edges.map_or(Vec::new(), |edges| {
-                                        edges
-                                            .into_iter()
-                                            .filter_map(|edge| {
-                                                edge.and_then(|edge| {
-                                                    edge.node.and_then(|node| {
-                                                        node.requested_reviewer
-                                                            .and_then(|reviewer| match reviewer {
-                                                                open_pull_requests::OpenPullRequestsRepositoryPullRequestsNodesReviewRequestsEdgesNodeRequestedReviewer::User(u) => Some(u.login),
-                                                                _ => None,
-                                                            })
-                                                    })
-                                                })
-                                            })
-                                            .collect()
-                                    })
-                                }),
-                                assignees: node.assignees.nodes.map_or(Vec::new(), |nodes| {
-                                    nodes
-                                        .into_iter()
-                                        .filter_map(|v| v.map(|node| node.login))
-                                        .collect()
-                                }),
-                            })
-                        }));
-                    }
-                }
-            }
+            let slug = RepoSlug::from(repo.as_str());
+            let (owner, name) = (slug.owner(), slug.name());
+            let data = self.query::<PullRequestReviews>(
+                owner,
+                name,
+                pull_request_reviews::Variables {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                },
+            )?;
+            reviews.extend(submitted_reviews(repo, data, since)?);
         }
-        Ok(prs)
+        Ok(reviews)
     }
 
-    pub fn merged_pull_requests_per_login(
+    /// Returns the comments posted on issues in `repos` since `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn issue_comments(
         &self,
         repos: &[String],
         since: &chrono::DateTime<chrono::Utc>,
-    ) -> Result<HashMap<String, (usize, i64)>> {
-        let mut prs = HashMap::new();
+    ) -> Result<Vec<IssueComment>> {
+        let mut comments = Vec::new();
         for repo in repos {
-            let query = MergedPullRequests::build_query(merged_pull_requests::Variables {
-                owner: "petabi".to_string(),
-                name: repo.to_string(),
+            let slug = RepoSlug::from(repo.as_str());
+            let (owner, name) = (slug.owner(), slug.name());
+            let data = self.query::<IssueComments>(
+                owner,
+                name,
+                issue_comments::Variables {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                },
+            )?;
+            comments.extend(posted_comments(repo, data, since)?);
+        }
+        Ok(comments)
+    }
+}
+
+/// Collects the reviews submitted since `since` on the pull requests in a
+/// response for `repo`, which are ordered by when they were last updated.
+fn submitted_reviews(
+    repo: &str,
+    data: pull_request_reviews::ResponseData,
+    since: &chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<Review>> {
+    let mut reviews = Vec::new();
+    let Some(repository) = data.repository else {
+        return Ok(reviews);
+    };
+    for node in repository
+        .pull_requests
+        .nodes
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+    {
+        // A review cannot be submitted after the last update of its pull
+        // request, so the rest were all submitted before `since`.
+        let updated_at = chrono::DateTime::parse_from_rfc3339(&node.updated_at)?;
+        if updated_at < *since {
+            break;
+        }
+        let nodes = node
+            .reviews
+            .and_then(|reviews| reviews.nodes)
+            .unwrap_or_default();
+        for review in nodes.into_iter().flatten() {
+            let (Some(author), Some(submitted_at)) = (review.author, review.submitted_at) else {
+                continue;
+            };
+            reviews.push(Review {
+                author: author.login,
+                repo: repo.to_string(),
+                submitted_at: chrono::DateTime::parse_from_rfc3339(&submitted_at)?,
+            });
+        }
+    }
+    Ok(reviews)
+}
+
+/// Collects the comments posted since `since` on the issues in a response
+/// for `repo`, which are ordered by when they were last updated.
+fn posted_comments(
+    repo: &str,
+    data: issue_comments::ResponseData,
+    since: &chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<IssueComment>> {
+    let mut comments = Vec::new();
+    let Some(repository) = data.repository else {
+        return Ok(comments);
+    };
+    for node in repository
+        .issues
+        .nodes
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+    {
+        // Commenting updates an issue, so the rest were all commented on
+        // before `since`.
+        let updated_at = chrono::DateTime::parse_from_rfc3339(&node.updated_at)?;
+        if updated_at < *since {
+            break;
+        }
+        for comment in node
+            .comments
+            .nodes
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+        {
+            let Some(author) = comment.author else {
+                continue;
+            };
+            comments.push(IssueComment {
+                author: author.login,
+                repo: repo.to_string(),
+                created_at: chrono::DateTime::parse_from_rfc3339(&comment.created_at)?,
             });
-            let res = self
-                .inner
-                .post("https://api.github.com/graphql")
-                .bearer_auth(&self.token)
-                .json(&query)
-                .send()?;
-
-            let body: graphql_client::Response<merged_pull_requests::ResponseData> = res.json()?;
-            if let Some(data) = body.data {
-                if let Some(repository) = data.repository {
-                    if let Some(nodes) = repository.pull_requests.nodes {
-                        for node in nodes.into_iter().flatten() {
-                            let login = if let Some(author) = node.author {
-                                author.login
-                            } else {
-                                continue;
-                            };
-                            let created_at =
-                                chrono::DateTime::parse_from_rfc3339(&node.created_at)?;
-                            if created_at < *since {
-                                break;
-                            }
-                            let count = prs.entry(login).or_insert((0, 0));
-                            count.0 += 1;
-                            count.1 += node.comments.total_count;
-                        }
-                    }
-                }
-            }
         }
-        Ok(prs)
     }
+    Ok(comments)
+}
+
+/// Returns true if `login` is one of `ignore_logins`. Bots are matched with or
+/// without the "[bot]" suffix, since GitHub includes it in some places and not
+/// in others.
+pub fn is_ignored(login: &str, ignore_logins: &[String]) -> bool {
+    let login = login.strip_suffix("[bot]").unwrap_or(login);
+    ignore_logins.iter().any(|ignored| {
+        ignored
+            .strip_suffix("[bot]")
+            .unwrap_or(ignored)
+            .eq_ignore_ascii_case(login)
+    })
+}
+
+/// The most repositories to query in a single request.
+const MAX_BATCH_LEN: usize = 20;
+
+/// Groups `repos` by owner, in the order they appear, into batches of at most
+/// `MAX_BATCH_LEN` pairs of a repository's name and `repos` entry.
+fn batches(repos: &[String]) -> Vec<(&str, Vec<(&str, &str)>)> {
+    let mut batches: Vec<(&str, Vec<(&str, &str)>)> = Vec::new();
+    for repo in repos {
+        let slug = RepoSlug::from(repo.as_str());
+        let (owner, name) = (slug.owner(), slug.name());
+        match batches
+            .iter_mut()
+            .find(|(o, batch)| *o == owner && batch.len() < MAX_BATCH_LEN)
+        {
+            Some((_, batch)) => batch.push((name, repo)),
+            None => batches.push((owner, vec![(name, repo)])),
+        }
+    }
+    batches
+}
+
+/// Returns the alias of the `i`th repository in a batch.
+fn alias(i: usize) -> String {
+    format!("r{i}")
 }
 
-#[derive(Debug)]
+/// Returns the fragment definitions in a query document.
+fn fragments(document: &str) -> &str {
+    document
+        .find("fragment ")
+        .map_or("", |start| &document[start..])
+}
+
+fn open_pull_requests_of(
+    repo: &str,
+    repository: open_pull_requests::OpenPullRequestsOfRepository,
+) -> Result<Vec<PullRequest>> {
+    use open_pull_requests::OpenPullRequestsOfRepositoryPullRequestsNodesReviewRequestsEdgesNodeRequestedReviewer as Reviewer;
+
+    let mut prs = Vec::new();
+    for node in repository
+        .pull_requests
+        .nodes
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        prs.push(PullRequest {
+            title: node.title,
+            number: node.number,
+            repo: repo.to_string(),
+            reviewers: node
+                .review_requests
+                .and_then(|rr| rr.edges)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|edge| edge.node?.requested_reviewer)
+                .filter_map(|reviewer| match reviewer {
+                    Reviewer::User(u) => Some(u.login),
+                    _ => None,
+                })
+                .collect(),
+            assignees: node
+                .assignees
+                .nodes
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|node| node.login)
+                .collect(),
+            is_draft: node.is_draft,
+            created_at: chrono::DateTime::parse_from_rfc3339(&node.created_at)?,
+            updated_at: chrono::DateTime::parse_from_rfc3339(&node.updated_at)?,
+        });
+    }
+    Ok(prs)
+}
+
+/// Builds an error describing an unsuccessful HTTP response, including the
+/// beginning of its body.
+fn http_error(repo: &str, status: StatusCode, body: &str) -> anyhow::Error {
+    const MAX_SNIPPET_LEN: usize = 200;
+
+    let body = body.trim();
+    let snippet = match body.char_indices().nth(MAX_SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    };
+    anyhow!("GitHub responded with {status} for {repo}: {snippet}")
+}
+
+/// Extracts the data from a GraphQL response, turning the errors GitHub reports
+/// in place of the data into an error.
+fn response_data<T>(repo: &str, body: Response<T>) -> Result<T> {
+    if let Some(data) = body.data {
+        return Ok(data);
+    }
+    let messages = body
+        .errors
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| e.message)
+        .collect::<Vec<_>>();
+    if messages.is_empty() {
+        Err(anyhow!("no data returned for {repo}"))
+    } else {
+        Err(anyhow!(
+            "GitHub returned errors for {repo}: {}",
+            messages.join("; ")
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Issue {
     pub title: String,
     pub number: i64,
     pub repo: String,
     pub assignees: Vec<String>,
+    pub labels: Vec<String>,
+    pub updated_at: chrono::DateTime<chrono::offset::FixedOffset>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct IssueMetadata {
-    pub author: String,
+    /// The login of the author, or `None` if their account was deleted.
+    pub author: Option<String>,
     pub labels: Vec<String>,
     pub assignees: Vec<String>,
     pub created_at: chrono::DateTime<chrono::offset::FixedOffset>,
     pub closed_at: Option<chrono::DateTime<chrono::offset::FixedOffset>>,
+    pub repo: String,
+    pub number: i64,
+    pub title: String,
+    pub reactions: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MergedPullRequest {
+    pub author: String,
+    pub repo: String,
+    pub merged_at: chrono::DateTime<chrono::offset::FixedOffset>,
+    pub comments: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Review {
+    pub author: String,
+    pub repo: String,
+    pub submitted_at: chrono::DateTime<chrono::offset::FixedOffset>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+pub struct IssueComment {
+    pub author: String,
+    pub repo: String,
+    pub created_at: chrono::DateTime<chrono::offset::FixedOffset>,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct PullRequest {
     pub title: String,
     pub number: i64,
     pub repo: String,
     pub reviewers: Vec<String>,
     pub assignees: Vec<String>,
+    pub is_draft: bool,
+    pub created_at: chrono::DateTime<chrono::offset::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::offset::FixedOffset>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    /// Answers one request with each of `responses`, given as a status code
+    /// and a JSON body, and returns the URL to send the requests to along
+    /// with a handle that yields the bodies of the requests received.
+    fn mock_server(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut request = vec![0; content_length];
+                reader.read_exact(&mut request).unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn mock_client(url: &str) -> Client {
+        let tokens = serde_json::from_str::<Tokens>(r#""secret""#).unwrap();
+        Client::with_base_url(&tokens, url)
+    }
+
+    const ASSIGNED_ISSUES: &str = r#"{
+        "data": {
+            "repository": {
+                "issues": {
+                    "nodes": [
+                        {
+                            "title": "Fix the build",
+                            "number": 12,
+                            "assignees": { "nodes": [{ "login": "alice" }] },
+                            "labels": { "nodes": [{ "name": "bug" }] },
+                            "updatedAt": "2024-01-30T00:00:00Z"
+                        }
+                    ]
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn assigned_issues_from_mock_server() {
+        let (url, server) = mock_server(vec![(200, ASSIGNED_ISSUES.to_string())]);
+
+        let issues = mock_client(&url)
+            .assigned_issues(&["petabi/pbmetric".to_string()])
+            .unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].title, "Fix the build");
+        assert_eq!(issues[0].repo, "petabi/pbmetric");
+        assert_eq!(issues[0].assignees, ["alice"]);
+        assert_eq!(issues[0].labels, ["bug"]);
+
+        let requests = server.join().unwrap();
+        let query: serde_json::Value = serde_json::from_str(&requests[0]).unwrap();
+        assert_eq!(query["variables"]["owner"], "petabi");
+        assert_eq!(query["variables"]["name"], "pbmetric");
+    }
+
+    #[test]
+    fn requests_go_through_proxy() {
+        let (url, server) = mock_server(vec![(200, ASSIGNED_ISSUES.to_string())]);
+        let proxy = ProxyConfig {
+            url: url.trim_end_matches("/graphql").to_string(),
+            no_proxy: Vec::new(),
+        };
+        let repos = ["petabi/pbmetric".to_string()];
+
+        // The API host does not resolve, so only the proxy can answer.
+        let issues = mock_client("http://api.github.invalid/graphql")
+            .with_proxy(&proxy)
+            .unwrap()
+            .assigned_issues(&repos)
+            .unwrap();
+        assert_eq!(issues[0].title, "Fix the build");
+        assert_eq!(server.join().unwrap().len(), 1);
+
+        let proxy = ProxyConfig {
+            no_proxy: vec!["api.github.invalid".to_string()],
+            ..proxy
+        };
+        assert!(mock_client("http://api.github.invalid/graphql")
+            .with_proxy(&proxy)
+            .unwrap()
+            .assigned_issues(&repos)
+            .is_err());
+    }
+
+    #[test]
+    fn cached_responses_are_reused_within_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let repos = ["pbmetric".to_string()];
+
+        let (url, server) = mock_server(vec![(200, ASSIGNED_ISSUES.to_string())]);
+        let client =
+            mock_client(&url).with_cache(dir.path().to_path_buf(), Duration::from_secs(600));
+        let issues = client.assigned_issues(&repos).unwrap();
+        assert_eq!(server.join().unwrap().len(), 1);
+        // The server is gone, so this would fail without the cache.
+        let cached = client.assigned_issues(&repos).unwrap();
+        assert_eq!(cached.len(), issues.len());
+        assert_eq!(cached[0].title, issues[0].title);
+
+        let client = mock_client(&url).with_cache(dir.path().to_path_buf(), Duration::ZERO);
+        assert!(client.assigned_issues(&repos).is_err());
+    }
+
+    #[test]
+    fn one_request_covers_repositories_of_an_owner() {
+        let (url, server) = mock_server(vec![(
+            200,
+            r#"{
+                "data": {
+                    "r0": { "pullRequests": { "nodes": [] } },
+                    "r1": {
+                        "pullRequests": {
+                            "nodes": [
+                                {
+                                    "title": "Add a feature",
+                                    "number": 3,
+                                    "isDraft": false,
+                                    "createdAt": "2024-01-29T00:00:00Z",
+                                    "updatedAt": "2024-01-30T00:00:00Z",
+                                    "reviewRequests": {
+                                        "edges": [
+                                            {
+                                                "node": {
+                                                    "requestedReviewer": {
+                                                        "__typename": "User",
+                                                        "login": "bob"
+                                                    }
+                                                }
+                                            }
+                                        ]
+                                    },
+                                    "assignees": { "nodes": [{ "login": "alice" }] }
+                                }
+                            ]
+                        }
+                    },
+                    "r2": {
+                        "pullRequests": {
+                            "nodes": [
+                                {
+                                    "title": "Fix a bug",
+                                    "number": 8,
+                                    "isDraft": true,
+                                    "createdAt": "2024-01-29T00:00:00Z",
+                                    "updatedAt": "2024-01-30T00:00:00Z",
+                                    "reviewRequests": null,
+                                    "assignees": { "nodes": [] }
+                                }
+                            ]
+                        }
+                    }
+                }
+            }"#
+            .to_string(),
+        )]);
+
+        let repos = ["aice-web", "petabi/pbmetric", "review"].map(String::from);
+        let prs = mock_client(&url).open_pull_requests(&repos).unwrap();
+        assert_eq!(prs.len(), 2);
+        assert_eq!(prs[0].repo, "petabi/pbmetric");
+        assert_eq!(prs[0].reviewers, ["bob"]);
+        assert_eq!(prs[0].assignees, ["alice"]);
+        assert!(!prs[0].is_draft);
+        assert_eq!(prs[1].repo, "review");
+        assert_eq!(prs[1].number, 8);
+        assert!(prs[1].is_draft);
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 1);
+        let query: serde_json::Value = serde_json::from_str(&requests[0]).unwrap();
+        assert_eq!(query["variables"]["name0"], "aice-web");
+        assert_eq!(query["variables"]["name1"], "pbmetric");
+        assert_eq!(query["variables"]["name2"], "review");
+        assert!(query["query"]
+            .as_str()
+            .unwrap()
+            .contains("fragment OpenPullRequestsOfRepository on Repository"));
+    }
+
+    #[test]
+    fn token_falls_back_to_environment_and_gh() {
+        let dir = tempfile::tempdir().unwrap();
+        let gh_config_dir = dir.path().to_str().unwrap().to_string();
+        let env = |vars: &[(&'static str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(k, v)| (*k, (*v).to_string()))
+                .collect::<HashMap<_, _>>();
+            move |name: &str| vars.get(name).cloned()
+        };
+
+        assert_eq!(fallback_token(env(&[])), None);
+        assert_eq!(
+            fallback_token(env(&[("GITHUB_TOKEN", "from-github-token")])),
+            Some("from-github-token".to_string())
+        );
+        assert_eq!(
+            fallback_token(env(&[
+                ("GH_TOKEN", "from-gh-token"),
+                ("GITHUB_TOKEN", "from-github-token")
+            ])),
+            Some("from-gh-token".to_string())
+        );
+
+        std::fs::write(
+            dir.path().join("hosts.yml"),
+            "ghe.example.com:\n    oauth_token: other\n\
+             github.com:\n    user: alice\n    oauth_token: gho_secret\n    git_protocol: https\n",
+        )
+        .unwrap();
+        assert_eq!(
+            fallback_token(env(&[("GH_CONFIG_DIR", &gh_config_dir)])),
+            Some("gho_secret".to_string())
+        );
+        assert_eq!(
+            fallback_token(env(&[
+                ("GITHUB_TOKEN", "from-github-token"),
+                ("GH_CONFIG_DIR", &gh_config_dir)
+            ])),
+            Some("from-github-token".to_string())
+        );
+    }
+
+    #[test]
+    fn repositories_are_batched_by_owner() {
+        let repos = ["a", "other/b", "petabi/c"].map(String::from);
+        assert_eq!(
+            batches(&repos),
+            [
+                ("petabi", vec![("a", "a"), ("c", "petabi/c")]),
+                ("other", vec![("b", "other/b")]),
+            ]
+        );
+
+        let repos = (0..=MAX_BATCH_LEN)
+            .map(|i| format!("repo{i}"))
+            .collect::<Vec<_>>();
+        let batches = batches(&repos);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].1.len(), MAX_BATCH_LEN);
+    }
+
+    #[test]
+    fn http_errors_from_mock_server() {
+        let (url, server) =
+            mock_server(vec![(401, r#"{"message": "Bad credentials"}"#.to_string())]);
+        let err = mock_client(&url)
+            .assigned_issues(&["pbmetric".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("401"), "{err}");
+        assert!(err.contains("Bad credentials"), "{err}");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn graphql_errors_are_reported() {
+        let body: Response<assigned_issues::ResponseData> = serde_json::from_str(
+            r#"{
+                "data": null,
+                "errors": [
+                    {
+                        "type": "NOT_FOUND",
+                        "path": ["repository"],
+                        "message": "Could not resolve to a Repository with the name 'petabi/nope'."
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let err = response_data("petabi/nope", body).unwrap_err().to_string();
+        assert!(err.contains("petabi/nope"));
+        assert!(err.contains("Could not resolve to a Repository"));
+    }
+
+    #[test]
+    fn reviews_are_attributed_to_their_authors() {
+        let body: Response<pull_request_reviews::ResponseData> = serde_json::from_str(
+            r#"{
+                "data": {
+                    "repository": {
+                        "pullRequests": {
+                            "nodes": [
+                                {
+                                    "updatedAt": "2024-01-30T00:00:00Z",
+                                    "reviews": {
+                                        "nodes": [
+                                            {
+                                                "submittedAt": "2024-01-29T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "bob-gh" }
+                                            },
+                                            {
+                                                "submittedAt": "2024-01-30T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "carol-gh" }
+                                            },
+                                            {
+                                                "submittedAt": null,
+                                                "author": { "__typename": "User", "login": "alice-gh" }
+                                            }
+                                        ]
+                                    }
+                                },
+                                {
+                                    "updatedAt": "2023-06-01T00:00:00Z",
+                                    "reviews": {
+                                        "nodes": [
+                                            {
+                                                "submittedAt": "2023-06-01T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "alice-gh" }
+                                            }
+                                        ]
+                                    }
+                                }
+                            ]
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let since = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        let data = response_data("petabi/pbmetric", body).unwrap();
+        let reviews = submitted_reviews("petabi/pbmetric", data, &since).unwrap();
+        let authors = reviews
+            .iter()
+            .map(|review| review.author.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(authors, ["bob-gh", "carol-gh"]);
+    }
+
+    #[test]
+    fn issue_comments_are_attributed_to_their_authors() {
+        let body: Response<issue_comments::ResponseData> = serde_json::from_str(
+            r#"{
+                "data": {
+                    "repository": {
+                        "issues": {
+                            "nodes": [
+                                {
+                                    "updatedAt": "2024-01-30T00:00:00Z",
+                                    "comments": {
+                                        "nodes": [
+                                            {
+                                                "createdAt": "2023-12-20T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "alice-gh" }
+                                            },
+                                            {
+                                                "createdAt": "2024-01-29T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "bob-gh" }
+                                            },
+                                            {
+                                                "createdAt": "2024-01-30T00:00:00Z",
+                                                "author": null
+                                            }
+                                        ]
+                                    }
+                                },
+                                {
+                                    "updatedAt": "2023-06-01T00:00:00Z",
+                                    "comments": {
+                                        "nodes": [
+                                            {
+                                                "createdAt": "2023-06-01T00:00:00Z",
+                                                "author": { "__typename": "User", "login": "carol-gh" }
+                                            }
+                                        ]
+                                    }
+                                }
+                            ]
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let since = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        let data = response_data("petabi/pbmetric", body).unwrap();
+        let comments = posted_comments("petabi/pbmetric", data, &since).unwrap();
+        let authors = comments
+            .iter()
+            .map(|comment| comment.author.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(authors, ["alice-gh", "bob-gh"]);
+    }
+
+    #[test]
+    fn http_errors_are_readable() {
+        let err = http_error(
+            "petabi/nope",
+            StatusCode::NOT_FOUND,
+            r#"{"message":"Not Found","documentation_url":"https://docs.github.com/graphql"}"#,
+        )
+        .to_string();
+        assert!(err.contains("404 Not Found"));
+        assert!(err.contains("petabi/nope"));
+        assert!(err.contains(r#""message":"Not Found""#));
+
+        let err = http_error("petabi/nope", StatusCode::BAD_GATEWAY, &"x".repeat(1000)).to_string();
+        assert!(err.len() < 300);
+        assert!(err.ends_with("..."));
+    }
+
+    #[test]
+    fn tokens_per_owner_and_default() {
+        let tokens: Tokens = toml::from_str::<HashMap<String, Tokens>>(
+            r#"
+            token = { default = "default-token", acme = "acme-token" }
+            "#,
+        )
+        .unwrap()
+        .remove("token")
+        .unwrap();
+        let client = Client::new(&tokens);
+        assert_eq!(client.token("acme").unwrap(), "acme-token");
+        assert_eq!(client.token("petabi").unwrap(), "default-token");
+
+        let tokens: Tokens = toml::from_str::<HashMap<String, Tokens>>(r#"token = "only""#)
+            .unwrap()
+            .remove("token")
+            .unwrap();
+        let client = Client::new(&tokens);
+        assert_eq!(client.token("petabi").unwrap(), "only");
+
+        let tokens = Tokens(HashMap::from([("acme".to_string(), "t".to_string())]));
+        let err = Client::new(&tokens).token("petabi").unwrap_err();
+        assert!(err.to_string().contains("petabi"));
+    }
+
+    #[test]
+    fn owners_without_token_are_reported_up_front() {
+        let repos = vec![
+            "acme/one".to_string(),
+            "widgets/two".to_string(),
+            "pbmetric".to_string(),
+            "widgets/three".to_string(),
+        ];
+        let tokens = Tokens(HashMap::from([
+            ("acme".to_string(), "t".to_string()),
+            ("petabi".to_string(), String::new()),
+        ]));
+        assert_eq!(tokens.owners_without_token(&repos), ["widgets", "petabi"]);
+        let err = Client::new(&tokens).check_tokens(&repos).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no GitHub token configured for widgets, petabi"
+        );
+
+        let tokens = Tokens::from("default-token".to_string());
+        assert!(tokens.owners_without_token(&repos).is_empty());
+        assert!(Client::new(&tokens).check_tokens(&repos).is_ok());
+    }
+
+    #[test]
+    fn bots_are_ignored_with_or_without_suffix() {
+        let ignore_logins = vec!["dependabot[bot]".to_string(), "renovate".to_string()];
+        assert!(is_ignored("dependabot[bot]", &ignore_logins));
+        assert!(is_ignored("dependabot", &ignore_logins));
+        assert!(is_ignored("renovate[bot]", &ignore_logins));
+        assert!(is_ignored("Renovate", &ignore_logins));
+        assert!(!is_ignored("alice", &ignore_logins));
+        assert!(!is_ignored("dependabot-fan", &ignore_logins));
+    }
+
+    #[test]
+    fn repo_without_owner_belongs_to_petabi() {
+        let slug = RepoSlug::from("pbmetric");
+        assert_eq!((slug.owner(), slug.name()), ("petabi", "pbmetric"));
+        let slug = RepoSlug::from("acme/widget");
+        assert_eq!((slug.owner(), slug.name()), ("acme", "widget"));
+        assert_eq!(slug.to_string(), "acme/widget");
+    }
+
+    #[test]
+    fn malformed_repo_slugs_are_rejected() {
+        assert_eq!(
+            RepoSlug::parse("acme/widget.rs").unwrap(),
+            RepoSlug::from("acme/widget.rs")
+        );
+        for repo in ["badslug", "a/b/c", "/b", "a/", "", "a.b/c", "a/b c"] {
+            assert_eq!(
+                RepoSlug::parse(repo).unwrap_err().to_string(),
+                format!("invalid repository \"{repo}\", expected \"owner/name\"")
+            );
+        }
+    }
 }
@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use serde::Deserialize;
+
+const API_URL: &str = "https://api.github.com";
+
+/// How long before it expires an installation token is replaced, so that it
+/// does not expire in the middle of a report.
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// A GitHub App installation to authenticate as, instead of with a personal
+/// access token.
+#[derive(Clone, Deserialize)]
+pub struct GithubAppConfig {
+    pub app_id: u64,
+    pub installation_id: u64,
+    /// The PEM file of a private key generated for the app.
+    pub private_key_path: PathBuf,
+}
+
+/// Mints installation tokens for a GitHub App, reusing each until it nears
+/// expiry.
+pub struct GithubApp {
+    app_id: u64,
+    installation_id: u64,
+    key: PKey<Private>,
+    url: String,
+    token: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+#[derive(Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl GithubApp {
+    /// Loads the private key of the app in `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the private key cannot be read or parsed.
+    pub fn new(config: &GithubAppConfig) -> Result<Self> {
+        let pem = fs::read(&config.private_key_path)
+            .with_context(|| format!("cannot read {}", config.private_key_path.display()))?;
+        let key = PKey::private_key_from_pem(&pem).with_context(|| {
+            format!(
+                "invalid private key in {}",
+                config.private_key_path.display()
+            )
+        })?;
+        Ok(Self::with_key(config, key, API_URL))
+    }
+
+    fn with_key(config: &GithubAppConfig, key: PKey<Private>, url: &str) -> Self {
+        Self {
+            app_id: config.app_id,
+            installation_id: config.installation_id,
+            key,
+            url: url.to_string(),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Returns an installation token, requesting a new one through `http` if
+    /// there is none or it is about to expire.
+    pub(crate) fn token(&self, http: &reqwest::blocking::Client) -> Result<String> {
+        let mut token = self
+            .token
+            .lock()
+            .map_err(|_| anyhow!("installation token lock poisoned"))?;
+        let now = Utc::now();
+        if let Some((token, expires_at)) = token.as_ref() {
+            if now + Duration::minutes(REFRESH_MARGIN_MINUTES) < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        debug!(
+            "requesting a token for installation {}",
+            self.installation_id
+        );
+        let res = http
+            .post(format!(
+                "{}/app/installations/{}/access_tokens",
+                self.url, self.installation_id
+            ))
+            .bearer_auth(self.jwt(now)?)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()?;
+        let status = res.status();
+        if !status.is_success() {
+            return Err(anyhow!(
+                "cannot get a token for installation {}: {status}: {}",
+                self.installation_id,
+                res.text().unwrap_or_default().trim()
+            ));
+        }
+        let installation: InstallationToken = res.json()?;
+        *token = Some((installation.token.clone(), installation.expires_at));
+        Ok(installation.token)
+    }
+
+    /// Creates a JSON Web Token identifying the app, signed with its private
+    /// key. It is backdated a minute to allow for clock drift and is valid
+    /// for ten minutes from then, the longest GitHub accepts.
+    fn jwt(&self, now: DateTime<Utc>) -> Result<String> {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"JWT"}"#);
+        let claims = serde_json::json!({
+            "iat": (now - Duration::seconds(60)).timestamp(),
+            "exp": (now + Duration::minutes(9)).timestamp(),
+            "iss": self.app_id.to_string(),
+        });
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let message = format!("{header}.{claims}");
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(message.as_bytes())?;
+        let signature = URL_SAFE_NO_PAD.encode(signer.sign_to_vec()?);
+        Ok(format!("{message}.{signature}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+
+    use super::*;
+
+    fn app() -> GithubApp {
+        let config = GithubAppConfig {
+            app_id: 1234,
+            installation_id: 5678,
+            private_key_path: PathBuf::new(),
+        };
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        GithubApp::with_key(&config, key, "http://127.0.0.1:9")
+    }
+
+    #[test]
+    fn jwt_is_signed_by_the_app() {
+        let app = app();
+        let now = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let jwt = app.jwt(now).unwrap();
+
+        let parts = jwt.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        let decode = |part: &str| -> serde_json::Value {
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+        };
+        assert_eq!(
+            decode(parts[0]),
+            serde_json::json!({ "alg": "RS256", "typ": "JWT" })
+        );
+        assert_eq!(
+            decode(parts[1]),
+            serde_json::json!({
+                "iat": now.timestamp() - 60,
+                "exp": now.timestamp() + 540,
+                "iss": "1234",
+            })
+        );
+
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &app.key).unwrap();
+        verifier
+            .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+            .unwrap();
+        assert!(verifier
+            .verify(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap())
+            .unwrap());
+    }
+
+    #[test]
+    fn token_is_reused_until_near_expiry() {
+        let app = app();
+        let http = reqwest::blocking::Client::new();
+        *app.token.lock().unwrap() = Some(("cached".to_string(), Utc::now() + Duration::hours(1)));
+        assert_eq!(app.token(&http).unwrap(), "cached");
+
+        // Nothing listens at the app's URL, so a refresh fails.
+        *app.token.lock().unwrap() =
+            Some(("cached".to_string(), Utc::now() + Duration::minutes(1)));
+        assert!(app.token(&http).is_err());
+    }
+}
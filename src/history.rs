@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::issue::IndividualStats;
+use crate::report::Report;
+
+/// The numbers of a report, kept to compare later reports with.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub since: DateTime<Utc>,
+    pub asof: DateTime<Utc>,
+    pub open_pull_requests: usize,
+    pub stale_pull_requests: usize,
+    pub stale_issues: usize,
+    pub issues_created: usize,
+    pub issues_completed: i64,
+    pub individual_stats: BTreeMap<String, IndividualStats>,
+}
+
+impl Snapshot {
+    #[must_use]
+    pub fn new(report: &Report) -> Self {
+        Self {
+            since: report.since,
+            asof: report.asof,
+            open_pull_requests: report.pull_requests.len(),
+            stale_pull_requests: report.stale_pull_requests.len(),
+            stale_issues: report.stale_issues.len(),
+            issues_created: report.weekly.created,
+            issues_completed: report.weekly.completed,
+            individual_stats: report.individual_stats.clone(),
+        }
+    }
+}
+
+/// Snapshots of past reports, stored in a database keyed by the end of
+/// their windows. The statistics of each user are kept as JSON, so that
+/// statistics added later need no change to the tables.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Opens the database, creating it and its tables if they do not exist.
+    fn open(&self) -> Result<Connection> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(&self.path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS snapshots (
+                asof INTEGER PRIMARY KEY,
+                since INTEGER NOT NULL,
+                open_pull_requests INTEGER NOT NULL,
+                stale_pull_requests INTEGER NOT NULL,
+                stale_issues INTEGER NOT NULL,
+                issues_created INTEGER NOT NULL,
+                issues_completed INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS individual_stats (
+                asof INTEGER NOT NULL REFERENCES snapshots (asof) ON DELETE CASCADE,
+                username TEXT NOT NULL,
+                stats TEXT NOT NULL,
+                PRIMARY KEY (asof, username)
+            );",
+        )?;
+        Ok(conn)
+    }
+
+    /// Stores `snapshot`, replacing any stored for the same `asof`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or written.
+    pub fn record(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let asof = snapshot.asof.timestamp_micros();
+        tx.execute("DELETE FROM individual_stats WHERE asof = ?1", [asof])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO snapshots VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                asof,
+                snapshot.since.timestamp_micros(),
+                snapshot.open_pull_requests,
+                snapshot.stale_pull_requests,
+                snapshot.stale_issues,
+                snapshot.issues_created,
+                snapshot.issues_completed,
+            ],
+        )?;
+        for (username, stats) in &snapshot.individual_stats {
+            tx.execute(
+                "INSERT INTO individual_stats VALUES (?1, ?2, ?3)",
+                params![asof, username, serde_json::to_string(stats)?],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the latest snapshot of a report as of before `asof`, or `None`
+    /// if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or read, or has
+    /// invalid statistics.
+    pub fn previous(&self, asof: &DateTime<Utc>) -> Result<Option<Snapshot>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let conn = self.open()?;
+        let Some(mut snapshot) = conn
+            .query_row(
+                "SELECT asof, since, open_pull_requests, stale_pull_requests, stale_issues,
+                    issues_created, issues_completed
+                FROM snapshots WHERE asof < ?1 ORDER BY asof DESC LIMIT 1",
+                [asof.timestamp_micros()],
+                |row| {
+                    Ok(Snapshot {
+                        asof: timestamp(row.get(0)?),
+                        since: timestamp(row.get(1)?),
+                        open_pull_requests: row.get(2)?,
+                        stale_pull_requests: row.get(3)?,
+                        stale_issues: row.get(4)?,
+                        issues_created: row.get(5)?,
+                        issues_completed: row.get(6)?,
+                        individual_stats: BTreeMap::new(),
+                    })
+                },
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let mut stmt =
+            conn.prepare("SELECT username, stats FROM individual_stats WHERE asof = ?1")?;
+        let rows = stmt.query_map([snapshot.asof.timestamp_micros()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (username, stats) = row?;
+            let stats = serde_json::from_str(&stats)
+                .map_err(|e| anyhow!("invalid statistics of {username}: {e}"))?;
+            snapshot.individual_stats.insert(username, stats);
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+/// Converts microseconds since the Unix epoch, as stored, back to a time.
+fn timestamp(micros: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(micros).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(asof: &str, lines: f64) -> Snapshot {
+        let asof = asof.parse::<DateTime<Utc>>().unwrap();
+        Snapshot {
+            since: asof - chrono::Duration::try_days(90).unwrap(),
+            asof,
+            open_pull_requests: 3,
+            stale_pull_requests: 1,
+            stale_issues: 2,
+            issues_created: 4,
+            issues_completed: 5,
+            individual_stats: BTreeMap::from([(
+                "alice".to_string(),
+                IndividualStats {
+                    issues_completed: 6.,
+                    lines_contributed: lines,
+                    ..IndividualStats::default()
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn recorded_snapshots_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("history").join("pbmetric.sqlite"));
+        let asof = "2024-02-08T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(history.previous(&asof).unwrap(), None);
+
+        history
+            .record(&snapshot("2024-01-25T00:00:00Z", 10.))
+            .unwrap();
+        history
+            .record(&snapshot("2024-02-01T00:00:00Z", 12.5))
+            .unwrap();
+        history
+            .record(&snapshot("2024-02-08T00:00:00Z", 20.))
+            .unwrap();
+        assert_eq!(
+            history.previous(&asof).unwrap(),
+            Some(snapshot("2024-02-01T00:00:00Z", 12.5))
+        );
+
+        let first = "2024-01-25T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(history.previous(&first).unwrap(), None);
+    }
+}
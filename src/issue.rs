@@ -1,36 +1,135 @@
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, HashMap};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 
-use crate::github::IssueMetadata;
+use crate::github::{is_ignored, IssueComment, IssueMetadata, MergedPullRequest, Review};
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct IndividualStats {
     pub bugs_reported: usize,
-    pub issues_completed: usize,
+    /// Closed issues, each split evenly among its assignees.
+    pub issues_completed: f64,
     pub issues_opened: usize,
     pub merged_merge_requests_opened: usize,
     pub merge_request_notes: u64,
-    pub lines_contributed: usize,
+    pub reviews_given: usize,
+    /// Comments on issues, such as for triage or support.
+    #[serde(default)]
+    pub issue_comments: usize,
+    pub lines_contributed: f64,
+    /// The part of `lines_contributed` in test code.
+    #[serde(default)]
+    pub test_lines_contributed: f64,
+    /// Lines deleted in the window, which `lines_contributed` cannot show.
+    #[serde(default)]
+    pub lines_removed: f64,
+    pub median_per_day: DailyMedians,
+    /// The statistics of each repository, keyed by "owner/name", if the
+    /// breakdown is enabled. Their medians are left out.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub per_repo: BTreeMap<String, IndividualStats>,
 }
 
+impl IndividualStats {
+    /// Returns the sum of the counts, lines included, as a rough measure of
+    /// how active a user was.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn activity(&self) -> f64 {
+        (self.bugs_reported
+            + self.issues_opened
+            + self.merged_merge_requests_opened
+            + self.reviews_given
+            + self.issue_comments) as f64
+            + self.issues_completed
+            + self.lines_contributed
+            + self.lines_removed
+    }
+
+    /// Adds the counts in `other` to these. Medians cannot be combined this
+    /// way and are left as they are.
+    fn add(&mut self, other: &IndividualStats) {
+        self.bugs_reported += other.bugs_reported;
+        self.issues_completed += other.issues_completed;
+        self.issues_opened += other.issues_opened;
+        self.merged_merge_requests_opened += other.merged_merge_requests_opened;
+        self.merge_request_notes += other.merge_request_notes;
+        self.reviews_given += other.reviews_given;
+        self.issue_comments += other.issue_comments;
+        self.lines_contributed += other.lines_contributed;
+        self.test_lines_contributed += other.test_lines_contributed;
+        self.lines_removed += other.lines_removed;
+    }
+}
+
+/// Adds `repo_stats`, the statistics of each user in `repo` alone, to the
+/// per-repository breakdown of the users in `individual_stats`.
+pub fn add_repo_stats(
+    individual_stats: &mut BTreeMap<String, IndividualStats>,
+    repo: &str,
+    repo_stats: &BTreeMap<String, IndividualStats>,
+) {
+    for (username, stats) in repo_stats {
+        if let Some(entry) = individual_stats.get_mut(username) {
+            entry
+                .per_repo
+                .entry(repo.to_string())
+                .or_default()
+                .add(stats);
+        }
+    }
+}
+
+/// The name under which the issues of deleted or otherwise unknown accounts
+/// are counted, which cannot be taken by a GitHub login.
+pub const UNKNOWN_AUTHORS: &str = "deleted/unknown users";
+
+/// Returns the login of the author of `issue`, or `UNKNOWN_AUTHORS` if the
+/// author is unknown.
+fn author_of(issue: &IssueMetadata) -> &str {
+    issue.author.as_deref().unwrap_or(UNKNOWN_AUTHORS)
+}
+
+/// The median number of each activity per day, which unlike the average is
+/// not skewed by a single busy day.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DailyMedians {
+    pub issues_completed: f64,
+    pub issues_opened: f64,
+    pub bugs_reported: f64,
+    pub merged_merge_requests_opened: f64,
+    pub reviews_given: f64,
+}
+
+/// Computes the statistics of each user between `since` and `asof`. Logins
+/// without a username in `account_map` are kept as they are, so that their
+/// activity still shows up.
+#[allow(clippy::cast_precision_loss)]
 #[allow(clippy::cast_sign_loss)]
+#[allow(clippy::too_many_arguments)]
 pub fn individual_stats(
     issues: &[IssueMetadata],
     pull_requests: &HashMap<String, (usize, i64)>,
+    reviews: &HashMap<String, usize>,
+    issue_comments: &HashMap<String, usize>,
     account_map: &HashMap<String, String>,
+    ignore_logins: &[String],
     since: &DateTime<Utc>,
     asof: &DateTime<Utc>,
 ) -> BTreeMap<String, IndividualStats> {
     let mut stats = BTreeMap::new();
     for issue in issues {
-        if *since < issue.created_at && issue.created_at < *asof {
-            let Some(author) = account_map.get(&issue.author) else {
-                continue;
-            };
-
+        if *since < issue.created_at
+            && issue.created_at < *asof
+            && !is_ignored(author_of(issue), ignore_logins)
+        {
+            let login = author_of(issue);
+            let author = account_map.get(login).map_or(login, String::as_str);
             let entry = stats
-                .entry(author.clone())
+                .entry(author.to_string())
                 .or_insert_with(IndividualStats::default);
             if issue.labels.contains(&"bug".to_string()) {
                 entry.bugs_reported += 1;
@@ -40,27 +139,989 @@ pub fn individual_stats(
         }
         if let Some(closed_at) = issue.closed_at {
             if *since < closed_at && closed_at < *asof {
-                for assignee in &issue.assignees {
-                    let Some(id) = account_map.get(assignee) else {
-                        continue;
-                    };
+                // Split as in `recent_issues_per_login`, so that the two agree.
+                let assignees = issue
+                    .assignees
+                    .iter()
+                    .filter(|assignee| !is_ignored(assignee, ignore_logins))
+                    .collect::<Vec<_>>();
+                let share = 1. / assignees.len() as f64;
+                for assignee in assignees {
+                    let id = account_map.get(assignee).unwrap_or(assignee);
                     let entry = stats
                         .entry(id.clone())
                         .or_insert_with(IndividualStats::default);
-                    entry.issues_completed += 1;
+                    entry.issues_completed += share;
                 }
             }
         }
     }
     for (login, count) in pull_requests {
-        let Some(author) = account_map.get(login) else {
+        if is_ignored(login, ignore_logins) {
             continue;
-        };
+        }
+        let author = account_map.get(login).unwrap_or(login);
         let entry = stats
-            .entry(author.to_string())
+            .entry(author.clone())
             .or_insert_with(IndividualStats::default);
         entry.merged_merge_requests_opened += count.0;
         entry.merge_request_notes += count.1 as u64;
     }
+    for (login, count) in reviews {
+        if is_ignored(login, ignore_logins) {
+            continue;
+        }
+        let reviewer = account_map.get(login).unwrap_or(login);
+        let entry = stats
+            .entry(reviewer.clone())
+            .or_insert_with(IndividualStats::default);
+        entry.reviews_given += count;
+    }
+    for (login, count) in issue_comments {
+        if is_ignored(login, ignore_logins) {
+            continue;
+        }
+        let commenter = account_map.get(login).unwrap_or(login);
+        let entry = stats
+            .entry(commenter.clone())
+            .or_insert_with(IndividualStats::default);
+        entry.issue_comments += count;
+    }
     stats
 }
+
+/// How long issues took from being opened to being closed, in days.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TimeToClose {
+    pub closed: usize,
+    pub mean_days: f64,
+    pub median_days: f64,
+}
+
+impl TimeToClose {
+    /// Summarizes `days`, returning `None` if there are no closed issues.
+    #[allow(clippy::cast_precision_loss)]
+    fn new(mut days: Vec<f64>) -> Option<Self> {
+        if days.is_empty() {
+            return None;
+        }
+        days.sort_by(f64::total_cmp);
+        Some(Self {
+            closed: days.len(),
+            mean_days: days.iter().sum::<f64>() / days.len() as f64,
+            median_days: median(&days),
+        })
+    }
+}
+
+/// Computes how long issues closed between `since` and `asof` took to close,
+/// overall and per assignee.
+#[allow(clippy::cast_precision_loss)]
+pub fn time_to_close(
+    issues: &[IssueMetadata],
+    account_map: &HashMap<String, String>,
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> (Option<TimeToClose>, BTreeMap<String, TimeToClose>) {
+    let mut overall = Vec::new();
+    let mut per_assignee = BTreeMap::<String, Vec<f64>>::new();
+    for issue in issues {
+        let Some(closed_at) = issue.closed_at else {
+            continue;
+        };
+        if closed_at <= *since || *asof <= closed_at {
+            continue;
+        }
+        let days = (closed_at - issue.created_at).num_seconds() as f64 / 86_400.;
+        overall.push(days);
+        for assignee in &issue.assignees {
+            if is_ignored(assignee, ignore_logins) {
+                continue;
+            }
+            let username = account_map.get(assignee).unwrap_or(assignee);
+            per_assignee.entry(username.clone()).or_default().push(days);
+        }
+    }
+    (
+        TimeToClose::new(overall),
+        per_assignee
+            .into_iter()
+            .filter_map(|(username, days)| Some((username, TimeToClose::new(days)?)))
+            .collect(),
+    )
+}
+
+/// The numbers of issues created and closed on a day.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DailyIssues {
+    pub date: NaiveDate,
+    pub created: usize,
+    pub closed: usize,
+}
+
+/// Counts the issues created and closed on each day of `timezone` between
+/// `since` and `asof`, in order, including the days with neither.
+pub fn daily_issues(
+    issues: &[IssueMetadata],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    timezone: Tz,
+) -> Vec<DailyIssues> {
+    let Ok(days) = u64::try_from(local_days(since, asof, timezone)) else {
+        return Vec::new();
+    };
+    let first = since.with_timezone(&timezone).date_naive();
+    let mut counts = (0..days)
+        .filter_map(|day| {
+            Some(DailyIssues {
+                date: first.checked_add_days(Days::new(day))?,
+                created: 0,
+                closed: 0,
+            })
+        })
+        .collect::<Vec<_>>();
+    let day_of = |at: DateTime<FixedOffset>| {
+        if at <= *since || *asof <= at {
+            return None;
+        }
+        usize::try_from(local_days(since, &at.with_timezone(&Utc), timezone)).ok()
+    };
+    for issue in issues {
+        if let Some(day) = day_of(issue.created_at).and_then(|day| counts.get_mut(day)) {
+            day.created += 1;
+        }
+        if let Some(day) = issue
+            .closed_at
+            .and_then(day_of)
+            .and_then(|day| counts.get_mut(day))
+        {
+            day.closed += 1;
+        }
+    }
+    counts
+}
+
+/// Sums the statistics of each team's members. A member of more than one team
+/// counts toward each of them.
+pub fn team_stats(
+    individual_stats: &BTreeMap<String, IndividualStats>,
+    teams: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, IndividualStats> {
+    teams
+        .iter()
+        .map(|(team, members)| {
+            let mut total = IndividualStats::default();
+            for stats in members.iter().filter_map(|m| individual_stats.get(m)) {
+                total.add(stats);
+            }
+            (team.clone(), total)
+        })
+        .collect()
+}
+
+/// Sums the statistics of everyone in `individual_stats`.
+pub fn total_stats(individual_stats: &BTreeMap<String, IndividualStats>) -> IndividualStats {
+    let mut total = IndividualStats::default();
+    for stats in individual_stats.values() {
+        total.add(stats);
+    }
+    total
+}
+
+const ISSUES_COMPLETED: usize = 0;
+const ISSUES_OPENED: usize = 1;
+const BUGS_REPORTED: usize = 2;
+const MERGED_PULL_REQUESTS: usize = 3;
+const REVIEWS_GIVEN: usize = 4;
+
+/// Returns the number of days from `since` to `asof`, counting the midnights
+/// in `timezone` between them.
+#[must_use]
+pub fn local_days(since: &DateTime<Utc>, asof: &DateTime<Utc>, timezone: Tz) -> i64 {
+    (asof.with_timezone(&timezone).date_naive() - since.with_timezone(&timezone).date_naive())
+        .num_days()
+}
+
+/// Computes, per username, the median daily counts of activities between
+/// `since` and `asof` by binning them into the days of `timezone`.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::too_many_arguments)]
+pub fn median_daily_activity(
+    issues: &[IssueMetadata],
+    pull_requests: &[MergedPullRequest],
+    reviews: &[Review],
+    account_map: &HashMap<String, String>,
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    timezone: Tz,
+) -> BTreeMap<String, DailyMedians> {
+    let Ok(days) = usize::try_from(local_days(since, asof, timezone)) else {
+        return BTreeMap::new();
+    };
+    if days == 0 {
+        return BTreeMap::new();
+    }
+    let mut buckets = BTreeMap::<String, [Vec<f64>; 5]>::new();
+    let mut record = |login: &str, activity: usize, at: DateTime<FixedOffset>, count: f64| {
+        if at <= *since || *asof <= at || is_ignored(login, ignore_logins) {
+            return;
+        }
+        let username = account_map.get(login).map_or(login, String::as_str);
+        let Ok(day) = usize::try_from(local_days(since, &at.with_timezone(&Utc), timezone)) else {
+            return;
+        };
+        let counts = buckets
+            .entry(username.to_string())
+            .or_insert_with(|| std::array::from_fn(|_| vec![0.; days]));
+        if let Some(total) = counts[activity].get_mut(day) {
+            *total += count;
+        }
+    };
+    for issue in issues {
+        if issue.labels.iter().any(|label| label == "bug") {
+            record(author_of(issue), BUGS_REPORTED, issue.created_at, 1.);
+        } else {
+            record(author_of(issue), ISSUES_OPENED, issue.created_at, 1.);
+        }
+        if let Some(closed_at) = issue.closed_at {
+            // Split as in `individual_stats`, so that the two agree.
+            let assignees = issue
+                .assignees
+                .iter()
+                .filter(|assignee| !is_ignored(assignee, ignore_logins))
+                .collect::<Vec<_>>();
+            let share = 1. / assignees.len() as f64;
+            for assignee in assignees {
+                record(assignee, ISSUES_COMPLETED, closed_at, share);
+            }
+        }
+    }
+    for pr in pull_requests {
+        record(&pr.author, MERGED_PULL_REQUESTS, pr.merged_at, 1.);
+    }
+    for review in reviews {
+        record(&review.author, REVIEWS_GIVEN, review.submitted_at, 1.);
+    }
+
+    buckets
+        .into_iter()
+        .map(|(username, mut counts)| {
+            let mut daily_median = |activity: usize| {
+                let counts = &mut counts[activity];
+                counts.sort_unstable_by(f64::total_cmp);
+                median(counts)
+            };
+            let medians = DailyMedians {
+                issues_completed: daily_median(ISSUES_COMPLETED),
+                issues_opened: daily_median(ISSUES_OPENED),
+                bugs_reported: daily_median(BUGS_REPORTED),
+                merged_merge_requests_opened: daily_median(MERGED_PULL_REQUESTS),
+                reviews_given: daily_median(REVIEWS_GIVEN),
+            };
+            (username, medians)
+        })
+        .collect()
+}
+
+/// Returns the median of `sorted`, which must not be empty.
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Counts, per label, the issues opened since `recent_since` by those not in
+/// `ignore_logins`, in descending order. An issue counts toward each of its
+/// labels, or toward "unlabeled" if it has none.
+pub fn recent_issues_per_label(
+    issues: &[IssueMetadata],
+    ignore_logins: &[String],
+    recent_since: &DateTime<Utc>,
+) -> Vec<(String, usize)> {
+    let mut counter = BTreeMap::new();
+    for issue in issues {
+        if *recent_since >= issue.created_at || is_ignored(author_of(issue), ignore_logins) {
+            continue;
+        }
+        if issue.labels.is_empty() {
+            *counter.entry("unlabeled").or_insert(0) += 1;
+        }
+        for label in &issue.labels {
+            *counter.entry(label.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut counts = counter
+        .into_iter()
+        .map(|(label, count)| (label.to_string(), count))
+        .collect::<Vec<_>>();
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
+
+/// An issue with the number of reactions to it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ReactedIssue {
+    pub repo: String,
+    pub number: i64,
+    pub title: String,
+    pub reactions: i64,
+}
+
+/// Returns up to `limit` issues created since `recent_since` with the most
+/// reactions, in descending order. Issues without reactions are left out.
+pub fn most_reacted_issues(
+    issues: &[IssueMetadata],
+    ignore_logins: &[String],
+    recent_since: &DateTime<Utc>,
+    limit: usize,
+) -> Vec<ReactedIssue> {
+    let mut reacted = issues
+        .iter()
+        .filter(|issue| {
+            *recent_since < issue.created_at
+                && issue.reactions > 0
+                && !is_ignored(author_of(issue), ignore_logins)
+        })
+        .collect::<Vec<_>>();
+    reacted.sort_by_key(|issue| Reverse(issue.reactions));
+    reacted
+        .into_iter()
+        .take(limit)
+        .map(|issue| ReactedIssue {
+            repo: issue.repo.clone(),
+            number: issue.number,
+            title: issue.title.clone(),
+            reactions: issue.reactions,
+        })
+        .collect()
+}
+
+/// Counts, per login, the issues opened (non-bug and bug) and completed since
+/// `since`, along with those opened and completed since `recent_since`.
+///
+/// The tuple holds non-bug issues opened, bugs reported, issues completed,
+/// issues opened recently, and issues completed recently. Credit for a
+/// completed issue is split evenly among its assignees.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::type_complexity)]
+pub fn recent_issues_per_login(
+    issues: &[IssueMetadata],
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    recent_since: &DateTime<Utc>,
+) -> HashMap<String, (usize, usize, f32, usize, f32)> {
+    let mut counter = HashMap::new();
+    for issue in issues {
+        if *since <= issue.created_at && !is_ignored(author_of(issue), ignore_logins) {
+            let stat = counter
+                .entry(author_of(issue).to_string())
+                .or_insert((0, 0, 0.0, 0, 0.0));
+            if issue.labels.iter().any(|label| label == "bug") {
+                stat.1 += 1;
+            } else {
+                stat.0 += 1;
+            }
+            if *recent_since < issue.created_at {
+                stat.3 += 1;
+            }
+        }
+        if let Some(closed_at) = issue.closed_at {
+            let assignees = issue
+                .assignees
+                .iter()
+                .filter(|assignee| !is_ignored(assignee, ignore_logins))
+                .collect::<Vec<_>>();
+            let total_assignees = assignees.len() as f32;
+            for assignee in assignees {
+                let stat = counter
+                    .entry(assignee.clone())
+                    .or_insert((0, 0, 0.0, 0, 0.0));
+                stat.2 += 1.0 / total_assignees;
+                if *recent_since < closed_at {
+                    stat.4 += 1.0 / total_assignees;
+                }
+            }
+        }
+    }
+    counter
+}
+
+/// Counts, per author, the pull requests merged between `since` and `asof`
+/// and the comments on them.
+pub fn merged_pull_requests_per_login(
+    pull_requests: &[MergedPullRequest],
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> HashMap<String, (usize, i64)> {
+    let mut counter = HashMap::new();
+    for pr in pull_requests {
+        if pr.merged_at < *since || *asof < pr.merged_at || is_ignored(&pr.author, ignore_logins) {
+            continue;
+        }
+        let count = counter.entry(pr.author.clone()).or_insert((0, 0));
+        count.0 += 1;
+        count.1 += pr.comments;
+    }
+    counter
+}
+
+/// Counts, per reviewer, the reviews submitted between `since` and `asof`.
+pub fn reviews_per_login(
+    reviews: &[Review],
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> HashMap<String, usize> {
+    let mut counter = HashMap::new();
+    for review in reviews {
+        if review.submitted_at < *since
+            || *asof < review.submitted_at
+            || is_ignored(&review.author, ignore_logins)
+        {
+            continue;
+        }
+        *counter.entry(review.author.clone()).or_insert(0) += 1;
+    }
+    counter
+}
+
+/// Counts, per commenter, the issue comments posted between `since` and
+/// `asof`.
+pub fn issue_comments_per_login(
+    comments: &[IssueComment],
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> HashMap<String, usize> {
+    let mut counter = HashMap::new();
+    for comment in comments {
+        if comment.created_at < *since
+            || *asof < comment.created_at
+            || is_ignored(&comment.author, ignore_logins)
+        {
+            continue;
+        }
+        *counter.entry(comment.author.clone()).or_insert(0) += 1;
+    }
+    counter
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn issue(author: &str, labels: &[&str], created_at: &str) -> IssueMetadata {
+        IssueMetadata {
+            author: Some(author.to_string()),
+            labels: labels.iter().map(ToString::to_string).collect(),
+            assignees: Vec::new(),
+            created_at: DateTime::parse_from_rfc3339(created_at).unwrap(),
+            closed_at: None,
+            repo: "pbmetric".to_string(),
+            number: 1,
+            title: String::new(),
+            reactions: 0,
+        }
+    }
+
+    #[test]
+    fn labeled_non_bug_issue_is_counted_as_opened() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(90).unwrap();
+        let recent_since = asof - Duration::try_weeks(1).unwrap();
+        let issues = vec![
+            issue("alice", &["enhancement"], "2024-01-29T00:00:00Z"),
+            issue("alice", &["bug", "urgent"], "2024-01-10T00:00:00Z"),
+            issue("alice", &[], "2024-01-05T00:00:00Z"),
+        ];
+
+        let counter = recent_issues_per_login(&issues, &[], &since, &recent_since);
+        let stat = counter["alice"];
+        assert_eq!(stat.0, 2);
+        assert_eq!(stat.1, 1);
+        assert_eq!(stat.3, 1);
+    }
+
+    #[test]
+    fn issues_are_binned_into_local_days() {
+        let since = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let asof = since + Duration::try_days(3).unwrap();
+        let issues = vec![
+            issue("alice", &[], "2024-01-01T10:00:00Z"),
+            IssueMetadata {
+                closed_at: Some(DateTime::parse_from_rfc3339("2024-01-03T20:00:00Z").unwrap()),
+                ..issue("bob", &[], "2023-12-20T00:00:00Z")
+            },
+        ];
+
+        let days = daily_issues(&issues, &since, &asof, Tz::UTC);
+        assert_eq!(
+            days.iter()
+                .map(|day| (day.date.to_string(), day.created, day.closed))
+                .collect::<Vec<_>>(),
+            [
+                ("2024-01-01".to_string(), 1, 0),
+                ("2024-01-02".to_string(), 0, 0),
+                ("2024-01-03".to_string(), 0, 1),
+            ]
+        );
+
+        // 20:00 UTC on January 3 is already January 4 in Seoul.
+        let days = daily_issues(&issues, &since, &asof, Tz::Asia__Seoul);
+        assert_eq!(days.len(), 3);
+        assert_eq!(days.iter().map(|day| day.closed).sum::<usize>(), 0);
+    }
+
+    #[test]
+    fn issues_of_deleted_accounts_are_counted_apart() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(90).unwrap();
+        let issues = vec![
+            IssueMetadata {
+                author: None,
+                ..issue("", &["bug"], "2024-01-10T00:00:00Z")
+            },
+            IssueMetadata {
+                author: None,
+                ..issue("", &[], "2024-01-11T00:00:00Z")
+            },
+            issue("unknown", &[], "2024-01-12T00:00:00Z"),
+        ];
+
+        let stats = individual_stats(
+            &issues,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &[],
+            &since,
+            &asof,
+        );
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[UNKNOWN_AUTHORS].bugs_reported, 1);
+        assert_eq!(stats[UNKNOWN_AUTHORS].issues_opened, 1);
+        assert_eq!(stats["unknown"].issues_opened, 1);
+
+        let counter = recent_issues_per_login(&issues, &[], &since, &since);
+        assert_eq!(counter[UNKNOWN_AUTHORS].1, 1);
+        assert_eq!(counter["unknown"].0, 1);
+    }
+
+    #[test]
+    fn recent_issues_are_counted_per_label() {
+        let recent_since = DateTime::parse_from_rfc3339("2024-01-24T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let issues = vec![
+            issue("alice", &["bug"], "2024-01-29T00:00:00Z"),
+            issue("bob", &["bug", "urgent"], "2024-01-28T00:00:00Z"),
+            issue("bob", &["enhancement"], "2024-01-27T00:00:00Z"),
+            issue("carol", &[], "2024-01-26T00:00:00Z"),
+            issue("alice", &["chore"], "2024-01-10T00:00:00Z"),
+            issue("dependabot[bot]", &["chore"], "2024-01-29T00:00:00Z"),
+        ];
+
+        let counts =
+            recent_issues_per_label(&issues, &["dependabot[bot]".to_string()], &recent_since);
+        assert_eq!(
+            counts,
+            [
+                ("bug".to_string(), 2),
+                ("enhancement".to_string(), 1),
+                ("unlabeled".to_string(), 1),
+                ("urgent".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn issues_are_ranked_by_reactions() {
+        let recent_since = DateTime::parse_from_rfc3339("2024-01-24T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let reacted = |number: i64, reactions: i64, created_at: &str| IssueMetadata {
+            number,
+            reactions,
+            ..issue("alice", &[], created_at)
+        };
+        let issues = [
+            reacted(1, 2, "2024-01-25T00:00:00Z"),
+            reacted(2, 0, "2024-01-25T00:00:00Z"),
+            reacted(3, 9, "2024-01-10T00:00:00Z"),
+            reacted(4, 5, "2024-01-26T00:00:00Z"),
+            reacted(5, 1, "2024-01-27T00:00:00Z"),
+            reacted(6, 2, "2024-01-28T00:00:00Z"),
+        ];
+
+        let ranked = most_reacted_issues(&issues, &[], &recent_since, 3)
+            .into_iter()
+            .map(|issue| (issue.number, issue.reactions))
+            .collect::<Vec<_>>();
+        assert_eq!(ranked, [(4, 5), (1, 2), (6, 2)]);
+
+        assert!(most_reacted_issues(&issues[1..3], &[], &recent_since, 3).is_empty());
+    }
+
+    #[test]
+    fn bot_pull_requests_are_not_counted() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(90).unwrap();
+        let pr = |author: &str| MergedPullRequest {
+            author: author.to_string(),
+            repo: "pbmetric".to_string(),
+            merged_at: DateTime::parse_from_rfc3339("2024-01-20T00:00:00Z").unwrap(),
+            comments: 2,
+        };
+        let ignore_logins = vec!["dependabot[bot]".to_string()];
+        let pull_requests = merged_pull_requests_per_login(
+            &[pr("alice-gh"), pr("dependabot")],
+            &ignore_logins,
+            &since,
+            &asof,
+        );
+        assert_eq!(pull_requests.len(), 1);
+        assert_eq!(pull_requests["alice-gh"], (1, 2));
+
+        let account_map = HashMap::from([
+            ("alice-gh".to_string(), "alice".to_string()),
+            ("dependabot".to_string(), "dependabot".to_string()),
+        ]);
+        let stats = individual_stats(
+            &[issue("dependabot", &[], "2024-01-20T00:00:00Z")],
+            &pull_requests,
+            &HashMap::new(),
+            &HashMap::new(),
+            &account_map,
+            &ignore_logins,
+            &since,
+            &asof,
+        );
+        assert!(!stats.contains_key("dependabot"));
+        assert_eq!(stats["alice"].merged_merge_requests_opened, 1);
+    }
+
+    #[test]
+    fn pull_requests_merged_outside_window_are_not_counted() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let pr = |merged_at: &str| MergedPullRequest {
+            author: "alice-gh".to_string(),
+            repo: "pbmetric".to_string(),
+            merged_at: DateTime::parse_from_rfc3339(merged_at).unwrap(),
+            comments: 1,
+        };
+        let pull_requests = [
+            pr("2023-12-20T00:00:00Z"),
+            pr("2024-01-15T00:00:00Z"),
+            pr("2024-02-05T00:00:00Z"),
+        ];
+        let counter = merged_pull_requests_per_login(&pull_requests, &[], &since, &asof);
+        assert_eq!(counter["alice-gh"], (1, 1));
+    }
+
+    #[test]
+    fn reviews_are_counted_per_reviewer() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let review = |author: &str, submitted_at: &str| Review {
+            author: author.to_string(),
+            repo: "pbmetric".to_string(),
+            submitted_at: DateTime::parse_from_rfc3339(submitted_at).unwrap(),
+        };
+        let reviews = [
+            review("bob-gh", "2024-01-10T00:00:00Z"),
+            review("bob-gh", "2024-01-20T00:00:00Z"),
+            review("carol-gh", "2024-01-20T00:00:00Z"),
+            review("carol-gh", "2024-02-01T00:00:00Z"),
+        ];
+        let counter = reviews_per_login(&reviews, &[], &since, &asof);
+        assert_eq!(counter["bob-gh"], 2);
+        assert_eq!(counter["carol-gh"], 1);
+
+        let account_map = HashMap::from([("bob-gh".to_string(), "bob".to_string())]);
+        let stats = individual_stats(
+            &[],
+            &HashMap::new(),
+            &counter,
+            &HashMap::new(),
+            &account_map,
+            &[],
+            &since,
+            &asof,
+        );
+        assert_eq!(stats["bob"].reviews_given, 2);
+        assert_eq!(stats["carol-gh"].reviews_given, 1);
+    }
+
+    #[test]
+    fn issue_comments_are_counted_per_commenter() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let comment = |author: &str, created_at: &str| IssueComment {
+            author: author.to_string(),
+            repo: "pbmetric".to_string(),
+            created_at: DateTime::parse_from_rfc3339(created_at).unwrap(),
+        };
+        let comments = [
+            comment("bob-gh", "2023-12-20T00:00:00Z"),
+            comment("bob-gh", "2024-01-20T00:00:00Z"),
+            comment("carol-gh", "2024-01-20T00:00:00Z"),
+            comment("carol-gh", "2024-01-21T00:00:00Z"),
+            comment("github-actions[bot]", "2024-01-21T00:00:00Z"),
+        ];
+        let counter = issue_comments_per_login(
+            &comments,
+            &["github-actions[bot]".to_string()],
+            &since,
+            &asof,
+        );
+        assert_eq!(counter.len(), 2);
+        assert_eq!(counter["bob-gh"], 1);
+        assert_eq!(counter["carol-gh"], 2);
+
+        let account_map = HashMap::from([("bob-gh".to_string(), "bob".to_string())]);
+        let stats = individual_stats(
+            &[],
+            &HashMap::new(),
+            &HashMap::new(),
+            &counter,
+            &account_map,
+            &[],
+            &since,
+            &asof,
+        );
+        assert_eq!(stats["bob"].issue_comments, 1);
+        assert_eq!(stats["carol-gh"].issue_comments, 2);
+    }
+
+    #[test]
+    fn time_to_close_overall_and_per_assignee() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let closed = |assignees: &[&str], created_at: &str, closed_at: &str| IssueMetadata {
+            assignees: assignees.iter().map(ToString::to_string).collect(),
+            closed_at: Some(DateTime::parse_from_rfc3339(closed_at).unwrap()),
+            ..issue("alice-gh", &[], created_at)
+        };
+        let issues = vec![
+            closed(
+                &["alice-gh"],
+                "2024-01-10T00:00:00Z",
+                "2024-01-11T00:00:00Z",
+            ),
+            closed(
+                &["alice-gh", "bob-gh"],
+                "2024-01-10T00:00:00Z",
+                "2024-01-13T00:00:00Z",
+            ),
+            closed(&["bob-gh"], "2024-01-01T00:00:00Z", "2024-01-21T12:00:00Z"),
+            closed(&["bob-gh"], "2023-11-01T00:00:00Z", "2023-12-01T00:00:00Z"),
+            issue("bob-gh", &[], "2024-01-15T00:00:00Z"),
+        ];
+        let account_map = HashMap::from([("alice-gh".to_string(), "alice".to_string())]);
+
+        let (overall, per_assignee) = time_to_close(&issues, &account_map, &[], &since, &asof);
+        let overall = overall.unwrap();
+        assert_eq!(overall.closed, 3);
+        assert!((overall.mean_days - 8.166).abs() < 0.001);
+        assert!((overall.median_days - 3.).abs() < f64::EPSILON);
+        assert_eq!(per_assignee["alice"].closed, 2);
+        assert!((per_assignee["alice"].median_days - 2.).abs() < f64::EPSILON);
+        assert!((per_assignee["bob-gh"].median_days - 11.75).abs() < f64::EPSILON);
+
+        let (overall, per_assignee) = time_to_close(&issues[4..], &account_map, &[], &since, &asof);
+        assert!(overall.is_none());
+        assert!(per_assignee.is_empty());
+    }
+
+    #[test]
+    fn busy_day_inflates_mean_but_not_median() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(5).unwrap();
+        let closed_on = |closed_at: &str| IssueMetadata {
+            assignees: vec!["alice-gh".to_string()],
+            closed_at: Some(DateTime::parse_from_rfc3339(closed_at).unwrap()),
+            ..issue("bob-gh", &[], "2024-01-01T00:00:00Z")
+        };
+        let mut issues = (0..10)
+            .map(|_| closed_on("2024-01-26T12:00:00Z"))
+            .collect::<Vec<_>>();
+        issues.push(closed_on("2024-01-27T12:00:00Z"));
+        issues.push(closed_on("2024-01-28T12:00:00Z"));
+        issues.push(closed_on("2024-01-29T12:00:00Z"));
+        let account_map = HashMap::from([("alice-gh".to_string(), "alice".to_string())]);
+
+        let stats = individual_stats(
+            &issues,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &account_map,
+            &[],
+            &since,
+            &asof,
+        );
+        assert!((stats["alice"].issues_completed - 13.).abs() < f64::EPSILON);
+
+        let medians =
+            median_daily_activity(&issues, &[], &[], &account_map, &[], &since, &asof, Tz::UTC);
+        let alice = &medians["alice"];
+        assert!((alice.issues_completed - 1.).abs() < f64::EPSILON);
+        assert!(alice.issues_opened.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn completed_issue_is_split_among_assignees() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let issues = [IssueMetadata {
+            assignees: vec![
+                "alice-gh".to_string(),
+                "bob-gh".to_string(),
+                "dependabot[bot]".to_string(),
+            ],
+            closed_at: Some(DateTime::parse_from_rfc3339("2024-01-20T00:00:00Z").unwrap()),
+            ..issue("carol-gh", &[], "2024-01-10T00:00:00Z")
+        }];
+        let account_map = HashMap::from([("alice-gh".to_string(), "alice".to_string())]);
+        let ignore_logins = ["dependabot[bot]".to_string()];
+
+        let stats = individual_stats(
+            &issues,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &account_map,
+            &ignore_logins,
+            &since,
+            &asof,
+        );
+        assert!((stats["alice"].issues_completed - 0.5).abs() < f64::EPSILON);
+        assert!((stats["bob-gh"].issues_completed - 0.5).abs() < f64::EPSILON);
+        assert!(!stats.contains_key("dependabot[bot]"));
+
+        let recent = recent_issues_per_login(&issues, &ignore_logins, &since, &since);
+        assert!((recent["alice-gh"].2 - 0.5).abs() < f32::EPSILON);
+
+        // Half an issue on one of two days.
+        let since = DateTime::parse_from_rfc3339("2024-01-19T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let asof = since + Duration::try_days(2).unwrap();
+        let medians = median_daily_activity(
+            &issues,
+            &[],
+            &[],
+            &account_map,
+            &ignore_logins,
+            &since,
+            &asof,
+            Tz::UTC,
+        );
+        assert!((medians["alice"].issues_completed - 0.25).abs() < f64::EPSILON);
+        assert!((medians["bob-gh"].issues_completed - 0.25).abs() < f64::EPSILON);
+        assert!(!medians.contains_key("dependabot[bot]"));
+    }
+
+    #[test]
+    fn team_totals_are_sums_of_members() {
+        let stats = BTreeMap::from([
+            (
+                "alice".to_string(),
+                IndividualStats {
+                    issues_completed: 3.,
+                    merged_merge_requests_opened: 2,
+                    merge_request_notes: 5,
+                    lines_contributed: 100.,
+                    ..IndividualStats::default()
+                },
+            ),
+            (
+                "bob".to_string(),
+                IndividualStats {
+                    issues_completed: 1.,
+                    bugs_reported: 2,
+                    reviews_given: 4,
+                    lines_contributed: 20.5,
+                    ..IndividualStats::default()
+                },
+            ),
+        ]);
+        let teams = BTreeMap::from([
+            (
+                "core".to_string(),
+                vec!["alice".to_string(), "bob".to_string()],
+            ),
+            (
+                "web".to_string(),
+                vec!["bob".to_string(), "nobody".to_string()],
+            ),
+        ]);
+
+        let totals = team_stats(&stats, &teams);
+        let core = &totals["core"];
+        assert!((core.issues_completed - 4.).abs() < f64::EPSILON);
+        assert_eq!(core.bugs_reported, 2);
+        assert_eq!(core.merged_merge_requests_opened, 2);
+        assert_eq!(core.merge_request_notes, 5);
+        assert_eq!(core.reviews_given, 4);
+        assert!((core.lines_contributed - 120.5).abs() < f64::EPSILON);
+        assert!((totals["web"].issues_completed - 1.).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn unmapped_authors_are_kept_under_their_login() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let issues = [
+            issue("alice-gh", &[], "2024-01-20T00:00:00Z"),
+            issue("stranger", &["bug"], "2024-01-21T00:00:00Z"),
+        ];
+        let account_map = HashMap::from([("alice-gh".to_string(), "alice".to_string())]);
+
+        let stats = individual_stats(
+            &issues,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &account_map,
+            &[],
+            &since,
+            &asof,
+        );
+        assert_eq!(stats["alice"].issues_opened, 1);
+        assert_eq!(stats["stranger"].bugs_reported, 1);
+        assert!(!stats.contains_key("alice-gh"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Collects metrics from git repositories and GitHub and turns them into a
+//! report on the progress of a team.
+//!
+//! The `pbmetric` command is a thin wrapper around this library, which can
+//! also create reports from within another program. [`agenda`] collects
+//! everything and computes a [`Report`]; [`Report::new`] computes one from
+//! data fetched separately, such as with a [`Client`]:
+//!
+//! ```
+//! use std::collections::{BTreeMap, HashMap};
+//!
+//! use chrono::{DateTime, Utc};
+//! use pbmetric::{Client, GithubData, MergedPullRequest, Report, ReportConfig, Tokens};
+//!
+//! // Fetching from GitHub would look like this:
+//! fn fetch(repos: &[String], since: &DateTime<Utc>) -> anyhow::Result<GithubData> {
+//!     let client = Client::new(&Tokens::default());
+//!     GithubData::fetch(&client, repos, since)
+//! }
+//!
+//! // Here, a pull request merged by alice stands in for the fetched data.
+//! let merged_at = DateTime::parse_from_rfc3339("2024-01-15T00:00:00Z").unwrap();
+//! let data = GithubData {
+//!     open_pull_requests: Vec::new(),
+//!     assigned_issues: Vec::new(),
+//!     issue_metadata: Vec::new(),
+//!     merged_pull_requests: vec![MergedPullRequest {
+//!         author: "alice".to_string(),
+//!         repo: "pbmetric".to_string(),
+//!         merged_at,
+//!         comments: 3,
+//!     }],
+//!     reviews: Vec::new(),
+//!     issue_comments: Vec::new(),
+//! };
+//!
+//! let asof = "2024-02-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+//! let since = "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+//! let report = Report::new(
+//!     &ReportConfig::default(),
+//!     data,
+//!     HashMap::new(),
+//!     &HashMap::new(),
+//!     &BTreeMap::new(),
+//!     &since,
+//!     &asof,
+//! );
+//! let alice = &report.individual_stats["alice"];
+//! assert_eq!(alice.merged_merge_requests_opened, 1);
+//! assert_eq!(alice.merge_request_notes, 3);
+//! ```
+
+mod cache;
+mod chart;
+mod csv;
+mod git;
+mod github;
+mod github_app;
+mod history;
+mod issue;
+mod prometheus;
+mod proxy;
+mod render;
+mod report;
+mod slack;
+
+pub use crate::csv::write_csv;
+pub use crate::git::{
+    update_all, use_git, BlameOptions, CommitDate, LineCount, RecencyWeight, Repo, Tag,
+};
+pub use crate::github::{
+    fallback_token, Client, Issue, IssueComment, IssueMetadata, MergedPullRequest, PullRequest,
+    RepoSlug, Review, Tokens,
+};
+pub use crate::github_app::{GithubApp, GithubAppConfig};
+pub use crate::history::{History, Snapshot};
+pub use crate::issue::{
+    DailyIssues, DailyMedians, IndividualStats, ReactedIssue, TimeToClose, UNKNOWN_AUTHORS,
+};
+pub use crate::proxy::ProxyConfig;
+pub use crate::render::Format;
+pub use crate::report::{
+    agenda, exclude_patterns, last_release, report_since, select_repos, write_report, GithubConfig,
+    GithubData, Leaderboard, LeaderboardMetric, RepoLink, Report, ReportConfig, SortBy,
+    WeeklyChanges,
+};
+pub use crate::slack::{post as post_to_slack, SlackConfig};
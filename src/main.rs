@@ -1,67 +1,308 @@
-mod git;
-mod github;
-mod issue;
-mod report;
-
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
-use std::process::exit;
+use std::process::{exit, Stdio};
+use std::time::Duration;
 
-use chrono::{DateTime, FixedOffset};
-use clap::{crate_version, Arg, Command};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{crate_version, value_parser, Arg, ArgAction, ArgMatches, Command};
 use directories::ProjectDirs;
-use lettre::message::SinglePart;
+use lettre::address::Envelope;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
 use lettre::Message;
 use lettre::{transport::smtp::authentication::Credentials, SmtpTransport, Transport};
+use log::{debug, warn, Level, LevelFilter};
+use regex::Regex;
 use serde::Deserialize;
 
-use crate::report::{agenda, GithubConfig};
+use pbmetric::{
+    agenda, exclude_patterns, fallback_token, last_release, post_to_slack, report_since,
+    select_repos, update_all, use_git, write_csv, write_report, Client, Format, GithubApp,
+    GithubAppConfig, GithubConfig, History, ProxyConfig, RecencyWeight, Repo, RepoSlug, Report,
+    ReportConfig, SlackConfig, Snapshot,
+};
 
 const QUALIFIER: &str = "com";
 const ORGANIZATION: &str = "petabi";
 const APPLICATION: &str = env!("CARGO_PKG_NAME");
+const CONFIG_TEMPLATE: &str = include_str!("config_template.toml");
+const LAST_REPORT_FILE: &str = "last_report";
 
 #[derive(Default, Deserialize)]
 struct MailConfig {
+    /// How to send the email, through `server` or the `sendmail` command.
+    #[serde(default)]
+    transport: MailTransport,
+    #[serde(default)]
     server: String,
+    /// Whether to log in to `server` with `username` and `password`. Mail
+    /// goes through unauthenticated if false or either of them is empty,
+    /// as for a relay trusting the host.
+    #[serde(default = "default_auth")]
+    auth: bool,
+    #[serde(default)]
     username: String,
+    /// The password, in which `${VAR}` is replaced with the value of the
+    /// environment variable `VAR` only when logging in.
+    #[serde(default)]
     password: String,
+    /// The sender address, which defaults to `username`.
+    #[serde(default)]
+    from: Option<String>,
+    /// A sendmail-compatible command, such as `msmtp`, for the sendmail
+    /// transport.
+    #[serde(default = "default_sendmail")]
+    sendmail: PathBuf,
     recipient: String,
+    /// The subject of the email, in which `{date}` is replaced with the date
+    /// of the report and `{org}` with the name of the organization.
+    #[serde(default = "default_subject_template")]
+    subject_template: String,
+    /// The format of the report, which defaults to that given by `--format`.
+    #[serde(default)]
+    format: Option<Format>,
+    /// Whether failing to send the email is only a warning.
+    #[serde(default)]
+    optional: bool,
+}
+
+impl MailConfig {
+    /// Returns the credentials to log in to the SMTP server with, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the password refers to an unset environment
+    /// variable.
+    fn credentials(&self) -> io::Result<Option<Credentials>> {
+        if !self.auth || self.username.is_empty() || self.password.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Credentials::new(
+            self.username.clone(),
+            expand_env(&self.password)?,
+        )))
+    }
+
+    fn sender(&self) -> &str {
+        self.from.as_deref().unwrap_or(&self.username)
+    }
+
+    /// Describes where the email goes through.
+    fn via(&self) -> String {
+        match self.transport {
+            MailTransport::Smtp => self.server.clone(),
+            MailTransport::Sendmail => self.sendmail.display().to_string(),
+        }
+    }
+}
+
+/// A file to write the report to as one of the delivery targets.
+#[derive(Deserialize)]
+struct OutputConfig {
+    /// The path of the file, or "-" for stdout.
+    path: PathBuf,
+    /// The format of the report, which defaults to that given by `--format`.
+    #[serde(default)]
+    format: Option<Format>,
+    /// Whether failing to write the file is only a warning.
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum MailTransport {
+    #[default]
+    Smtp,
+    Sendmail,
+}
+
+fn default_auth() -> bool {
+    true
+}
+
+fn default_sendmail() -> PathBuf {
+    PathBuf::from("sendmail")
+}
+
+fn default_subject_template() -> String {
+    "Project Snapshot {date}".to_string()
 }
 
 #[derive(Default, Deserialize)]
 struct Config {
-    mail: MailConfig,
+    #[serde(default)]
+    mail: Option<MailConfig>,
+    #[serde(default)]
+    slack: Option<SlackConfig>,
+    /// Files to write the report to, along with any email or Slack post.
+    #[serde(default)]
+    outputs: Vec<OutputConfig>,
     github: GithubConfig,
+    /// A GitHub App to authenticate as, in place of `github.token`.
+    #[serde(default)]
+    github_app: Option<GithubAppConfig>,
+    #[serde(default)]
+    proxy: Option<ProxyConfig>,
     email_map: BTreeMap<String, String>,
-    repos: BTreeMap<String, git::Repo>,
+    repos: BTreeMap<String, Repo>,
+    #[serde(default)]
+    report: ReportConfig,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    override_exclude: bool,
+    /// The git executable, if not the one on `PATH`.
+    #[serde(default)]
+    git_path: Option<PathBuf>,
 }
 
 impl Config {
     fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Config> {
         let mut buffer = String::new();
         File::open(path)?.read_to_string(&mut buffer)?;
-        match toml::from_str::<Config>(&buffer) {
-            Ok(config) => Ok(config),
-            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        Self::from_toml(&buffer)
+    }
+
+    /// Parses a configuration, replacing `${VAR}` in GitHub tokens with the
+    /// value of the environment variable `VAR`. The mail password is left
+    /// as it is until the report is mailed.
+    fn from_toml(s: &str) -> io::Result<Config> {
+        let mut config = match toml::from_str::<Config>(s) {
+            Ok(config) => config,
+            Err(e) => return Err(parse_error(s, &e)),
+        };
+        // Bare repository names are still accepted for older configurations.
+        config.github.qualify_repositories();
+        for token in config.github.token.values_mut() {
+            *token = expand_env(token)?;
+        }
+        if config.github.token.is_empty() {
+            if let Some(token) = fallback_token(|name| env::var(name).ok()) {
+                config.github.token = token.into();
+            }
+        }
+        Ok(config)
+    }
+
+    /// Checks the values that parsing does not, so that a mistake is reported
+    /// before any repository is fetched rather than when the report is sent.
+    /// Returns every problem found. The mail addresses are only checked if
+    /// `sends_mail`, since a report written locally never uses them.
+    fn validate(&self, sends_mail: bool) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if let Some(mail) = self.mail.as_ref().filter(|_| sends_mail) {
+            for (field, address) in [("from", mail.sender()), ("recipient", &mail.recipient)] {
+                if let Err(e) = address.parse::<Mailbox>() {
+                    errors.push(format!("mail: invalid {field} address \"{address}\": {e}"));
+                }
+            }
+        }
+        for repo in self.github.repositories() {
+            if let Err(e) = RepoSlug::parse(repo) {
+                errors.push(format!("github: {e}"));
+            }
+        }
+        if self.github_app.is_none() {
+            let owners = self
+                .github
+                .token
+                .owners_without_token(self.github.repositories());
+            if !owners.is_empty() {
+                errors.push(format!("github: no token for {}", owners.join(", ")));
+            }
+        }
+        for (name, repo) in &self.repos {
+            if repo.url.trim().is_empty() {
+                errors.push(format!("repos: no url for \"{name}\""));
+            }
+            if let Some(RecencyWeight::Exponential { half_life_days }) = repo.blame.recency {
+                if half_life_days <= 0. {
+                    errors.push(format!(
+                        "repos: half_life_days for \"{name}\" must be positive"
+                    ));
+                }
+            }
+        }
+        for (email, login) in &self.email_map {
+            if login.trim().is_empty() {
+                errors.push(format!("email_map: no account for \"{email}\""));
+            }
+        }
+        let patterns = exclude_patterns(&self.exclude, self.override_exclude);
+        let repo_patterns = self
+            .repos
+            .values()
+            .filter_map(|repo| repo.exclude.as_ref())
+            .flatten();
+        for pattern in patterns.iter().chain(repo_patterns) {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(format!("invalid exclude pattern: {e}"));
+            }
+        }
+        for pattern in &self.report.test_paths {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(format!("report: invalid test path pattern: {e}"));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 }
 
+/// Describes a TOML syntax or type error by the line and column where it
+/// occurs, which `toml` only reports as a byte span, along with the text of
+/// that line so that the offending key is visible.
+fn parse_error(s: &str, e: &toml::de::Error) -> io::Error {
+    let msg = match e.span() {
+        Some(span) => {
+            let start = s[..span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line = s[..start].matches('\n').count() + 1;
+            let column = s[start..span.start].chars().count() + 1;
+            let text = s[start..].lines().next().unwrap_or_default().trim();
+            format!("line {line}, column {column} (`{text}`): {}", e.message())
+        }
+        None => e.message().to_string(),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
 fn main() {
     let matches = Command::new(APPLICATION)
         .version(crate_version!())
-        .arg(Arg::new("asof").long("asof").num_args(1))
-        .arg(Arg::new("epoch").long("epoch").num_args(1))
-        .arg(
-            Arg::new("offline")
-                .long("offline")
-                .help("Skips updating repositories"),
+        .args(report_args())
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("report")
+                .about("Creates and delivers a report, which is the default")
+                .args(report_args()),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Checks the configuration without accessing the network"),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Writes a configuration template to the configuration directory")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Overwrites an existing configuration"),
+                ),
+        )
+        .subcommand(
+            Command::new("list-repos")
+                .about("Lists the configured repositories and where they are cloned"),
         )
         .get_matches();
 
@@ -69,30 +310,239 @@ fn main() {
         eprintln!("no valid home directory path");
         exit(1);
     };
-    let config = load_config(dirs.config_dir());
-    let asof = matches
-        .get_one::<String>("asof")
-        .map_or_else(
-            chrono::Utc::now,
-            |v| match DateTime::<FixedOffset>::parse_from_rfc3339(v) {
-                Ok(asof) => asof.with_timezone(&chrono::Utc),
-                Err(e) => {
-                    eprintln!("{e}: {v}");
+    match matches.subcommand() {
+        Some(("init", matches)) => {
+            match init_config(dirs.config_dir(), matches.get_flag("force")) {
+                Ok(path) => println!("{}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    eprintln!("configuration already exists; use --force to overwrite it");
                     exit(1);
                 }
-            },
-        );
-    let epoch =
-        matches.get_one::<String>("epoch").map(
-            |v| match DateTime::<FixedOffset>::parse_from_rfc3339(v) {
-                Ok(epoch) => epoch.with_timezone(&chrono::Utc),
                 Err(e) => {
-                    eprintln!("{e}: {v}");
+                    eprintln!("cannot write the configuration: {e}");
                     exit(1);
                 }
-            },
-        );
+            }
+        }
+        Some(("validate", _)) => validate(dirs.config_dir()),
+        Some(("list-repos", _)) => list_repos(&dirs),
+        Some(("report", matches)) => report(&dirs, matches),
+        _ => report(&dirs, &matches),
+    }
+}
+
+/// Writes log messages to stderr.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => eprintln!("error: {}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            Level::Info | Level::Debug | Level::Trace => eprintln!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
 
+/// Shows errors, warnings, and progress by default; `quiet` hides all but
+/// errors, and each `verbose` shows one more level of detail.
+fn init_logger(quiet: bool, verbose: u8) {
+    static LOGGER: StderrLogger = StderrLogger;
+    let level = if quiet {
+        LevelFilter::Error
+    } else {
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Returns the arguments of the `report` subcommand, which are also accepted
+/// without a subcommand.
+#[allow(clippy::too_many_lines)]
+fn report_args() -> Vec<Arg> {
+    vec![
+        Arg::new("asof")
+            .long("asof")
+            .num_args(1)
+            .value_parser(parse_time)
+            .help("Creates the report as of an RFC 3339 time instead of now"),
+        Arg::new("epoch")
+            .long("epoch")
+            .num_args(1)
+            .value_parser(parse_time)
+            .help("Sets the earliest time the lookback window may start at"),
+        Arg::new("since")
+            .long("since")
+            .num_args(1)
+            .value_parser(parse_time)
+            .help("Starts the report at an RFC 3339 time, overriding --epoch and --lookback-days"),
+        Arg::new("until")
+            .long("until")
+            .num_args(1)
+            .value_parser(parse_time)
+            .help("Ends the report at an RFC 3339 time, overriding --asof"),
+        Arg::new("since-last")
+            .long("since-last")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("epoch")
+            .help("Covers the period since the last delivered report"),
+        Arg::new("since-tag")
+            .long("since-tag")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("since")
+            .help("Starts the report at the most recent tag in the repositories"),
+        Arg::new("format")
+            .long("format")
+            .num_args(1)
+            .value_parser(Format::NAMES)
+            .default_value("html")
+            .help("Sets the format of the report"),
+        Arg::new("lookback-days")
+            .long("lookback-days")
+            .num_args(1)
+            .value_parser(value_parser!(u32).range(1..))
+            .help("Sets the number of days covered by the individual statistics"),
+        Arg::new("recent-days")
+            .long("recent-days")
+            .num_args(1)
+            .value_parser(value_parser!(u32).range(1..))
+            .help("Sets the number of days covered by the recent changes"),
+        Arg::new("stale-hours")
+            .long("stale-hours")
+            .num_args(1)
+            .value_parser(value_parser!(u32).range(1..))
+            .help("Sets the number of hours after which an assigned issue is stale"),
+        Arg::new("stale-pr-hours")
+            .long("stale-pr-hours")
+            .num_args(1)
+            .value_parser(value_parser!(u32).range(1..))
+            .help("Sets the number of hours after which an open pull request is stale"),
+        Arg::new("repo")
+            .long("repo")
+            .visible_alias("repos")
+            .num_args(1)
+            .value_name("REPO")
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .help("Covers only the given configured repositories; repeat or separate with commas"),
+        Arg::new("output")
+            .long("output")
+            .num_args(1)
+            .value_name("PATH")
+            .value_parser(value_parser!(PathBuf))
+            .help("Writes the report to a file, or to stdout if \"-\", instead of delivering it"),
+        Arg::new("output-csv")
+            .long("output-csv")
+            .num_args(1)
+            .value_name("PATH")
+            .value_parser(value_parser!(PathBuf))
+            .help("Also writes the individual statistics to a CSV file"),
+        Arg::new("db")
+            .long("db")
+            .num_args(1)
+            .value_name("PATH")
+            .value_parser(value_parser!(PathBuf))
+            .help("Keeps the numbers of each report in a SQLite database for comparison"),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Prints the report and what would be delivered without delivering it"),
+        Arg::new("offline")
+            .long("offline")
+            .action(ArgAction::SetTrue)
+            .help("Skips updating repositories"),
+        Arg::new("git")
+            .long("git")
+            .num_args(1)
+            .value_parser(value_parser!(PathBuf))
+            .help("Runs the given git executable instead of the one on PATH"),
+        Arg::new("git-retries")
+            .long("git-retries")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(value_parser!(u32))
+            .default_value("3")
+            .help("Sets how many times to retry cloning or fetching after a network error"),
+        Arg::new("no-cache")
+            .long("no-cache")
+            .visible_alias("refresh")
+            .action(ArgAction::SetTrue)
+            .help("Queries GitHub without reusing cached responses"),
+        Arg::new("no-progress")
+            .long("no-progress")
+            .action(ArgAction::SetTrue)
+            .help("Hides the progress of scanning repositories"),
+        Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .action(ArgAction::Count)
+            .help("Shows more progress, such as each file scanned; repeat for more"),
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("verbose")
+            .help("Shows errors only"),
+    ]
+}
+
+#[allow(clippy::too_many_lines)]
+fn report(dirs: &ProjectDirs, matches: &ArgMatches) {
+    init_logger(matches.get_flag("quiet"), matches.get_count("verbose"));
+    let sends_mail = !matches.contains_id("output") && !matches.get_flag("dry-run");
+    let mut config = load_config(dirs.config_dir(), sends_mail);
+    if let Some(lookback_days) = matches.get_one::<u32>("lookback-days") {
+        config.report.lookback_days = *lookback_days;
+    }
+    if let Some(recent_days) = matches.get_one::<u32>("recent-days") {
+        config.report.recent_days = *recent_days;
+    }
+    if let Some(stale_hours) = matches.get_one::<u32>("stale-hours") {
+        config.report.stale_hours = *stale_hours;
+    }
+    if let Some(stale_pr_hours) = matches.get_one::<u32>("stale-pr-hours") {
+        config.report.stale_pull_request_hours = *stale_pr_hours;
+    }
+    if let Some(names) = matches.get_many::<String>("repo") {
+        let names = names.cloned().collect::<Vec<_>>();
+        if let Err(e) = select_repos(&names, &mut config.github, &mut config.repos) {
+            eprintln!("{e}");
+            exit(1);
+        }
+    }
+    let asof = matches
+        .get_one::<DateTime<Utc>>("until")
+        .or_else(|| matches.get_one::<DateTime<Utc>>("asof"))
+        .copied()
+        .unwrap_or_else(Utc::now);
+    let epoch = matches.get_one::<DateTime<Utc>>("epoch").copied();
+    let state_path = dirs.data_dir().join(LAST_REPORT_FILE);
+    let epoch = if matches.get_flag("since-last") {
+        match read_last_report(&state_path) {
+            Ok(last) => last,
+            Err(e) => {
+                eprintln!("cannot read {}: {e}", state_path.display());
+                exit(1);
+            }
+        }
+    } else {
+        epoch
+    };
     let repo_dir = match repo_dir(dirs.cache_dir()) {
         Ok(dir) => dir,
         Err(e) => {
@@ -101,71 +551,581 @@ fn main() {
         }
     };
 
-    let orig_dir = match env::current_dir() {
-        Ok(dir) => dir,
+    let git_path = matches
+        .get_one::<PathBuf>("git")
+        .or(config.git_path.as_ref())
+        .map_or(Path::new("git"), PathBuf::as_path);
+    match use_git(git_path) {
+        Ok(version) => debug!("{version}"),
         Err(e) => {
-            eprintln!("cannot read the current directory: {e}");
+            eprintln!("{e}");
             exit(1);
         }
-    };
-    if let Err(e) = git::update_all(
+    }
+    let offline = matches.get_flag("offline");
+    let git_retries = matches
+        .get_one::<u32>("git-retries")
+        .copied()
+        .unwrap_or_default();
+    if let Err(e) = update_all(
         &repo_dir,
         &config.repos,
         &asof,
-        matches.contains_id("offline"),
+        offline,
+        git_retries,
+        config.proxy.as_ref(),
     ) {
         eprintln!("cannot update git repositories: {e}");
-        if let Err(e) = env::set_current_dir(orig_dir) {
-            eprintln!("cannot restore the working directory: {e}");
-        }
         exit(1);
     }
-    let mut body = Vec::<u8>::new();
-    if let Err(e) = agenda(
-        &mut body,
+    // The tags are only known once the repositories are checked out as of
+    // `asof`.
+    let tag_since = if matches.get_flag("since-tag") {
+        match last_release(&repo_dir, &config.repos) {
+            Ok(Some(since)) => Some(since),
+            Ok(None) => {
+                eprintln!("no tag in the repositories");
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("cannot find the last tag: {e}");
+                exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let since = match report_window(
+        &asof,
+        tag_since
+            .as_ref()
+            .or_else(|| matches.get_one::<DateTime<Utc>>("since")),
+        epoch.as_ref(),
+        config.report.lookback_days,
+    ) {
+        Ok(since) => since,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+    let format = matches
+        .get_one::<String>("format")
+        .and_then(|v| v.parse::<Format>().ok())
+        .unwrap_or_default();
+    let mut github_api = Client::new(&config.github.token);
+    if let Some(proxy) = &config.proxy {
+        match github_api.with_proxy(proxy) {
+            Ok(client) => github_api = client,
+            Err(e) => {
+                eprintln!("invalid proxy {}: {e}", proxy.url);
+                exit(1);
+            }
+        }
+    }
+    if let Some(app) = &config.github_app {
+        match GithubApp::new(app) {
+            Ok(app) => github_api = github_api.with_app(app),
+            Err(e) => {
+                eprintln!("cannot authenticate as the GitHub App: {e:#}");
+                exit(1);
+            }
+        }
+    }
+    if config.github.cache_ttl_minutes > 0 && !matches.get_flag("no-cache") {
+        github_api = github_api.with_cache(
+            dirs.cache_dir().join("github"),
+            Duration::from_secs(config.github.cache_ttl_minutes * 60),
+        );
+    }
+    let mut report = match agenda(
+        &config.report,
         &config.github,
+        &github_api,
         &repo_dir,
         &config.repos,
+        &exclude_patterns(&config.exclude, config.override_exclude),
         &config.email_map,
+        &since,
         &asof,
-        epoch.as_ref(),
+        !matches.get_flag("no-progress") && !matches.get_flag("quiet"),
     ) {
-        eprintln!("cannot create an agenda: {e}");
-        exit(1);
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("cannot create an agenda: {e}");
+            exit(1);
+        }
+    };
+    if let Some(db) = matches.get_one::<PathBuf>("db") {
+        match History::new(db.clone()).previous(&report.asof) {
+            Ok(previous) => report.previous = previous,
+            Err(e) => warn!("cannot read the previous report from {}: {e}", db.display()),
+        }
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("output-csv") {
+        if let Err(e) = write_csv_output(path, &report) {
+            eprintln!("cannot write the CSV to {}: {e}", path.display());
+            exit(1);
+        }
     }
-    if let Err(e) = env::set_current_dir(orig_dir) {
-        eprintln!("cannot restore the working directory: {e}");
+    if let Some(path) = matches.get_one::<PathBuf>("output") {
+        if let Err(e) = write_output(path, format, &report) {
+            eprintln!("cannot write the report to {}: {e}", path.display());
+            exit(1);
+        }
+        // A dry run leaves the next run's window and the history alone.
+        if !matches.get_flag("dry-run") {
+            record_report(&state_path, matches.get_one::<PathBuf>("db"), &report);
+        }
+        return;
+    }
+    let targets = delivery_targets(config.mail, config.slack, config.outputs, format);
+    if matches.get_flag("dry-run") {
+        if let Err(e) = write_output(Path::new("-"), format, &report) {
+            eprintln!("cannot write the report: {e}");
+            exit(1);
+        }
+        for target in &targets {
+            eprintln!("Would {}", target.plan(&report));
+        }
+        return;
+    }
+    if targets.is_empty() {
+        eprintln!("no delivery channel configured");
         exit(1);
     }
 
-    let part = SinglePart::html(body);
-    let (Ok(to), Ok(from)) = (config.mail.recipient.parse(), config.mail.username.parse()) else {
-        eprintln!("cannot parse email addresses");
+    let mut failed = false;
+    for (target, result) in deliver_all(&targets, &report) {
+        let Err(e) = result else {
+            continue;
+        };
+        if target.is_optional() {
+            warn!("cannot deliver to {}: {e:#}", target.name());
+        } else {
+            eprintln!("cannot deliver to {}: {e:#}", target.name());
+            failed = true;
+        }
+    }
+    if failed {
         exit(1);
+    }
+    record_report(&state_path, matches.get_one::<PathBuf>("db"), &report);
+}
+
+/// Where a report is delivered.
+enum Target {
+    Email(MailConfig, Format),
+    Slack(SlackConfig),
+    File(OutputConfig, Format),
+}
+
+impl Target {
+    /// Names the target in messages.
+    fn name(&self) -> String {
+        match self {
+            Self::Email(mail, _) => format!("{} via {}", mail.recipient, mail.via()),
+            Self::Slack(_) => "Slack".to_string(),
+            Self::File(output, _) if output.path == Path::new("-") => "stdout".to_string(),
+            Self::File(output, _) => output.path.display().to_string(),
+        }
+    }
+
+    /// Describes what delivering `report` would do, for a dry run.
+    fn plan(&self, report: &Report) -> String {
+        match self {
+            Self::Email(mail, _) => format!(
+                "send \"{}\" to {} via {}",
+                subject(&mail.subject_template, report.date(&report.asof)),
+                mail.recipient,
+                mail.via()
+            ),
+            Self::Slack(_) => "post the report to Slack".to_string(),
+            Self::File(_, format) => format!("write the {format} report to {}", self.name()),
+        }
+    }
+
+    fn is_optional(&self) -> bool {
+        match self {
+            Self::Email(mail, _) => mail.optional,
+            Self::Slack(slack) => slack.optional,
+            Self::File(output, _) => output.optional,
+        }
+    }
+
+    fn deliver(&self, rendered: &mut Rendered) -> anyhow::Result<()> {
+        match self {
+            Self::Email(mail, format) => send_email(mail, *format, rendered),
+            Self::Slack(slack) => post_to_slack(slack, rendered.report),
+            Self::File(output, format) => {
+                write_body(&output.path, rendered.get(*format)?.as_bytes())?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Lists the configured delivery targets, which use `format` unless they
+/// choose their own.
+fn delivery_targets(
+    mail: Option<MailConfig>,
+    slack: Option<SlackConfig>,
+    outputs: Vec<OutputConfig>,
+    format: Format,
+) -> Vec<Target> {
+    let mut targets = Vec::new();
+    if let Some(mail) = mail {
+        let format = mail.format.unwrap_or(format);
+        targets.push(Target::Email(mail, format));
+    }
+    if let Some(slack) = slack {
+        targets.push(Target::Slack(slack));
+    }
+    for output in outputs {
+        let format = output.format.unwrap_or(format);
+        targets.push(Target::File(output, format));
+    }
+    targets
+}
+
+/// Delivers `report` to each of `targets`, going on after a failure, and
+/// returns the outcome for each. The report is rendered once per format.
+fn deliver_all<'a>(
+    targets: &'a [Target],
+    report: &Report,
+) -> Vec<(&'a Target, anyhow::Result<()>)> {
+    let mut rendered = Rendered::new(report);
+    targets
+        .iter()
+        .map(|target| (target, target.deliver(&mut rendered)))
+        .collect()
+}
+
+/// The report rendered in each format asked for so far.
+struct Rendered<'a> {
+    report: &'a Report,
+    bodies: HashMap<Format, String>,
+}
+
+impl<'a> Rendered<'a> {
+    fn new(report: &'a Report) -> Self {
+        Self {
+            report,
+            bodies: HashMap::new(),
+        }
+    }
+
+    /// Returns the report in `format`, rendering it the first time.
+    fn get(&mut self, format: Format) -> anyhow::Result<&str> {
+        if !self.bodies.contains_key(&format) {
+            let body = render(format, self.report)?;
+            self.bodies.insert(format, body);
+        }
+        Ok(&self.bodies[&format])
+    }
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(s).map(|t| t.with_timezone(&Utc))
+}
+
+/// Returns the start of the window covered by a report ending at `asof`.
+/// `since` sets it directly; otherwise it is `lookback_days` before `asof`,
+/// but no earlier than `epoch`.
+fn report_window(
+    asof: &DateTime<Utc>,
+    since: Option<&DateTime<Utc>>,
+    epoch: Option<&DateTime<Utc>>,
+    lookback_days: u32,
+) -> Result<DateTime<Utc>, String> {
+    let since = since.map_or_else(|| report_since(asof, epoch, lookback_days), |since| *since);
+    if since < *asof {
+        Ok(since)
+    } else {
+        Err(format!(
+            "the report must start before it ends: {} is not before {}",
+            since.to_rfc3339(),
+            asof.to_rfc3339()
+        ))
+    }
+}
+
+/// Records the time of `report` in `state_path`, so that the next run with
+/// `--since-last` starts from there, and its numbers in the history at `db`
+/// if given. Failing to do so does not undo the delivery, so it is only a
+/// warning.
+fn record_report(state_path: &Path, db: Option<&PathBuf>, report: &Report) {
+    if let Err(e) = write_last_report(state_path, &report.asof) {
+        warn!(
+            "cannot record the time of this report in {}: {e}",
+            state_path.display()
+        );
+    }
+    if let Some(db) = db {
+        if let Err(e) = History::new(db.clone()).record(&Snapshot::new(report)) {
+            warn!("cannot record this report in {}: {e}", db.display());
+        }
+    }
+}
+
+/// Reads the time of the last report, or `None` if no report has been made.
+fn read_last_report(path: &Path) -> io::Result<Option<DateTime<Utc>>> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
     };
-    let msg = Message::builder()
+    parse_time(s.trim())
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_last_report(path: &Path, asof: &DateTime<Utc>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n", asof.to_rfc3339()))
+}
+
+/// Writes the report to `path`, or to stdout if `path` is "-".
+fn write_output(path: &Path, format: Format, report: &Report) -> anyhow::Result<()> {
+    write_body(path, render(format, report)?.as_bytes())?;
+    Ok(())
+}
+
+/// Writes a rendered report to `path`, or to stdout if `path` is "-".
+fn write_body(path: &Path, body: &[u8]) -> io::Result<()> {
+    if path == Path::new("-") {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(body)?;
+        out.flush()
+    } else {
+        fs::write(path, body)
+    }
+}
+
+fn write_csv_output(path: &Path, report: &Report) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(path)?);
+    write_csv(&mut out, report)?;
+    out.flush()
+}
+
+fn send_email(mail: &MailConfig, format: Format, rendered: &mut Rendered) -> anyhow::Result<()> {
+    let msg = build_message(mail, format, rendered)?;
+    match mail.transport {
+        MailTransport::Smtp => deliver(&smtp_transport(mail)?, &msg),
+        MailTransport::Sendmail => deliver(&Sendmail::new(mail.sendmail.clone()), &msg),
+    }
+}
+
+fn smtp_transport(mail: &MailConfig) -> anyhow::Result<SmtpTransport> {
+    let mut builder = SmtpTransport::starttls_relay(&mail.server)
+        .with_context(|| format!("cannot connect to {}", mail.server))?;
+    if let Some(credentials) = mail.credentials()? {
+        builder = builder.credentials(credentials);
+    }
+    Ok(builder.build())
+}
+
+/// Sends email by piping it to a sendmail-compatible command, which is given
+/// the sender with `-f` and the recipients as arguments.
+struct Sendmail {
+    command: PathBuf,
+}
+
+impl Sendmail {
+    fn new(command: PathBuf) -> Self {
+        Self { command }
+    }
+}
+
+impl Transport for Sendmail {
+    type Ok = ();
+    type Error = io::Error;
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> io::Result<()> {
+        let mut cmd = std::process::Command::new(&self.command);
+        cmd.arg("-i");
+        if let Some(from) = envelope.from() {
+            cmd.arg("-f").arg(from);
+        }
+        let mut child = cmd
+            .arg("--")
+            .args(envelope.to())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", self.command.display())))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(email)?;
+        }
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "{} {}: {}",
+                self.command.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+}
+
+/// Builds an email containing the report, with the individual statistics
+/// attached as CSV. An HTML report comes with a markdown alternative for mail
+/// clients that do not render HTML.
+fn build_message(
+    mail: &MailConfig,
+    format: Format,
+    rendered: &mut Rendered,
+) -> anyhow::Result<Message> {
+    let report = rendered.report;
+    let to = mail
+        .recipient
+        .parse()
+        .with_context(|| format!("invalid recipient address: {}", mail.recipient))?;
+    let from = mail
+        .sender()
+        .parse()
+        .with_context(|| format!("invalid sender address: {}", mail.sender()))?;
+    let builder = Message::builder()
         .to(to)
         .from(from)
-        .subject(format!(
-            "Project Snapshot {}",
-            chrono::offset::Utc::now().date_naive()
-        ))
-        .singlepart(part)
-        .unwrap();
-    let credentials = Credentials::new(config.mail.username, config.mail.password);
-    let sender = SmtpTransport::starttls_relay(&config.mail.server)
-        .unwrap()
-        .credentials(credentials)
-        .build();
-    let _result = sender.send(&msg);
+        .subject(subject(&mail.subject_template, report.date(&report.asof)));
+    let body = match format {
+        Format::Html => MultiPart::mixed().multipart(MultiPart::alternative_plain_html(
+            rendered.get(Format::Markdown)?.to_string(),
+            rendered.get(Format::Html)?.to_string(),
+        )),
+        Format::Markdown | Format::Json | Format::Prometheus => {
+            MultiPart::mixed().singlepart(SinglePart::plain(rendered.get(format)?.to_string()))
+        }
+    };
+    let mut csv = Vec::new();
+    write_csv(&mut csv, report).context("cannot write the CSV")?;
+    let attachment = Attachment::new(format!("pbmetric-{}.csv", report.date(&report.asof))).body(
+        csv,
+        ContentType::parse("text/csv").expect("valid content type"),
+    );
+    builder
+        .multipart(body.singlepart(attachment))
+        .context("cannot build the email")
+}
+
+fn render(format: Format, report: &Report) -> anyhow::Result<String> {
+    let mut body = Vec::new();
+    write_report(&mut body, format, report).context("cannot write the report")?;
+    Ok(String::from_utf8(body)?)
+}
+
+fn deliver<T>(transport: &T, msg: &Message) -> anyhow::Result<()>
+where
+    T: Transport,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    transport.send(msg).context("cannot send the email")?;
+    Ok(())
 }
 
-fn load_config<P: AsRef<Path>>(dir: P) -> Config {
+fn subject(template: &str, date: NaiveDate) -> String {
+    template
+        .replace("{date}", &date.to_string())
+        .replace("{org}", ORGANIZATION)
+}
+
+/// Replaces each `${VAR}` in `value` with the value of the environment
+/// variable `VAR`.
+fn expand_env(value: &str) -> io::Result<String> {
+    let pattern = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid regex");
+    let mut expanded = String::with_capacity(value.len());
+    let mut last = 0;
+    for caps in pattern.captures_iter(value) {
+        let (Some(whole), Some(name)) = (caps.get(0), caps.get(1)) else {
+            continue;
+        };
+        let Ok(var) = env::var(name.as_str()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("environment variable {} is not set", name.as_str()),
+            ));
+        };
+        expanded.push_str(&value[last..whole.start()]);
+        expanded.push_str(&var);
+        last = whole.end();
+    }
+    expanded.push_str(&value[last..]);
+    Ok(expanded)
+}
+
+/// Writes the configuration template to `config_dir`, returning its path. An
+/// existing configuration is overwritten only if `force` is set.
+fn init_config(config_dir: &Path, force: bool) -> io::Result<PathBuf> {
+    fs::create_dir_all(config_dir)?;
+    let path = config_dir.join("config.toml");
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options.open(&path)?.write_all(CONFIG_TEMPLATE.as_bytes())?;
+    Ok(path)
+}
+
+/// Checks that the configuration in `config_dir` can be loaded and is valid.
+fn validate(config_dir: &Path) {
+    let path = config_dir.join("config.toml");
+    let config = match Config::from_path(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            exit(1);
+        }
+    };
+    check_config(&path, &config, true);
+    println!("{}: OK", path.display());
+}
+
+/// Exits after printing every problem with `config` loaded from `path`, if
+/// there are any, leaving out the mail addresses unless `sends_mail`.
+fn check_config(path: &Path, config: &Config, sends_mail: bool) {
+    if let Err(errors) = config.validate(sends_mail) {
+        for e in errors {
+            eprintln!("{}: {e}", path.display());
+        }
+        exit(1);
+    }
+}
+
+/// Prints each configured repository with its URL and local clone.
+fn list_repos(dirs: &ProjectDirs) {
+    let config = load_config(dirs.config_dir(), false);
+    let repo_dir = match repo_dir(dirs.cache_dir()) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("cannot create the repository directory: {e}");
+            exit(1);
+        }
+    };
+    for (name, repo) in &config.repos {
+        println!("{name}\t{}\t{}", repo.url, repo_dir.join(name).display());
+    }
+}
+
+fn load_config<P: AsRef<Path>>(dir: P, sends_mail: bool) -> Config {
     let mut path = PathBuf::new();
     path.push(dir);
     path.push("config.toml");
     match Config::from_path(&path) {
-        Ok(config) => config,
+        Ok(config) => {
+            check_config(&path, &config, sends_mail);
+            config
+        }
         Err(e) => {
             if e.kind() == io::ErrorKind::NotFound {
                 Config::default()
@@ -184,3 +1144,522 @@ fn repo_dir<P: AsRef<Path>>(cache_dir: P) -> io::Result<PathBuf> {
     fs::create_dir_all(&repo_dir)?;
     Ok(repo_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lettre::transport::stub::StubTransport;
+
+    use super::*;
+    use pbmetric::GithubData;
+
+    fn mail_config() -> MailConfig {
+        MailConfig {
+            transport: MailTransport::Smtp,
+            server: "smtp.example.com".to_string(),
+            auth: true,
+            username: "pbmetric@example.com".to_string(),
+            password: "secret".to_string(),
+            from: None,
+            sendmail: default_sendmail(),
+            recipient: "team@example.com".to_string(),
+            subject_template: default_subject_template(),
+            format: None,
+            optional: false,
+        }
+    }
+
+    #[test]
+    fn config_template_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = init_config(dir.path(), false).unwrap();
+        let config = Config::from_path(&path).unwrap();
+        assert!(config.mail.is_some());
+        assert!(config.slack.is_none());
+        assert!(config.repos.is_empty());
+
+        let err = init_config(dir.path(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        fs::write(&path, "").unwrap();
+        init_config(dir.path(), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG_TEMPLATE);
+    }
+
+    const VALID_CONFIG: &str = "\
+        [mail]\n\
+        server = \"smtp.example.com\"\n\
+        username = \"pbmetric@example.com\"\n\
+        recipient = \"team@example.com\"\n\
+        [github]\n\
+        token = \"token\"\n\
+        repositories = [\"pbmetric\", \"rust-lang/rust\"]\n\
+        account = {}\n\
+        [email_map]\n\
+        \"alice@example.com\" = \"alice\"\n\
+        [repos]\n\
+        pbmetric = { url = \"https://github.com/petabi/pbmetric.git\" }\n";
+
+    fn validation_errors(toml: &str) -> Vec<String> {
+        Config::from_toml(toml).unwrap().validate(true).unwrap_err()
+    }
+
+    #[test]
+    fn valid_config_passes_validation() {
+        assert_eq!(
+            Config::from_toml(VALID_CONFIG).unwrap().validate(true),
+            Ok(())
+        );
+        let template = Config::from_toml(CONFIG_TEMPLATE).unwrap();
+        assert_eq!(template.validate(true), Ok(()));
+    }
+
+    #[test]
+    fn invalid_mail_addresses_are_reported() {
+        let errors = validation_errors(
+            &VALID_CONFIG
+                .replace("\"pbmetric@example.com\"", "\"pbmetric\"")
+                .replace("team@example.com", "team@"),
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("mail: invalid from address \"pbmetric\""));
+        assert!(errors[1].starts_with("mail: invalid recipient address \"team@\""));
+
+        let config = Config::from_toml(&VALID_CONFIG.replace("team@example.com", "team@")).unwrap();
+        assert_eq!(config.validate(false), Ok(()));
+    }
+
+    #[test]
+    fn repositories_must_be_owner_and_name() {
+        let config =
+            toml::from_str::<Config>(&VALID_CONFIG.replace("\"pbmetric\"", "\"badslug\"")).unwrap();
+        assert_eq!(
+            config.validate(true).unwrap_err(),
+            ["github: invalid repository \"badslug\", expected \"owner/name\""]
+        );
+        // Loading the configuration qualifies bare names first.
+        let config =
+            Config::from_toml(&VALID_CONFIG.replace("\"pbmetric\"", "\"badslug\"")).unwrap();
+        assert_eq!(
+            config.github.repositories(),
+            ["petabi/badslug", "rust-lang/rust"]
+        );
+        assert_eq!(config.validate(true), Ok(()));
+
+        let errors = validation_errors(&VALID_CONFIG.replace(
+            "\"rust-lang/rust\"",
+            "\"rust-lang/rust/issues\", \"https://github.com/petabi/pbmetric\"",
+        ));
+        assert_eq!(
+            errors,
+            [
+                "github: invalid repository \"rust-lang/rust/issues\", expected \"owner/name\"",
+                "github: invalid repository \"https://github.com/petabi/pbmetric\", expected \"owner/name\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn repos_must_have_url() {
+        let errors =
+            validation_errors(&VALID_CONFIG.replace("https://github.com/petabi/pbmetric.git", " "));
+        assert_eq!(errors, ["repos: no url for \"pbmetric\""]);
+
+        let errors = validation_errors(&VALID_CONFIG.replace(
+            ".git\" }",
+            ".git\", recency = { decay = \"exponential\", half_life_days = 0 } }",
+        ));
+        assert_eq!(
+            errors,
+            ["repos: half_life_days for \"pbmetric\" must be positive"]
+        );
+    }
+
+    #[test]
+    fn email_map_values_must_not_be_empty() {
+        let errors = validation_errors(&VALID_CONFIG.replace("\"alice\"", "\"\""));
+        assert_eq!(errors, ["email_map: no account for \"alice@example.com\""]);
+    }
+
+    #[test]
+    fn all_validation_errors_are_reported() {
+        let errors = validation_errors(
+            &format!("exclude = [\"(\"]\n{VALID_CONFIG}")
+                .replace("team@example.com", "team")
+                .replace("\"alice\"", "\"\""),
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("mail: invalid recipient address"));
+        assert_eq!(errors[1], "email_map: no account for \"alice@example.com\"");
+        assert!(errors[2].starts_with("invalid exclude pattern: "));
+
+        let errors = validation_errors(&format!("{VALID_CONFIG}[report]\ntest_paths = [\"[\"]\n"));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("report: invalid test path pattern: "));
+    }
+
+    #[test]
+    fn parse_errors_have_location() {
+        let err = Config::from_toml("[github]\ntoken = \"x\"\nrepositories = 3\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 16 (`repositories = 3`): invalid type: integer `3`, expected a sequence"
+        );
+
+        let err = Config::from_toml("[github]\ntoken = \n").err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("line 2, column 9 (`token =`): "));
+    }
+
+    #[test]
+    fn last_report_time_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join(LAST_REPORT_FILE);
+        assert_eq!(read_last_report(&path).unwrap(), None);
+
+        let asof = DateTime::parse_from_rfc3339("2024-02-01T09:30:00+09:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        write_last_report(&path, &asof).unwrap();
+        assert_eq!(read_last_report(&path).unwrap(), Some(asof));
+
+        fs::write(&path, "yesterday").unwrap();
+        assert_eq!(
+            read_last_report(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn explicit_since_overrides_derived_window() {
+        let asof = parse_time("2024-02-01T00:00:00Z").unwrap();
+        let epoch = parse_time("2024-01-15T00:00:00Z").unwrap();
+        assert_eq!(report_window(&asof, None, Some(&epoch), 90), Ok(epoch));
+
+        let since = parse_time("2023-06-01T00:00:00Z").unwrap();
+        assert_eq!(
+            report_window(&asof, Some(&since), Some(&epoch), 90),
+            Ok(since)
+        );
+        assert_eq!(report_window(&asof, Some(&since), None, 7), Ok(since));
+    }
+
+    #[test]
+    fn window_must_not_be_empty() {
+        let asof = parse_time("2024-02-01T00:00:00Z").unwrap();
+        assert!(report_window(&asof, Some(&asof), None, 90).is_err());
+        let since = parse_time("2024-02-01T09:00:00+09:00").unwrap();
+        assert_eq!(since, asof);
+        assert!(report_window(&asof, Some(&since), None, 90).is_err());
+        let since = parse_time("2024-03-01T00:00:00Z").unwrap();
+        assert!(report_window(&asof, Some(&since), None, 90).is_err());
+        assert!(parse_time("2024-02-01").is_err());
+    }
+
+    #[test]
+    fn secrets_are_read_from_environment() {
+        env::set_var("PBMETRIC_TEST_GITHUB_TOKEN", "ghp_secret");
+        let mut config = Config::from_toml(
+            r#"
+            [github]
+            token = "${PBMETRIC_TEST_GITHUB_TOKEN}"
+            repositories = []
+            account = {}
+
+            [email_map]
+            [repos]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.github.token.values_mut().collect::<Vec<_>>(),
+            ["ghp_secret"]
+        );
+
+        assert_eq!(
+            expand_env("pre-${PBMETRIC_TEST_GITHUB_TOKEN}-$HOME").unwrap(),
+            "pre-ghp_secret-$HOME"
+        );
+        let err = expand_env("${PBMETRIC_TEST_UNSET_VARIABLE}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable PBMETRIC_TEST_UNSET_VARIABLE is not set"
+        );
+
+        // The mail password is only needed to send the report.
+        let config = Config::from_toml(&VALID_CONFIG.replace(
+            "[mail]\n",
+            "[mail]\npassword = \"${PBMETRIC_TEST_UNSET_VARIABLE}\"\n",
+        ))
+        .unwrap();
+        let mail = config.mail.unwrap();
+        assert!(mail.credentials().is_err());
+        assert!(smtp_transport(&mail).is_err());
+    }
+
+    fn empty_report() -> Report {
+        let asof = Utc::now();
+        let github_data = GithubData {
+            open_pull_requests: Vec::new(),
+            assigned_issues: Vec::new(),
+            issue_metadata: Vec::new(),
+            merged_pull_requests: Vec::new(),
+            reviews: Vec::new(),
+            issue_comments: Vec::new(),
+        };
+        Report::new(
+            &ReportConfig::default(),
+            github_data,
+            HashMap::new(),
+            &HashMap::new(),
+            &BTreeMap::new(),
+            &asof,
+            &asof,
+        )
+    }
+
+    #[test]
+    fn html_email_has_plain_text_alternative() {
+        let msg = build_message(
+            &mail_config(),
+            Format::Html,
+            &mut Rendered::new(&empty_report()),
+        )
+        .unwrap();
+        let msg = String::from_utf8(msg.formatted()).unwrap();
+        assert!(msg.contains("Content-Type: multipart/alternative"));
+        assert!(msg.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(msg.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(msg.contains("## Changes in the Past Week"));
+        assert!(msg.contains("<h2>Changes in the Past Week</h2>"));
+
+        let msg = build_message(
+            &mail_config(),
+            Format::Markdown,
+            &mut Rendered::new(&empty_report()),
+        )
+        .unwrap();
+        let msg = String::from_utf8(msg.formatted()).unwrap();
+        assert!(!msg.contains("multipart/alternative"));
+        assert!(!msg.contains("text/html"));
+    }
+
+    #[test]
+    fn report_is_delivered_to_every_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::from_toml(&format!(
+            "[[outputs]]\n\
+             path = \"{0}/report.md\"\n\
+             format = \"markdown\"\n\
+             [[outputs]]\n\
+             path = \"{0}/report.html\"\n\
+             [[outputs]]\n\
+             path = \"{0}/missing/report.json\"\n\
+             format = \"json\"\n\
+             optional = true\n\
+             [github]\n\
+             token = \"token\"\n\
+             repositories = []\n\
+             account = {{}}\n\
+             [email_map]\n\
+             [repos]\n",
+            dir.path().display()
+        ))
+        .unwrap();
+        let targets = delivery_targets(config.mail, config.slack, config.outputs, Format::Html);
+        assert_eq!(targets.len(), 3);
+
+        let report = empty_report();
+        let results = deliver_all(&targets, &report);
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_ok());
+        assert!(results[2].0.is_optional());
+        assert!(results[2].1.is_err());
+        assert!(fs::read_to_string(dir.path().join("report.md"))
+            .unwrap()
+            .starts_with("## Changes in the Past Week\n"));
+        assert!(fs::read_to_string(dir.path().join("report.html"))
+            .unwrap()
+            .starts_with("<html><head>"));
+    }
+
+    #[test]
+    fn each_format_is_rendered_once() {
+        let report = empty_report();
+        let mut rendered = Rendered::new(&report);
+        let html = rendered.get(Format::Html).unwrap().to_string();
+        rendered.get(Format::Markdown).unwrap();
+        assert_eq!(rendered.get(Format::Html).unwrap(), html);
+        assert_eq!(rendered.bodies.len(), 2);
+    }
+
+    #[test]
+    fn csv_is_attached() {
+        let msg = build_message(
+            &mail_config(),
+            Format::Html,
+            &mut Rendered::new(&empty_report()),
+        )
+        .unwrap();
+        let msg = String::from_utf8(msg.formatted()).unwrap();
+        assert!(msg.contains("Content-Type: multipart/mixed"));
+        assert!(msg.contains("Content-Type: text/csv"));
+        assert!(msg.contains("Content-Disposition: attachment; filename=\"pbmetric-"));
+        assert!(msg.contains("username,bugs_reported,"));
+    }
+
+    #[test]
+    fn subject_uses_template_and_asof_date() {
+        let asof = DateTime::parse_from_rfc3339("2023-06-30T23:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            subject(&default_subject_template(), asof.date_naive()),
+            "Project Snapshot 2023-06-30"
+        );
+        assert_eq!(
+            subject("[{org}] Weekly metrics for {date}", asof.date_naive()),
+            "[petabi] Weekly metrics for 2023-06-30"
+        );
+    }
+
+    #[test]
+    fn subject_date_is_in_report_timezone() {
+        let asof = DateTime::parse_from_rfc3339("2023-06-30T23:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut report = empty_report();
+        report.asof = asof;
+        report.timezone = chrono_tz::Asia::Seoul;
+        assert_eq!(
+            subject(&default_subject_template(), report.date(&report.asof)),
+            "Project Snapshot 2023-07-01"
+        );
+    }
+
+    #[test]
+    fn send_errors_are_surfaced() {
+        let msg = build_message(
+            &mail_config(),
+            Format::Html,
+            &mut Rendered::new(&empty_report()),
+        )
+        .unwrap();
+        assert!(deliver(&StubTransport::new_ok(), &msg).is_ok());
+        let err = deliver(&StubTransport::new_error(), &msg).unwrap_err();
+        assert!(format!("{err:#}").starts_with("cannot send the email: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sendmail_is_given_the_message() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let shim = dir.path().join("sendmail");
+        fs::write(
+            &shim,
+            "#!/bin/sh\n\
+             echo \"$@\" > \"$0.args\"\n\
+             cat > \"$0.msg\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&shim, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = Config::from_toml(&format!(
+            "[mail]\n\
+             transport = \"sendmail\"\n\
+             sendmail = \"{}\"\n\
+             username = \"pbmetric@example.com\"\n\
+             recipient = \"team@example.com\"\n\
+             [github]\n\
+             token = \"token\"\n\
+             repositories = []\n\
+             account = {{}}\n\
+             [email_map]\n\
+             [repos]\n",
+            shim.display()
+        ))
+        .unwrap();
+        let mail = config.mail.unwrap();
+        assert_eq!(mail.via(), shim.display().to_string());
+        send_email(&mail, Format::Markdown, &mut Rendered::new(&empty_report())).unwrap();
+
+        let args = fs::read_to_string(dir.path().join("sendmail.args")).unwrap();
+        assert_eq!(
+            args.trim(),
+            "-i -f pbmetric@example.com -- team@example.com"
+        );
+        let msg = fs::read_to_string(dir.path().join("sendmail.msg")).unwrap();
+        assert!(msg.contains("To: team@example.com"));
+        assert!(msg.contains("## Changes in the Past Week"));
+
+        fs::write(&shim, "#!/bin/sh\necho 'no such user' >&2\nexit 67\n").unwrap();
+        let err = deliver(
+            &Sendmail::new(shim),
+            &build_message(
+                &mail_config(),
+                Format::Markdown,
+                &mut Rendered::new(&empty_report()),
+            )
+            .unwrap(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").ends_with("no such user"));
+    }
+
+    #[test]
+    fn smtp_without_authentication() {
+        let mail = mail_config();
+        assert!(mail.credentials().unwrap().is_some());
+        assert!(smtp_transport(&mail).is_ok());
+
+        for mail in [
+            MailConfig {
+                auth: false,
+                ..mail_config()
+            },
+            MailConfig {
+                username: String::new(),
+                password: String::new(),
+                from: Some("pbmetric@example.com".to_string()),
+                ..mail_config()
+            },
+        ] {
+            assert!(mail.credentials().unwrap().is_none());
+            assert!(smtp_transport(&mail).is_ok());
+            let msg = build_message(&mail, Format::Markdown, &mut Rendered::new(&empty_report()))
+                .unwrap();
+            assert_eq!(
+                msg.envelope().from().unwrap().to_string(),
+                "pbmetric@example.com"
+            );
+        }
+
+        let mail = MailConfig {
+            from: Some("Metrics <metrics@example.com>".to_string()),
+            ..mail_config()
+        };
+        let msg =
+            build_message(&mail, Format::Markdown, &mut Rendered::new(&empty_report())).unwrap();
+        assert_eq!(
+            msg.envelope().from().unwrap().to_string(),
+            "metrics@example.com"
+        );
+    }
+
+    #[test]
+    fn invalid_addresses_are_reported() {
+        let mail = MailConfig {
+            recipient: "not an address".to_string(),
+            ..mail_config()
+        };
+        let err =
+            build_message(&mail, Format::Html, &mut Rendered::new(&empty_report())).unwrap_err();
+        assert_eq!(err.to_string(), "invalid recipient address: not an address");
+    }
+}
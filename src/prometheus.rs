@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use crate::issue::IndividualStats;
+use crate::report::Report;
+
+/// The statistics reported for each user and team, as the suffix of the
+/// metric name, its help text, and how to get it.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::type_complexity)]
+const STATS: [(&str, &str, fn(&IndividualStats) -> f64); 9] = [
+    ("bugs_reported", "Bugs reported", |s| s.bugs_reported as f64),
+    ("issues_completed", "Issues completed", |s| {
+        s.issues_completed
+    }),
+    ("issues_opened", "Issues opened", |s| s.issues_opened as f64),
+    ("pull_requests_merged", "Pull requests merged", |s| {
+        s.merged_merge_requests_opened as f64
+    }),
+    (
+        "pull_request_comments",
+        "Comments on merged pull requests",
+        |s| s.merge_request_notes as f64,
+    ),
+    ("reviews_given", "Pull request reviews given", |s| {
+        s.reviews_given as f64
+    }),
+    ("issue_comments", "Issue comments posted", |s| {
+        s.issue_comments as f64
+    }),
+    (
+        "lines_contributed",
+        "Lines authored and still present",
+        |s| s.lines_contributed,
+    ),
+    ("lines_removed", "Lines deleted", |s| s.lines_removed),
+];
+
+/// Writes the numbers in `report` in the Prometheus text exposition format.
+/// Per-user and per-team statistics cover the lookback window.
+pub fn write_metrics(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    gauge(
+        out,
+        "open_pull_requests",
+        "Open pull requests",
+        report.pull_requests.len(),
+    )?;
+    gauge(
+        out,
+        "stale_pull_requests",
+        "Open pull requests with no recent update",
+        report.stale_pull_requests.len(),
+    )?;
+    gauge(
+        out,
+        "stale_issues",
+        "Assigned issues with no recent update",
+        report.stale_issues.len(),
+    )?;
+    gauge(
+        out,
+        "recent_issues_created",
+        "Issues created in the recent window",
+        report.weekly.created,
+    )?;
+    gauge(
+        out,
+        "recent_issues_completed",
+        "Issues completed in the recent window",
+        report.weekly.completed,
+    )?;
+    if let Some(time_to_close) = &report.time_to_close {
+        gauge(
+            out,
+            "issues_closed",
+            "Issues closed in the lookback window",
+            time_to_close.closed,
+        )?;
+        gauge(
+            out,
+            "issue_close_days_mean",
+            "Mean days from opening to closing an issue",
+            time_to_close.mean_days,
+        )?;
+        gauge(
+            out,
+            "issue_close_days_median",
+            "Median days from opening to closing an issue",
+            time_to_close.median_days,
+        )?;
+    }
+    write_stats(out, "", "user", &report.individual_stats)?;
+    write_stats(out, "team_", "team", &report.team_stats)
+}
+
+fn gauge(out: &mut dyn Write, name: &str, help: &str, value: impl Display) -> io::Result<()> {
+    writeln!(out, "# HELP pbmetric_{name} {help}.")?;
+    writeln!(out, "# TYPE pbmetric_{name} gauge")?;
+    writeln!(out, "pbmetric_{name} {value}")
+}
+
+fn write_stats(
+    out: &mut dyn Write,
+    prefix: &str,
+    label: &str,
+    stats: &BTreeMap<String, IndividualStats>,
+) -> io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    for (name, help, stat) in STATS {
+        writeln!(out, "# HELP pbmetric_{prefix}{name} {help} per {label}.")?;
+        writeln!(out, "# TYPE pbmetric_{prefix}{name} gauge")?;
+        for (key, stats) in stats {
+            writeln!(
+                out,
+                "pbmetric_{prefix}{name}{{{label}=\"{}\"}} {}",
+                escape(key),
+                stat(stats)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,107 @@
+use reqwest::blocking::ClientBuilder;
+use reqwest::{NoProxy, Proxy};
+use serde::Deserialize;
+
+/// A proxy for requests to GitHub and for git to fetch repositories through.
+///
+/// Without one, both still follow the standard `HTTPS_PROXY` and `NO_PROXY`
+/// environment variables.
+#[derive(Clone, Default, Deserialize)]
+pub struct ProxyConfig {
+    pub url: String,
+    /// Hosts to connect to directly, including their subdomains, or "*" for
+    /// all hosts.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Makes the clients built by `builder` go through the proxy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL of the proxy is invalid.
+    pub fn apply(&self, builder: ClientBuilder) -> reqwest::Result<ClientBuilder> {
+        let proxy = Proxy::all(&self.url)?.no_proxy(NoProxy::from_string(&self.no_proxy.join(",")));
+        Ok(builder.proxy(proxy))
+    }
+
+    /// Returns the proxy to reach `url` through, or `None` if its host is
+    /// exempt. `url` may also be an scp-like git address, as in
+    /// `git@github.com:petabi/pbmetric.git`.
+    #[must_use]
+    pub fn for_url(&self, url: &str) -> Option<&str> {
+        let host = host(url);
+        let exempt = self.no_proxy.iter().any(|entry| {
+            let entry = entry.trim().trim_start_matches('.');
+            entry == "*"
+                || host.is_some_and(|host| {
+                    host.eq_ignore_ascii_case(entry)
+                        || host
+                            .to_ascii_lowercase()
+                            .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+                })
+        });
+        if exempt {
+            None
+        } else {
+            Some(&self.url)
+        }
+    }
+}
+
+/// Extracts the host from `url`, leaving out any user and port.
+fn host(url: &str) -> Option<&str> {
+    let authority = match url.split_once("://") {
+        Some((_, rest)) => rest.split('/').next()?,
+        None => url.split_once(':')?.0,
+    };
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next()?;
+    Some(host).filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_in_no_proxy_are_exempt() {
+        let proxy = ProxyConfig {
+            url: "http://proxy.example.com:3128".to_string(),
+            no_proxy: vec![".internal.example.com".to_string(), "localhost".to_string()],
+        };
+        assert_eq!(
+            proxy.for_url("https://github.com/petabi/pbmetric.git"),
+            Some("http://proxy.example.com:3128")
+        );
+        assert_eq!(
+            proxy.for_url("git@github.com:petabi/pbmetric.git"),
+            Some("http://proxy.example.com:3128")
+        );
+        assert_eq!(
+            proxy.for_url("https://git.internal.example.com/pbmetric.git"),
+            None
+        );
+        assert_eq!(
+            proxy.for_url("ssh://git@Internal.Example.com:2222/pbmetric.git"),
+            None
+        );
+        assert_eq!(proxy.for_url("http://localhost:8080/pbmetric.git"), None);
+        assert_eq!(
+            proxy.for_url("https://notinternal.example.com/pbmetric.git"),
+            Some("http://proxy.example.com:3128")
+        );
+
+        let proxy = ProxyConfig {
+            no_proxy: vec!["*".to_string()],
+            ..proxy
+        };
+        assert_eq!(
+            proxy.for_url("https://github.com/petabi/pbmetric.git"),
+            None
+        );
+    }
+}
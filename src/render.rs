@@ -0,0 +1,349 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Html,
+    Markdown,
+    Json,
+    Prometheus,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 4] = ["html", "markdown", "json", "prometheus"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Self::Html),
+            "markdown" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            "prometheus" => Ok(Self::Prometheus),
+            _ => Err(format!("unknown report format: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Html => f.write_str("html"),
+            Self::Markdown => f.write_str("markdown"),
+            Self::Json => f.write_str("json"),
+            Self::Prometheus => f.write_str("prometheus"),
+        }
+    }
+}
+
+/// Writes the building blocks of a report, such as headings and (nested)
+/// lists, in a particular document format.
+pub trait Renderer {
+    fn begin_document(&mut self) -> io::Result<()>;
+    fn end_document(&mut self, footer: &str) -> io::Result<()>;
+    fn heading(&mut self, text: &str) -> io::Result<()>;
+    fn begin_list(&mut self) -> io::Result<()>;
+    fn end_list(&mut self) -> io::Result<()>;
+    fn item(&mut self, text: &str) -> io::Result<()>;
+    fn link(&self, text: &str, url: &str) -> String;
+    /// Writes an SVG image, or nothing in formats that cannot show one.
+    fn chart(&mut self, svg: &str) -> io::Result<()>;
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; line-height: 1.4; color: #24292f; }
+h2 { border-bottom: 1px solid #d0d7de; padding-bottom: 0.2em; }
+ul { padding-left: 1.5em; }
+footer { margin-top: 2em; font-size: small; color: #57606a; }
+";
+
+/// Writes an HTML document with a table of contents linking to its sections.
+///
+/// The sections of a document are buffered until its end, when the headings
+/// for the table of contents are known. Those written outside a document go
+/// out directly.
+pub struct Html<'a> {
+    out: &'a mut dyn Write,
+    body: Option<Vec<u8>>,
+    headings: Vec<(String, String)>,
+    /// Whether each open list has an item to close.
+    open_items: Vec<bool>,
+}
+
+impl<'a> Html<'a> {
+    pub fn new(out: &'a mut dyn Write) -> Self {
+        Self {
+            out,
+            body: None,
+            headings: Vec::new(),
+            open_items: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.body {
+            Some(body) => body.write_all(bytes),
+            None => self.out.write_all(bytes),
+        }
+    }
+}
+
+impl Renderer for Html<'_> {
+    fn begin_document(&mut self) -> io::Result<()> {
+        self.body = Some(Vec::new());
+        self.headings.clear();
+        Ok(())
+    }
+
+    fn end_document(&mut self, footer: &str) -> io::Result<()> {
+        let body = self.body.take().unwrap_or_default();
+        self.out
+            .write_all(b"<html><head><meta charset=\"utf-8\">")?;
+        self.out
+            .write_all(format!("<style>\n{STYLE}</style></head><body>\n").as_bytes())?;
+        if !self.headings.is_empty() {
+            self.out.write_all(b"<nav>\n<ul>\n")?;
+            for (id, text) in &self.headings {
+                self.out
+                    .write_all(format!("<li><a href=\"#{id}\">{text}</a></li>\n").as_bytes())?;
+            }
+            self.out.write_all(b"</ul>\n</nav>\n")?;
+        }
+        self.out.write_all(&body)?;
+        if !self.headings.is_empty() {
+            self.out.write_all(b"</section>\n")?;
+        }
+        self.out
+            .write_all(format!("<footer>{footer}</footer>").as_bytes())?;
+        self.out.write_all(b"</body></html>")
+    }
+
+    fn heading(&mut self, text: &str) -> io::Result<()> {
+        if self.body.is_none() {
+            return self.write(format!("<h2>{text}</h2>\n").as_bytes());
+        }
+        if !self.headings.is_empty() {
+            self.write(b"</section>\n")?;
+        }
+        let id = anchor(text);
+        self.write(format!("<section id=\"{id}\">\n<h2>{text}</h2>\n").as_bytes())?;
+        self.headings.push((id, text.to_string()));
+        Ok(())
+    }
+
+    fn begin_list(&mut self) -> io::Result<()> {
+        self.open_items.push(false);
+        self.write(b"<ul>\n")
+    }
+
+    fn end_list(&mut self) -> io::Result<()> {
+        if self.open_items.pop() == Some(true) {
+            self.write(b"</li>\n")?;
+        }
+        self.write(b"</ul>\n")
+    }
+
+    fn item(&mut self, text: &str) -> io::Result<()> {
+        let open = self
+            .open_items
+            .last_mut()
+            .map(|open| std::mem::replace(open, true));
+        if open == Some(true) {
+            self.write(b"</li>\n")?;
+        }
+        self.write(format!("<li>{text}\n").as_bytes())
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        format!(r#"<a href="{url}">{text}</a>"#)
+    }
+
+    fn chart(&mut self, svg: &str) -> io::Result<()> {
+        self.write(format!("<figure>{svg}</figure>\n").as_bytes())
+    }
+}
+
+/// Turns a heading into an anchor of lowercase letters, digits, and hyphens,
+/// such as "changes-in-the-past-week".
+fn anchor(heading: &str) -> String {
+    heading
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub struct Markdown<'a> {
+    out: &'a mut dyn Write,
+    depth: usize,
+    slack: bool,
+}
+
+impl<'a> Markdown<'a> {
+    pub fn new(out: &'a mut dyn Write) -> Self {
+        Self {
+            out,
+            depth: 0,
+            slack: false,
+        }
+    }
+
+    /// Creates a renderer for Slack's mrkdwn, which has no headings and uses
+    /// its own syntax for links.
+    pub fn slack(out: &'a mut dyn Write) -> Self {
+        Self {
+            out,
+            depth: 0,
+            slack: true,
+        }
+    }
+}
+
+impl Renderer for Markdown<'_> {
+    fn begin_document(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_document(&mut self, footer: &str) -> io::Result<()> {
+        if self.slack {
+            self.out.write_all(format!("{footer}\n").as_bytes())
+        } else {
+            self.out.write_all(format!("---\n\n{footer}\n").as_bytes())
+        }
+    }
+
+    fn heading(&mut self, text: &str) -> io::Result<()> {
+        if self.slack {
+            self.out.write_all(format!("*{text}*\n").as_bytes())
+        } else {
+            self.out.write_all(format!("## {text}\n\n").as_bytes())
+        }
+    }
+
+    fn begin_list(&mut self) -> io::Result<()> {
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn end_list(&mut self) -> io::Result<()> {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn item(&mut self, text: &str) -> io::Result<()> {
+        let indent = "  ".repeat(self.depth.saturating_sub(1));
+        self.out.write_all(format!("{indent}- {text}\n").as_bytes())
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        if self.slack {
+            format!("<{url}|{text}>")
+        } else {
+            format!("[{text}]({url})")
+        }
+    }
+
+    fn chart(&mut self, _svg: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(format: Option<Format>) -> String {
+        let mut out = Vec::new();
+        let mut renderer: Box<dyn Renderer> = match format {
+            Some(Format::Html) => Box::new(Html::new(&mut out)),
+            Some(_) => Box::new(Markdown::new(&mut out)),
+            None => Box::new(Markdown::slack(&mut out)),
+        };
+        renderer.begin_document().unwrap();
+        renderer.heading("Changes in the Past Week").unwrap();
+        renderer.begin_list().unwrap();
+        renderer.item("Created: 2").unwrap();
+        renderer.begin_list().unwrap();
+        renderer.item("alice: 2").unwrap();
+        renderer.end_list().unwrap();
+        renderer.end_list().unwrap();
+        let link = renderer.link("pbmetric", "https://github.com/petabi/pbmetric");
+        renderer
+            .end_document(&format!("Generated by {link}"))
+            .unwrap();
+        drop(renderer);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn markdown_document() {
+        assert_eq!(
+            sample(Some(Format::Markdown)),
+            "## Changes in the Past Week\n\n\
+             - Created: 2\n  \
+               - alice: 2\n\
+             \n\
+             ---\n\n\
+             Generated by [pbmetric](https://github.com/petabi/pbmetric)\n"
+        );
+    }
+
+    #[test]
+    fn slack_document() {
+        assert_eq!(
+            sample(None),
+            "*Changes in the Past Week*\n\
+             - Created: 2\n  \
+               - alice: 2\n\
+             \n\
+             Generated by <https://github.com/petabi/pbmetric|pbmetric>\n"
+        );
+    }
+
+    #[test]
+    fn html_document() {
+        let html = sample(Some(Format::Html));
+        assert!(html.starts_with("<html><head><meta charset=\"utf-8\"><style>\n"));
+        assert!(html.contains(
+            "<nav>\n<ul>\n\
+             <li><a href=\"#changes-in-the-past-week\">Changes in the Past Week</a></li>\n\
+             </ul>\n</nav>\n"
+        ));
+        assert!(html.contains(
+            "<section id=\"changes-in-the-past-week\">\n\
+             <h2>Changes in the Past Week</h2>\n\
+             <ul>\n<li>Created: 2\n<ul>\n<li>alice: 2\n</li>\n</ul>\n</li>\n</ul>\n\
+             </section>\n<footer>"
+        ));
+        assert!(html.ends_with("</footer></body></html>"));
+        assert!(!html.contains("<pre>") && !html.contains("</pre>"));
+    }
+
+    #[test]
+    fn anchors_are_made_of_words() {
+        assert_eq!(
+            anchor("Pull Requests with No Update in Past 3 Days"),
+            "pull-requests-with-no-update-in-past-3-days"
+        );
+        assert_eq!(anchor("Time to Close (All)"), "time-to-close-all");
+    }
+
+    #[test]
+    fn format_names() {
+        for name in Format::NAMES {
+            assert_eq!(name.parse::<Format>().unwrap().to_string(), name);
+        }
+        assert!("pdf".parse::<Format>().is_err());
+    }
+}
@@ -1,20 +1,37 @@
 use std::cmp::{max, Ordering};
-use std::collections::{BTreeMap, HashMap};
-use std::io::Write;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
 use std::path::Path;
-use std::process::exit;
 
-use anyhow::Result;
-use chrono::{DateTime, Duration, Utc};
-use serde::Deserialize;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
 
-use crate::git::{blame_stats, Repo};
-use crate::github;
-use crate::issue::{individual_stats, IndividualStats};
+use crate::chart::issue_chart;
+use crate::git::{
+    blame_stats, has_commits_between, last_tag, normalize_email, tags_between, LineCount, Repo, Tag,
+};
+use crate::github::{self, IssueMetadata, MergedPullRequest, RepoSlug};
+use crate::history::Snapshot;
+use crate::issue::{
+    add_repo_stats, daily_issues, individual_stats, issue_comments_per_login, local_days,
+    median_daily_activity, merged_pull_requests_per_login, most_reacted_issues,
+    recent_issues_per_label, recent_issues_per_login, reviews_per_login, team_stats, time_to_close,
+    total_stats, DailyIssues, IndividualStats, ReactedIssue, TimeToClose,
+};
+use crate::prometheus::write_metrics;
+use crate::render::{Format, Html, Markdown, Renderer};
 
-const EXCLUDE_DEFAULT: [&str; 9] = [
+const EXCLUDE_DEFAULT: [&str; 13] = [
     r"^\.git/",
     r"(^|/)Cargo\.lock$",
+    r"(^|/)dist/",
+    r"(^|/)node_modules/",
+    r"(^|/)third_party/",
+    r"(^|/)vendor/",
     r"\.dat$",
     r"\.log$",
     r"\.pcap$",
@@ -24,291 +41,2727 @@ const EXCLUDE_DEFAULT: [&str; 9] = [
     r"^LICENSE$",
 ];
 
+/// The number of recently created issues listed by their reactions.
+const MOST_REACTED_ISSUES: usize = 5;
+
+const TEST_PATHS_DEFAULT: [&str; 4] = [
+    r"(^|/)tests/",
+    r"(^|/)spec/",
+    r"(^|/)__tests__/",
+    r"_test\.rs$",
+];
+
+/// Returns the exclude patterns shared by all repositories: the built-in
+/// defaults, unless `override_defaults` is set, followed by `exclude`.
+#[must_use]
+pub fn exclude_patterns(exclude: &[String], override_defaults: bool) -> Vec<String> {
+    let mut patterns = if override_defaults {
+        Vec::new()
+    } else {
+        EXCLUDE_DEFAULT
+            .iter()
+            .map(|e| (*e).to_string())
+            .collect::<Vec<String>>()
+    };
+    patterns.extend(exclude.iter().cloned());
+    patterns
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// The number of days covered by the individual statistics.
+    pub lookback_days: u32,
+    /// The number of days covered by the recent changes.
+    pub recent_days: u32,
+    /// The number of hours without an update after which an assigned issue
+    /// is considered stale.
+    pub stale_hours: u32,
+    /// The number of hours without an update after which an open pull request
+    /// is considered stale.
+    pub stale_pull_request_hours: u32,
+    /// GitHub logins, such as bots, left out of the statistics.
+    pub ignore_logins: Vec<String>,
+    /// Title prefixes, matched case-insensitively, marking open pull requests
+    /// as work in progress to leave out, like drafts.
+    pub wip_prefixes: Vec<String>,
+    /// Team names mapped to the usernames of their members.
+    pub teams: BTreeMap<String, Vec<String>>,
+    /// The ranking of top contributors.
+    pub leaderboard: Leaderboard,
+    /// Labels an assigned issue needs one of to be listed as stale. All
+    /// issues are listed if empty.
+    pub include_labels: Vec<String>,
+    /// Labels that keep an assigned issue from being listed as stale, taking
+    /// precedence over `include_labels`.
+    pub exclude_labels: Vec<String>,
+    /// Regular expressions matching the paths of test code, whose lines are
+    /// counted apart from production code.
+    pub test_paths: Vec<String>,
+    /// The timezone, as an IANA name such as "Asia/Seoul", that dates are
+    /// shown in and days begin in.
+    pub timezone: Tz,
+    /// Breaks down the statistics of each user by repository.
+    pub per_repo: bool,
+    /// The usernames to list individual statistics and leaderboard ranks
+    /// for, such as those of a team's members. Everyone is listed if empty.
+    /// Others still count toward the team and overall totals.
+    pub report_users: Vec<String>,
+    /// The least activity, the sum of lines, issues, pull requests, reviews,
+    /// and comments, for a user to be listed individually.
+    pub min_activity: f64,
+    /// The order users are listed in the individual statistics.
+    pub sort_by: SortBy,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            lookback_days: 90,
+            recent_days: 7,
+            stale_hours: 24,
+            stale_pull_request_hours: 72,
+            ignore_logins: ["dependabot[bot]", "github-actions[bot]", "renovate[bot]"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            wip_prefixes: vec!["[WIP]".to_string()],
+            teams: BTreeMap::new(),
+            leaderboard: Leaderboard::default(),
+            include_labels: Vec::new(),
+            exclude_labels: Vec::new(),
+            test_paths: TEST_PATHS_DEFAULT.iter().map(ToString::to_string).collect(),
+            timezone: Tz::UTC,
+            per_repo: false,
+            report_users: Vec::new(),
+            min_activity: 0.,
+            sort_by: SortBy::default(),
+        }
+    }
+}
+
+/// The number of top contributors to rank, and what to rank them by.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Leaderboard {
+    pub metric: LeaderboardMetric,
+    /// The number of contributors listed. The leaderboard is left out if
+    /// zero.
+    pub size: usize,
+}
+
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Self {
+            metric: LeaderboardMetric::default(),
+            size: 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardMetric {
+    #[default]
+    LinesContributed,
+    IssuesCompleted,
+    PullRequestsMerged,
+}
+
+impl LeaderboardMetric {
+    #[allow(clippy::cast_precision_loss)]
+    fn value(self, stats: &IndividualStats) -> f64 {
+        match self {
+            Self::LinesContributed => stats.lines_contributed,
+            Self::IssuesCompleted => stats.issues_completed,
+            Self::PullRequestsMerged => stats.merged_merge_requests_opened as f64,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::LinesContributed => "Lines Contributed",
+            Self::IssuesCompleted => "Issues Completed",
+            Self::PullRequestsMerged => "Pull Requests Merged",
+        }
+    }
+}
+
+/// The order users are listed in the individual statistics.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Username,
+    Lines,
+    IssuesCompleted,
+    Prs,
+}
+
+impl SortBy {
+    /// Returns the users in `individual_stats` in this order, which is
+    /// descending unless by username. Ties are broken by username.
+    fn sorted(
+        self,
+        individual_stats: &BTreeMap<String, IndividualStats>,
+    ) -> Vec<(&String, &IndividualStats)> {
+        let mut sorted = individual_stats.iter().collect::<Vec<_>>();
+        let metric = match self {
+            Self::Username => return sorted,
+            Self::Lines => LeaderboardMetric::LinesContributed,
+            Self::IssuesCompleted => LeaderboardMetric::IssuesCompleted,
+            Self::Prs => LeaderboardMetric::PullRequestsMerged,
+        };
+        // The sort is stable, so ties stay in the order of their usernames.
+        sorted.sort_by(|(_, a), (_, b)| metric.value(b).total_cmp(&metric.value(a)));
+        sorted
+    }
+}
+
 #[derive(Default, Deserialize)]
 pub struct GithubConfig {
-    token: String,
+    #[serde(default)]
+    pub token: github::Tokens,
     repositories: Vec<String>,
     account: HashMap<String, String>,
+    /// How long to reuse a response to the same query, in minutes. Responses
+    /// are not cached if zero, which is the default.
+    #[serde(default)]
+    pub cache_ttl_minutes: u64,
+    /// How to present repositories, keyed by "owner/name" or "name", such as
+    /// those mirrored or on GitHub Enterprise.
+    #[serde(default)]
+    repo_links: HashMap<String, RepoLink>,
+}
+
+impl GithubConfig {
+    /// Returns the repositories to query, as "owner/name".
+    #[must_use]
+    pub fn repositories(&self) -> &[String] {
+        &self.repositories
+    }
+
+    /// Turns each repository given as just "name", as configurations written
+    /// before "owner/name" was required do, into one owned by petabi.
+    pub fn qualify_repositories(&mut self) {
+        for repo in &mut self.repositories {
+            if !repo.contains('/') {
+                *repo = full_name(repo);
+            }
+        }
+    }
+
+    /// Returns how to present each repository configured so, keyed by
+    /// "owner/name".
+    #[must_use]
+    pub fn repo_links(&self) -> BTreeMap<String, RepoLink> {
+        self.repo_links
+            .iter()
+            .map(|(repo, link)| (full_name(repo), link.clone()))
+            .collect()
+    }
+}
+
+/// How a repository is named and linked to in reports, in place of its
+/// "owner/name" and its page on github.com.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct RepoLink {
+    pub display_name: Option<String>,
+    /// The page of the repository, under which its issues and pull requests
+    /// are.
+    pub html_url: Option<String>,
+}
+
+/// Restricts `github` and `repos` to the repositories in `names`, each given
+/// as "owner/name" or "name", for a report on some of them only. A checkout
+/// in `repos` is selected by its name or by the GitHub repository it is
+/// cloned from.
+///
+/// # Errors
+///
+/// Returns an error if a repository in `names` is in neither `github` nor
+/// `repos`.
+pub fn select_repos(
+    names: &[String],
+    github: &mut GithubConfig,
+    repos: &mut BTreeMap<String, Repo>,
+) -> Result<()> {
+    let full_names = names.iter().map(|name| full_name(name)).collect::<Vec<_>>();
+    let is_checkout_of = |checkout: &str, repo: &Repo, i: usize| {
+        checkout == names[i] || full_name_of(checkout, &repo.url) == full_names[i]
+    };
+    for (i, name) in names.iter().enumerate() {
+        let on_github = github
+            .repositories
+            .iter()
+            .any(|repo| full_name(repo) == full_names[i]);
+        let checked_out = repos
+            .iter()
+            .any(|(checkout, repo)| is_checkout_of(checkout, repo, i));
+        if !on_github && !checked_out {
+            bail!("repository \"{name}\" is not in the configuration");
+        }
+    }
+    github
+        .repositories
+        .retain(|repo| full_names.contains(&full_name(repo)));
+    repos.retain(|checkout, repo| (0..names.len()).any(|i| is_checkout_of(checkout, repo, i)));
+    Ok(())
 }
 
-#[allow(clippy::cast_possible_truncation)]
+/// The numbers and lists making up a report.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub since: DateTime<Utc>,
+    pub asof: DateTime<Utc>,
+    pub timezone: Tz,
+    pub lookback_days: u32,
+    pub recent_days: u32,
+    pub stale_hours: u32,
+    pub stale_pull_request_hours: u32,
+    pub pull_requests: Vec<github::PullRequest>,
+    pub stale_pull_requests: Vec<github::PullRequest>,
+    pub stale_issues: Vec<github::Issue>,
+    pub weekly: WeeklyChanges,
+    pub time_to_close: Option<TimeToClose>,
+    pub time_to_close_by: BTreeMap<String, TimeToClose>,
+    /// The issues created and closed on each day of the window.
+    pub daily_issues: Vec<DailyIssues>,
+    pub individual_stats: BTreeMap<String, IndividualStats>,
+    pub team_stats: BTreeMap<String, IndividualStats>,
+    /// The sums of `individual_stats`, leaving out lines by unknown emails.
+    pub totals: IndividualStats,
+    pub leaderboard: Leaderboard,
+    /// The least activity for a user to be listed individually.
+    pub min_activity: f64,
+    pub sort_by: SortBy,
+    pub unknown_emails: Vec<(String, f64)>,
+    /// The tags made in each repository within the window, leaving out the
+    /// repositories without any.
+    pub releases: BTreeMap<String, Vec<Tag>>,
+    /// The repositories without any commit within the window.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inactive_repos: Vec<String>,
+    /// How to present repositories, keyed by "owner/name".
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub repo_links: BTreeMap<String, RepoLink>,
+    /// The numbers of an earlier report to show the changes from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Snapshot>,
+}
+
+/// Issues created and completed in the past week, in total and per user in
+/// descending order, with those created also counted per label.
+#[derive(Debug, Default, Serialize)]
+pub struct WeeklyChanges {
+    pub created: usize,
+    pub created_by: Vec<(String, usize)>,
+    pub created_by_label: Vec<(String, usize)>,
+    /// The created issues with the most reactions.
+    pub most_reacted: Vec<ReactedIssue>,
+    pub completed: i64,
+    pub completed_by: Vec<(String, f32)>,
+}
+
+impl WeeklyChanges {
+    /// Totals the recent changes in `issue_stats`, as counted by
+    /// `recent_issues_per_login`, and breaks them down by username.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::type_complexity)]
+    fn new(
+        issue_stats: &HashMap<String, (usize, usize, f32, usize, f32)>,
+        account_map: &HashMap<String, String>,
+    ) -> Self {
+        let created = issue_stats.values().map(|v| v.3).sum();
+        let mut created_by = issue_stats
+            .iter()
+            .filter(|(_, v)| v.3 != 0)
+            .map(|(login, v)| (v.3, account_map.get(login).unwrap_or(login)))
+            .collect::<Vec<(usize, &String)>>();
+        created_by.sort();
+        let completed = issue_stats.values().map(|v| v.4).sum::<f32>().round() as i64;
+        let mut completed_by = issue_stats
+            .iter()
+            .filter(|(_, v)| v.4 != 0.)
+            .map(|(login, v)| (v.4, account_map.get(login).unwrap_or(login)))
+            .collect::<Vec<(f32, &String)>>();
+        completed_by.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less));
+        Self {
+            created,
+            created_by: created_by
+                .into_iter()
+                .rev()
+                .map(|(count, username)| (username.clone(), count))
+                .collect(),
+            created_by_label: Vec::new(),
+            most_reacted: Vec::new(),
+            completed,
+            completed_by: completed_by
+                .into_iter()
+                .rev()
+                .map(|(count, username)| (username.clone(), count))
+                .collect(),
+        }
+    }
+}
+
+/// Data fetched from GitHub for a report.
+pub struct GithubData {
+    pub open_pull_requests: Vec<github::PullRequest>,
+    pub assigned_issues: Vec<github::Issue>,
+    pub issue_metadata: Vec<IssueMetadata>,
+    pub merged_pull_requests: Vec<MergedPullRequest>,
+    pub reviews: Vec<github::Review>,
+    pub issue_comments: Vec<github::IssueComment>,
+}
+
+impl GithubData {
+    /// Fetches the data for a report covering `since` and later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query fails or its response is malformed.
+    pub fn fetch(client: &github::Client, repos: &[String], since: &DateTime<Utc>) -> Result<Self> {
+        Ok(Self {
+            open_pull_requests: client.open_pull_requests(repos)?,
+            assigned_issues: client.assigned_issues(repos)?,
+            issue_metadata: client.issue_metadata_since(repos, since)?,
+            merged_pull_requests: client.merged_pull_requests(repos, since)?,
+            reviews: client.reviews(repos, since)?,
+            issue_comments: client.issue_comments(repos, since)?,
+        })
+    }
+}
+
+/// Collects the data for a report covering `since` to `asof` from git
+/// repositories and GitHub, which is accessed through `github_api`. The
+/// progress of scanning each repository is shown on stderr if
+/// `show_progress` is set and stderr is a terminal.
+///
+/// # Errors
+///
+/// Returns an error if fetching the data from GitHub fails, or if scanning a
+/// repository or reading its tags fails, such as when a pattern in `exclude`
+/// or the test paths is invalid or a git command fails.
 #[allow(clippy::too_many_arguments)]
-#[allow(clippy::too_many_lines)]
 pub fn agenda<P: AsRef<Path>>(
-    out: &mut dyn Write,
+    report_conf: &ReportConfig,
     github_conf: &GithubConfig,
+    github_api: &github::Client,
     repo_root: P,
     repos: &BTreeMap<String, Repo>,
+    exclude: &[String],
     email_map: &BTreeMap<String, String>,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+    show_progress: bool,
+) -> Result<Report> {
+    // Scanning takes a while, so a missing token should be found out first.
+    github_api.check_tokens(&github_conf.repositories)?;
+    let loc_per_repo = repo_loc(
+        repo_root.as_ref(),
+        repos,
+        exclude,
+        &report_conf.test_paths,
+        since,
+        asof,
+        show_progress,
+    )?;
+    let inactive_repos = repos
+        .keys()
+        .filter(|name| !loc_per_repo.contains_key(*name))
+        .cloned()
+        .collect();
+    let mut total_loc = HashMap::<String, LineCount>::new();
+    for loc in loc_per_repo.values() {
+        for (email, loc) in loc {
+            *total_loc.entry(email.clone()).or_default() += *loc;
+        }
+    }
+
+    let releases = repo_tags(repo_root.as_ref(), repos, since, asof)?;
+
+    let github_data = GithubData::fetch(github_api, &github_conf.repositories, since)?;
+    let mut report = Report::new(
+        report_conf,
+        github_data,
+        total_loc,
+        &github_conf.account,
+        email_map,
+        since,
+        asof,
+    );
+    report.releases = releases;
+    report.inactive_repos = inactive_repos;
+    report.repo_links = github_conf.repo_links();
+    if report_conf.per_repo {
+        for (name, loc) in loc_per_repo {
+            let mut repo_stats = BTreeMap::new();
+            add_lines_contributed(&mut repo_stats, loc, email_map);
+            add_repo_stats(
+                &mut report.individual_stats,
+                &full_name_of(&name, &repos[&name].url),
+                &repo_stats,
+            );
+        }
+    }
+    Ok(report)
+}
+
+/// Returns the commit date of the most recent tag in the checkouts of `repos`
+/// under `repo_root`, for a report covering everything since the last
+/// release, or `None` if none of them has a tag.
+///
+/// # Errors
+///
+/// Returns an error if a git command fails.
+pub fn last_release<P: AsRef<Path>>(
+    repo_root: P,
+    repos: &BTreeMap<String, Repo>,
+) -> io::Result<Option<DateTime<Utc>>> {
+    let mut last = None;
+    for name in repos.keys() {
+        if let Some(tag) = last_tag(repo_root.as_ref().join(name))? {
+            debug!("{name}: last tagged {} on {}", tag.name, tag.date);
+            last = last.max(Some(tag.date));
+        }
+    }
+    Ok(last)
+}
+
+/// Returns the start of the window covered by the individual statistics:
+/// `lookback_days` before `asof`, but no earlier than `epoch`.
+#[must_use]
+pub fn report_since(
     asof: &DateTime<Utc>,
     epoch: Option<&DateTime<Utc>>,
-) -> Result<()> {
-    out.write_all(b"<html><body>")?;
+    lookback_days: u32,
+) -> DateTime<Utc> {
+    let window_ago = days_before(asof, lookback_days);
+    match epoch {
+        Some(epoch) => *max(epoch, &window_ago),
+        None => window_ago,
+    }
+}
 
-    let quarter_ago = *asof - Duration::try_days(90).expect("valid constant value");
-    let since = match epoch {
-        Some(epoch) => max(epoch, &quarter_ago),
-        None => &quarter_ago,
+/// Returns the time `days` days before `asof`.
+fn days_before(asof: &DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    Duration::try_days(i64::from(days))
+        .and_then(|window| asof.checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Returns the start of the day in `timezone` that is `days` days before the
+/// day of `asof`, so that a window of days is made of whole local days.
+fn start_of_day_before(asof: &DateTime<Utc>, days: u32, timezone: Tz) -> DateTime<Utc> {
+    let Some(date) = asof
+        .with_timezone(&timezone)
+        .date_naive()
+        .checked_sub_days(Days::new(days.into()))
+    else {
+        return DateTime::<Utc>::MIN_UTC;
     };
+    // Midnight may be skipped by a daylight saving transition.
+    timezone
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+        .map_or_else(
+            || days_before(asof, days),
+            |start| start.with_timezone(&Utc),
+        )
+}
 
-    let total_loc = repo_loc(repo_root.as_ref(), repos, since, asof);
+/// Returns the time `hours` hours before `asof`.
+fn hours_before(asof: &DateTime<Utc>, hours: u32) -> DateTime<Utc> {
+    Duration::try_hours(i64::from(hours))
+        .and_then(|window| asof.checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+impl Report {
+    /// Computes a report from the data collected for the window between
+    /// `since` and `asof`.
+    #[must_use]
+    #[allow(clippy::too_many_lines)]
+    pub fn new(
+        config: &ReportConfig,
+        github_data: GithubData,
+        total_loc: HashMap<String, LineCount>,
+        account_map: &HashMap<String, String>,
+        email_map: &BTreeMap<String, String>,
+        since: &DateTime<Utc>,
+        asof: &DateTime<Utc>,
+    ) -> Self {
+        let stats_per_repo = if config.per_repo {
+            github_repo_stats(
+                &github_data,
+                account_map,
+                &config.ignore_logins,
+                since,
+                asof,
+            )
+        } else {
+            BTreeMap::new()
+        };
+        let pull_requests = github_data
+            .open_pull_requests
+            .into_iter()
+            .filter(|pr| {
+                !pr.is_draft && !is_wip(&pr.title, &config.wip_prefixes) && pr.created_at <= *asof
+            })
+            .map(|mut pr| {
+                pr.reviewers = usernames(pr.reviewers, account_map);
+                pr.assignees = usernames(pr.assignees, account_map);
+                pr
+            })
+            .collect::<Vec<_>>();
+        let stale_pr_since = hours_before(asof, config.stale_pull_request_hours);
+        let stale_pull_requests = pull_requests
+            .iter()
+            .filter(|pr| pr.updated_at <= stale_pr_since)
+            .cloned()
+            .collect();
+
+        let stale_since = hours_before(asof, config.stale_hours);
+        let stale_issues = github_data
+            .assigned_issues
+            .into_iter()
+            .filter(|issue| issue.updated_at <= stale_since && labels_match(issue, config))
+            .map(|mut issue| {
+                issue.assignees = usernames(issue.assignees, account_map);
+                issue
+            })
+            .collect();
+
+        let recent_since = start_of_day_before(asof, config.recent_days, config.timezone);
+        let github_issue_stats = recent_issues_per_login(
+            &github_data.issue_metadata,
+            &config.ignore_logins,
+            since,
+            &recent_since,
+        );
+        let mut weekly = WeeklyChanges::new(&github_issue_stats, account_map);
+        weekly.created_by_label = recent_issues_per_label(
+            &github_data.issue_metadata,
+            &config.ignore_logins,
+            &recent_since,
+        );
+        weekly.most_reacted = most_reacted_issues(
+            &github_data.issue_metadata,
+            &config.ignore_logins,
+            &recent_since,
+            MOST_REACTED_ISSUES,
+        );
+
+        let (time_to_close, time_to_close_by) = time_to_close(
+            &github_data.issue_metadata,
+            account_map,
+            &config.ignore_logins,
+            since,
+            asof,
+        );
+        let daily_issues = daily_issues(&github_data.issue_metadata, since, asof, config.timezone);
+        let mut individual_stats = individual_stats(
+            &github_data.issue_metadata,
+            &merged_pull_requests_per_login(
+                &github_data.merged_pull_requests,
+                &config.ignore_logins,
+                since,
+                asof,
+            ),
+            &reviews_per_login(&github_data.reviews, &config.ignore_logins, since, asof),
+            &issue_comments_per_login(
+                &github_data.issue_comments,
+                &config.ignore_logins,
+                since,
+                asof,
+            ),
+            account_map,
+            &config.ignore_logins,
+            since,
+            asof,
+        );
+        for (username, medians) in median_daily_activity(
+            &github_data.issue_metadata,
+            &github_data.merged_pull_requests,
+            &github_data.reviews,
+            account_map,
+            &config.ignore_logins,
+            since,
+            asof,
+            config.timezone,
+        ) {
+            if let Some(stats) = individual_stats.get_mut(&username) {
+                stats.median_per_day = medians;
+            }
+        }
+        for (repo, repo_stats) in &stats_per_repo {
+            add_repo_stats(&mut individual_stats, repo, repo_stats);
+        }
+        let unknown_emails = add_lines_contributed(&mut individual_stats, total_loc, email_map);
+        let team_stats = team_stats(&individual_stats, &config.teams);
+        let totals = total_stats(&individual_stats);
+        if !config.report_users.is_empty() {
+            individual_stats.retain(|username, _| config.report_users.contains(username));
+        }
 
-    let github_api = github::Client::new(&github_conf.token);
+        Self {
+            since: *since,
+            asof: *asof,
+            timezone: config.timezone,
+            lookback_days: u32::try_from((*asof - *since).num_days()).unwrap_or_default(),
+            recent_days: config.recent_days,
+            stale_hours: config.stale_hours,
+            stale_pull_request_hours: config.stale_pull_request_hours,
+            pull_requests,
+            stale_pull_requests,
+            stale_issues,
+            weekly,
+            time_to_close,
+            time_to_close_by,
+            daily_issues,
+            individual_stats,
+            team_stats,
+            totals,
+            leaderboard: config.leaderboard,
+            min_activity: config.min_activity,
+            sort_by: config.sort_by,
+            unknown_emails,
+            releases: BTreeMap::new(),
+            inactive_repos: Vec::new(),
+            repo_links: BTreeMap::new(),
+            previous: None,
+        }
+    }
 
-    let pull_requests = github_api.open_pull_requests(&github_conf.repositories)?;
-    write_pull_request_section(out, &pull_requests, &github_conf.account)?;
+    /// Returns the date of `time` in the timezone of the report.
+    #[must_use]
+    pub fn date(&self, time: &DateTime<Utc>) -> NaiveDate {
+        time.with_timezone(&self.timezone).date_naive()
+    }
+}
 
-    let github_issues = github_api.assigned_stale_issues(&github_conf.repositories, asof)?;
-    if !github_issues.is_empty() {
-        write_issues_section(out, &github_issues, &github_conf.account)?;
+/// Computes the statistics of each user in each repository that has
+/// activity in `github_data`, keyed by "owner/name".
+fn github_repo_stats(
+    github_data: &GithubData,
+    account_map: &HashMap<String, String>,
+    ignore_logins: &[String],
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> BTreeMap<String, BTreeMap<String, IndividualStats>> {
+    fn of<T: Clone>(items: &[T], repo: &str, repo_of: fn(&T) -> &str) -> Vec<T> {
+        items
+            .iter()
+            .filter(|item| full_name(repo_of(item)) == repo)
+            .cloned()
+            .collect()
     }
 
-    let issue_metadata = github_api.issue_metadata_since(&github_conf.repositories, since)?;
-    let week_ago = *asof - Duration::try_weeks(1).expect("valid constant value");
-    let github_issue_stats =
-        github_api.recent_issues_per_login(&github_conf.repositories, since, &week_ago)?;
-    let created_count: usize = github_issue_stats.values().map(|v| v.3).sum();
-    let authors = github_issue_stats
-        .iter()
-        .map(|(k, v)| (k.clone(), v.3))
-        .collect::<BTreeMap<String, usize>>();
-    let closed_count = github_issue_stats
-        .values()
-        .map(|v| v.4)
-        .sum::<f32>()
-        .round() as i64;
-    let assignees = github_issue_stats
+    let repos = github_data
+        .issue_metadata
         .iter()
-        .map(|(k, v)| (k.clone(), v.4))
-        .collect::<BTreeMap<String, f32>>();
+        .map(|issue| &issue.repo)
+        .chain(github_data.merged_pull_requests.iter().map(|pr| &pr.repo))
+        .chain(github_data.reviews.iter().map(|review| &review.repo))
+        .chain(
+            github_data
+                .issue_comments
+                .iter()
+                .map(|comment| &comment.repo),
+        )
+        .map(|repo| full_name(repo))
+        .collect::<BTreeSet<_>>();
+    repos
+        .into_iter()
+        .map(|repo| {
+            let stats = individual_stats(
+                &of(&github_data.issue_metadata, &repo, |issue| &issue.repo),
+                &merged_pull_requests_per_login(
+                    &of(&github_data.merged_pull_requests, &repo, |pr| &pr.repo),
+                    ignore_logins,
+                    since,
+                    asof,
+                ),
+                &reviews_per_login(
+                    &of(&github_data.reviews, &repo, |review| &review.repo),
+                    ignore_logins,
+                    since,
+                    asof,
+                ),
+                &issue_comments_per_login(
+                    &of(&github_data.issue_comments, &repo, |comment| &comment.repo),
+                    ignore_logins,
+                    since,
+                    asof,
+                ),
+                account_map,
+                ignore_logins,
+                since,
+                asof,
+            );
+            (repo, stats)
+        })
+        .collect()
+}
+
+/// Returns `repo`, configured as "owner/name" or "name", as "owner/name".
+fn full_name(repo: &str) -> String {
+    RepoSlug::from(repo).to_string()
+}
+
+/// Returns the "owner/name" of the repository checked out as `name` from
+/// `url`, taken from the URL if it is on GitHub.
+fn full_name_of(name: &str, url: &str) -> String {
+    url.split_once("github.com")
+        .map(|(_, path)| {
+            path.trim_start_matches([':', '/'])
+                .trim_end_matches('/')
+                .trim_end_matches(".git")
+        })
+        .filter(|path| path.split('/').count() == 2)
+        .map_or_else(|| full_name(name), ToString::to_string)
+}
 
-    out.write_all(b"<h2>Changes in the Past Week</h2>\n<ul>\n")?;
-    out.write_all(format!("<li>Created: {created_count}\n<ul>\n").as_bytes())?;
-    let mut authors = authors
+/// Credits the lines in `total_loc` to the usernames of their authors' emails,
+/// returning the lines by emails without a username, the most first and then
+/// by email, so that the order is the same for the same lines.
+///
+/// An email without its own entry in `email_map` falls back to a wildcard
+/// entry for its domain, such as `*@petabi.com`.
+fn add_lines_contributed(
+    individual_stats: &mut BTreeMap<String, IndividualStats>,
+    total_loc: HashMap<String, LineCount>,
+    email_map: &BTreeMap<String, String>,
+) -> Vec<(String, f64)> {
+    let email_map = email_map
         .iter()
-        .map(|(username, count)| (*count, username))
-        .collect::<Vec<(usize, &String)>>();
-    authors.sort();
-    for (count, username) in authors.iter().rev() {
-        if *count == 0 {
+        .map(|(email, username)| (normalize_email(email), username))
+        .collect::<HashMap<_, _>>();
+    let mut unknown_emails = Vec::new();
+    for (email, loc) in total_loc {
+        let username = email_map.get(&email).or_else(|| {
+            let (_, domain) = email.rsplit_once('@')?;
+            email_map.get(&format!("*@{domain}"))
+        });
+        let Some(&username) = username else {
+            unknown_emails.push((email, loc.total()));
             continue;
-        }
-        let username = github_conf.account.get(*username).unwrap_or(username);
-        out.write_all(format!("<li>{username}: {count}\n").as_bytes())?;
+        };
+        let entry = individual_stats.entry(username.clone()).or_default();
+        entry.lines_contributed += loc.total();
+        entry.test_lines_contributed += loc.test;
+        entry.lines_removed += loc.removed;
     }
-    out.write_all(b"</ul>\n")?;
-    out.write_all(format!("<li>Completed: {closed_count}\n<ul>\n").as_bytes())?;
-    let mut assignees = assignees
-        .iter()
-        .map(|(username, count)| (*count, username))
-        .collect::<Vec<(f32, &String)>>();
-    assignees.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less));
-    for (count, username) in assignees.iter().rev() {
-        if *count == 0. {
-            continue;
+    unknown_emails.sort_by(|(a_email, a_loc), (b_email, b_loc)| {
+        b_loc.total_cmp(a_loc).then_with(|| a_email.cmp(b_email))
+    });
+    unknown_emails
+}
+
+/// Maps logins to usernames, keeping those without a username as they are.
+fn usernames(logins: Vec<String>, account_map: &HashMap<String, String>) -> Vec<String> {
+    logins
+        .into_iter()
+        .map(|login| account_map.get(&login).cloned().unwrap_or(login))
+        .collect()
+}
+
+/// Writes `report` in the given format.
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails.
+pub fn write_report(out: &mut dyn Write, format: Format, report: &Report) -> Result<()> {
+    let mut renderer: Box<dyn Renderer> = match format {
+        Format::Html => Box::new(Html::new(out)),
+        Format::Markdown => Box::new(Markdown::new(out)),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, report)?;
+            out.write_all(b"\n")?;
+            return Ok(());
+        }
+        Format::Prometheus => {
+            write_metrics(out, report)?;
+            return Ok(());
         }
-        let username = github_conf.account.get(*username).unwrap_or(username);
-        out.write_all(format!("<li>{username}: {count:.0}\n").as_bytes())?;
+    };
+    render_report(renderer.as_mut(), report)
+}
+
+/// Writes the sections of `report` through `renderer`.
+pub fn render_report(renderer: &mut dyn Renderer, report: &Report) -> Result<()> {
+    renderer.begin_document()?;
+    let links = &report.repo_links;
+    write_pull_request_section(renderer, &report.pull_requests, links)?;
+    if !report.stale_pull_requests.is_empty() {
+        write_stale_pr_section(
+            renderer,
+            &report.stale_pull_requests,
+            report.stale_pull_request_hours,
+            links,
+        )?;
+    }
+    if !report.stale_issues.is_empty() {
+        write_issues_section(renderer, &report.stale_issues, report.stale_hours, links)?;
+    }
+    write_weekly_section(
+        renderer,
+        &report.weekly,
+        report.recent_days,
+        report.previous.as_ref(),
+        links,
+    )?;
+    write_totals_section(renderer, report)?;
+    write_time_to_close_section(renderer, report)?;
+    if !report.releases.is_empty() {
+        write_release_section(renderer, report)?;
+    }
+    let leaders = leaderboard(&report.individual_stats, report.leaderboard);
+    if !leaders.is_empty() {
+        write_leaderboard_section(renderer, report, &leaders)?;
     }
-    out.write_all(b"</ul>\n</ul>\n")?;
 
-    let pull_requests =
-        github_api.merged_pull_requests_per_login(&github_conf.repositories, since)?;
-    out.write_all(b"\n<h2>Individual Statistics for the Past 90 Days</h2>\n<ul>")?;
-    let mut stats = individual_stats(
-        &issue_metadata,
-        &pull_requests,
-        &github_conf.account,
-        since,
-        asof,
-    );
-    for (email, loc) in &total_loc {
-        let Some(username) = email_map.get(email) else {
+    renderer.heading(&format!(
+        "Individual Statistics for the Past {} Days",
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    let mut hidden = 0;
+    for (username, stats) in report.sort_by.sorted(&report.individual_stats) {
+        if stats.activity() < report.min_activity {
+            hidden += 1;
             continue;
-        };
-        let entry = stats.entry(username.to_string()).or_default();
-        entry.lines_contributed += loc;
+        }
+        let previous = report.previous.as_ref().and_then(|previous| {
+            let stats = previous.individual_stats.get(username)?;
+            Some((
+                stats,
+                local_days(&previous.since, &previous.asof, report.timezone),
+            ))
+        });
+        print_individual_stat(
+            renderer,
+            username,
+            stats,
+            previous,
+            &report.since,
+            &report.asof,
+            report.timezone,
+            links,
+        )?;
+    }
+    if hidden > 0 {
+        renderer.item(&format!(
+            "{hidden} {} with less activity hidden",
+            if hidden == 1 { "user" } else { "users" }
+        ))?;
     }
-    for (username, stats) in stats {
-        print_individual_stat(out, &username, &stats, since, asof)?;
+    renderer.end_list()?;
+    if !report.team_stats.is_empty() {
+        write_team_section(renderer, report)?;
     }
-    out.write_all(b"</ul>\n")?;
-    print_unknown_emails(out, &total_loc, email_map)?;
-    out.write_all(b"</pre>\n")?;
+    print_unknown_emails(renderer, &report.unknown_emails)?;
 
-    out.write_all(
-        format!(
-            r#"<footer>Generated by <a href="https://github.com/petabi/pbmetric">{}</a></footer>"#,
-            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"),)
+    let footer = format!(
+        "Generated by {}",
+        renderer.link(
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+            "https://github.com/petabi/pbmetric"
         )
-        .as_bytes(),
-    )?;
-
-    out.write_all(b"</body></html>")?;
+    );
+    renderer.end_document(&footer)?;
     Ok(())
 }
 
+/// Returns the tags made within the window in each of `repos` checked out
+/// under `root`, leaving out the repositories without any.
+fn repo_tags(
+    root: &Path,
+    repos: &BTreeMap<String, Repo>,
+    since: &DateTime<Utc>,
+    asof: &DateTime<Utc>,
+) -> Result<BTreeMap<String, Vec<Tag>>> {
+    let mut releases = BTreeMap::new();
+    for name in repos.keys() {
+        let tags = tags_between(root.join(name), since, asof)
+            .map_err(|e| anyhow!("cannot read the tags of {name}: {e}"))?;
+        if !tags.is_empty() {
+            releases.insert(name.clone(), tags);
+        }
+    }
+    Ok(releases)
+}
+
+/// Counts the lines in each of `repos` checked out under `root`, leaving out
+/// those without any commit in the window, which are not blamed.
+///
+/// # Errors
+///
+/// Returns an error if a pattern in `exclude` or `test_paths` is invalid or
+/// a git command fails.
 fn repo_loc(
     root: &Path,
     repos: &BTreeMap<String, Repo>,
+    exclude: &[String],
+    test_paths: &[String],
     start_date: &DateTime<Utc>,
     end_date: &DateTime<Utc>,
-) -> HashMap<String, usize> {
-    let mut total_loc = HashMap::new();
+    show_progress: bool,
+) -> Result<BTreeMap<String, HashMap<String, LineCount>>> {
+    let mut loc_per_repo = BTreeMap::new();
     let mut path = root.to_path_buf();
     for (name, repo) in repos {
         path.push(name);
-        println!("Scanning {name}");
-        let mut exclude = EXCLUDE_DEFAULT
-            .iter()
-            .map(|e| (*e).to_string())
-            .collect::<Vec<String>>();
-        if let Some(repo_exclude) = &repo.exclude {
-            exclude.extend(repo_exclude.iter().cloned());
+        if !has_commits_between(&path, start_date, end_date, &repo.branches, repo.blame.date)
+            .map_err(|e| anyhow!("cannot scan {name}: {e}"))?
+        {
+            info!("Skipping {name}, which has no commits in the window");
+            path.pop();
+            continue;
         }
-        let blame_stats = match blame_stats(&path, start_date, end_date, exclude) {
-            Ok(stats) => stats,
-            Err(e) => {
-                eprintln!("cannot scan repositories: {e}");
-                exit(1);
-            }
+        info!("Scanning {name}");
+        // A progress bar on stderr is hidden if stderr is not a terminal.
+        let progress = if show_progress {
+            ProgressBar::new(0)
+                .with_style(
+                    ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len} files")
+                        .expect("valid template")
+                        .progress_chars("=> "),
+                )
+                .with_message(name.clone())
+        } else {
+            ProgressBar::hidden()
         };
-        for (email, loc) in blame_stats {
-            let entry = total_loc.entry(email).or_insert(0);
-            *entry += loc;
+        let mut exclude = exclude.to_vec();
+        if let Some(repo_exclude) = &repo.exclude {
+            exclude.extend(repo_exclude.iter().cloned());
         }
+        let blame_stats = blame_stats(
+            &path,
+            start_date,
+            end_date,
+            exclude,
+            test_paths,
+            &repo.branches,
+            &repo.blame,
+            &progress,
+        )
+        .map_err(|e| anyhow!("cannot scan {name}: {e}"))?;
+        progress.finish_and_clear();
+        loc_per_repo.insert(name.clone(), blame_stats);
         path.pop();
     }
-    total_loc
+    Ok(loc_per_repo)
 }
 
 fn write_pull_request_section(
-    out: &mut dyn Write,
+    renderer: &mut dyn Renderer,
     pull_requests: &[github::PullRequest],
-    account_map: &HashMap<String, String>,
+    links: &BTreeMap<String, RepoLink>,
 ) -> Result<()> {
-    let pull_requests = pull_requests
-        .iter()
-        .filter(|pr| !pr.title.starts_with("[WIP]"))
-        .collect::<Vec<_>>();
     if pull_requests.is_empty() {
         return Ok(());
     }
-    out.write_all(b"<h2>Pull Requests Under Review</h2>\n<ul>")?;
+    renderer.heading("Pull Requests Under Review")?;
+    write_pull_request_list(renderer, pull_requests, links)
+}
+
+/// Returns whether `title` starts with any of `prefixes`, ignoring case.
+fn is_wip(title: &str, prefixes: &[String]) -> bool {
+    let title = title.to_lowercase();
+    prefixes
+        .iter()
+        .any(|prefix| title.starts_with(&prefix.to_lowercase()))
+}
+
+/// Returns whether `issue` has none of the excluded labels and, if any labels
+/// are included, one of them.
+fn labels_match(issue: &github::Issue, config: &ReportConfig) -> bool {
+    if issue
+        .labels
+        .iter()
+        .any(|label| config.exclude_labels.contains(label))
+    {
+        return false;
+    }
+    config.include_labels.is_empty()
+        || issue
+            .labels
+            .iter()
+            .any(|label| config.include_labels.contains(label))
+}
+
+fn write_stale_pr_section(
+    renderer: &mut dyn Renderer,
+    pull_requests: &[github::PullRequest],
+    hours: u32,
+    links: &BTreeMap<String, RepoLink>,
+) -> Result<()> {
+    renderer.heading(&format!(
+        "Pull Requests with No Update in Past {}",
+        hour_period(hours)
+    ))?;
+    write_pull_request_list(renderer, pull_requests, links)
+}
+
+/// Lists pull requests, mentioning their reviewers and assignees.
+fn write_pull_request_list(
+    renderer: &mut dyn Renderer,
+    pull_requests: &[github::PullRequest],
+    links: &BTreeMap<String, RepoLink>,
+) -> Result<()> {
+    renderer.begin_list()?;
     for pr in pull_requests {
-        out.write_all(
-            format!(
-                r#"<li><a href="https://github.com/petabi/{repo}/pull/{num}">{repo}#{num}</a> {}"#,
-                pr.title,
-                repo = pr.repo,
-                num = pr.number
-            )
-            .as_bytes(),
-        )?;
-        for reviewers in &pr.reviewers {
-            let username = account_map.get(reviewers).unwrap_or(reviewers);
-            out.write_all(format!(" @{username}").as_bytes())?;
-        }
-        for assignee in &pr.assignees {
-            let username = account_map.get(assignee).unwrap_or(assignee);
-            out.write_all(format!(" @{username}").as_bytes())?;
-        }
+        renderer.item(&format!(
+            "{} {}{}",
+            github_link(renderer, links, &pr.repo, "pull", pr.number),
+            pr.title,
+            mentions(pr.reviewers.iter().chain(&pr.assignees))
+        ))?;
     }
-    out.write_all(b"</ul>\n")?;
+    renderer.end_list()?;
     Ok(())
 }
 
 fn write_issues_section(
-    out: &mut dyn Write,
+    renderer: &mut dyn Renderer,
     github_issues: &[github::Issue],
-    account_map: &HashMap<String, String>,
+    hours: u32,
+    links: &BTreeMap<String, RepoLink>,
 ) -> Result<()> {
-    out.write_all(b"<h2>Assigned Issues with No Update in Past 24 Hours</h2>\n<ul>")?;
+    renderer.heading(&format!(
+        "Assigned Issues with No Update in Past {}",
+        hour_period(hours)
+    ))?;
+    renderer.begin_list()?;
     for issue in github_issues {
-        out.write_all(format!(r#"<li><a href="https://github.com/petabi/{repo}/issues/{num}">{repo}#{num}</a> {}"#, issue.title, repo = issue.repo, num = issue.number).as_bytes())?;
-        for assignee in &issue.assignees {
-            let username = account_map.get(assignee).unwrap_or(assignee);
-            out.write_all(format!(" @{username}").as_bytes())?;
+        renderer.item(&format!(
+            "{} {}{}",
+            github_link(renderer, links, &issue.repo, "issues", issue.number),
+            issue.title,
+            mentions(&issue.assignees)
+        ))?;
+    }
+    renderer.end_list()?;
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn write_weekly_section(
+    renderer: &mut dyn Renderer,
+    weekly: &WeeklyChanges,
+    days: u32,
+    previous: Option<&Snapshot>,
+    links: &BTreeMap<String, RepoLink>,
+) -> Result<()> {
+    renderer.heading(&format!("Changes in the Past {}", period(days)))?;
+    renderer.begin_list()?;
+    renderer.item(&format!(
+        "Created: {}{}",
+        weekly.created,
+        change(
+            weekly.created as f64,
+            previous.map(|previous| previous.issues_created as f64),
+            0
+        )
+    ))?;
+    renderer.begin_list()?;
+    for (username, count) in &weekly.created_by {
+        renderer.item(&format!("{username}: {count}"))?;
+    }
+    if !weekly.created_by_label.is_empty() {
+        renderer.item("By label")?;
+        renderer.begin_list()?;
+        for (label, count) in &weekly.created_by_label {
+            renderer.item(&format!("{label}: {count}"))?;
+        }
+        renderer.end_list()?;
+    }
+    if !weekly.most_reacted.is_empty() {
+        renderer.item("Most reactions")?;
+        renderer.begin_list()?;
+        for issue in &weekly.most_reacted {
+            renderer.item(&format!(
+                "{} {} ({} reactions)",
+                github_link(renderer, links, &issue.repo, "issues", issue.number),
+                issue.title,
+                issue.reactions
+            ))?;
         }
-        out.write_all(b"\n")?;
+        renderer.end_list()?;
     }
-    out.write_all(b"</ul>\n")?;
+    renderer.end_list()?;
+    renderer.item(&format!(
+        "Completed: {}{}",
+        weekly.completed,
+        change(
+            weekly.completed as f64,
+            previous.map(|previous| previous.issues_completed as f64),
+            0
+        )
+    ))?;
+    renderer.begin_list()?;
+    for (username, count) in &weekly.completed_by {
+        renderer.item(&format!("{username}: {count:.0}"))?;
+    }
+    renderer.end_list()?;
+    renderer.end_list()?;
     Ok(())
 }
 
+fn write_time_to_close_section(renderer: &mut dyn Renderer, report: &Report) -> Result<()> {
+    renderer.heading(&format!(
+        "Time to Close Issues in the Past {} Days",
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    let Some(overall) = &report.time_to_close else {
+        renderer.item("No issues closed")?;
+        renderer.end_list()?;
+        return Ok(());
+    };
+    renderer.item(&time_to_close_summary("All", overall))?;
+    renderer.begin_list()?;
+    for (username, time_to_close) in &report.time_to_close_by {
+        renderer.item(&time_to_close_summary(username, time_to_close))?;
+    }
+    renderer.end_list()?;
+    renderer.end_list()?;
+    Ok(())
+}
+
+fn time_to_close_summary(name: &str, time_to_close: &TimeToClose) -> String {
+    format!(
+        "{name}: {} closed, {:.1} days on average, {:.1} days median",
+        time_to_close.closed, time_to_close.mean_days, time_to_close.median_days
+    )
+}
+
+/// Describes a number of days, calling seven days a week.
+fn period(days: u32) -> String {
+    match days {
+        1 => "Day".to_string(),
+        7 => "Week".to_string(),
+        _ => format!("{days} Days"),
+    }
+}
+
+/// Describes a number of hours, in days if it is a multiple of a day longer
+/// than one.
+fn hour_period(hours: u32) -> String {
+    if hours > 24 && hours % 24 == 0 {
+        format!("{} Days", hours / 24)
+    } else if hours == 1 {
+        "Hour".to_string()
+    } else {
+        format!("{hours} Hours")
+    }
+}
+
+/// Returns " @username" for each username, in order.
+fn mentions<'a, I>(usernames: I) -> String
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    usernames
+        .into_iter()
+        .fold(String::new(), |mut mentions, username| {
+            mentions.push_str(" @");
+            mentions.push_str(username);
+            mentions
+        })
+}
+
+/// Returns a link to an issue or a pull request, depending on `kind`, under
+/// the page of `repo` in `links` if there is one, or else on github.com.
+///
+/// The link text uses the display name of `repo` in `links` if any, and
+/// otherwise omits the owner of repositories owned by petabi.
+fn github_link(
+    renderer: &dyn Renderer,
+    links: &BTreeMap<String, RepoLink>,
+    repo: &str,
+    kind: &str,
+    number: i64,
+) -> String {
+    let slug = RepoSlug::from(repo);
+    let (owner, name) = (slug.owner(), slug.name());
+    let link = links.get(&slug.to_string());
+    let text = match link.and_then(|link| link.display_name.as_ref()) {
+        Some(display_name) => format!("{display_name}#{number}"),
+        None if owner == github::DEFAULT_OWNER => format!("{name}#{number}"),
+        None => format!("{owner}/{name}#{number}"),
+    };
+    let url = match link.and_then(|link| link.html_url.as_ref()) {
+        Some(html_url) => format!("{}/{kind}/{number}", html_url.trim_end_matches('/')),
+        None => format!("https://github.com/{owner}/{name}/{kind}/{number}"),
+    };
+    renderer.link(&text, &url)
+}
+
+/// Writes the statistics of a user along with the changes from `previous`,
+/// the statistics of an earlier report covering the given number of days.
+/// Repositories are named as in `links`.
 #[allow(clippy::cast_precision_loss)]
+#[allow(clippy::too_many_arguments)]
 fn print_individual_stat(
-    out: &mut dyn Write,
+    renderer: &mut dyn Renderer,
     username: &str,
     stats: &IndividualStats,
+    previous: Option<(&IndividualStats, i64)>,
     since: &DateTime<Utc>,
     asof: &DateTime<Utc>,
+    timezone: Tz,
+    links: &BTreeMap<String, RepoLink>,
 ) -> Result<()> {
-    let days = (*asof - *since).num_days();
-    out.write_all(format!("<li>{username}\n<ul>\n").as_bytes())?;
-    out.write_all(
-        format!(
-            "<li>{:.3} issues completed per day\n",
-            stats.issues_completed as f64 / days as f64
-        )
-        .as_bytes(),
-    )?;
-    out.write_all(
-        format!(
-            "<li>{:.3} issues (non-bug) opened per day\n",
-            stats.issues_opened as f64 / days as f64
-        )
-        .as_bytes(),
-    )?;
-    out.write_all(
-        format!(
-            "<li>{:.3} bugs reported per day\n",
-            stats.bugs_reported as f64 / days as f64
+    let days = local_days(since, asof, timezone);
+    let per_day = |count: fn(&IndividualStats) -> f64| {
+        (
+            ratio(count(stats), days as f64),
+            previous.map(|(previous, days)| ratio(count(previous), days as f64)),
         )
-        .as_bytes(),
-    )?;
-    out.write_all(
-        format!(
-            "<li>{:.3} pull/merge requests opened per day\n",
-            stats.merged_merge_requests_opened as f64 / days as f64
-        )
-        .as_bytes(),
-    )?;
-    out.write_all(
-        format!(
-            "<li>{:5.2} comments per merge request\n",
-            stats.merge_request_notes as f64 / stats.merged_merge_requests_opened as f64
-        )
-        .as_bytes(),
-    )?;
-    out.write_all(
-        format!(
-            "<li>{:5.2} lines of code contributed per day\n",
-            stats.lines_contributed as f64 / days as f64
+    };
+    renderer.item(username)?;
+    renderer.begin_list()?;
+    let (rate, previous_rate) = per_day(|s| s.issues_completed);
+    renderer.item(&format!(
+        "{rate:.3} issues completed per day{} ({:.1} total, median {:.1})",
+        change(rate, previous_rate, 3),
+        stats.issues_completed,
+        stats.median_per_day.issues_completed
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.issues_opened as f64);
+    renderer.item(&format!(
+        "{rate:.3} issues (non-bug) opened per day{} ({} total, median {:.1})",
+        change(rate, previous_rate, 3),
+        stats.issues_opened,
+        stats.median_per_day.issues_opened
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.bugs_reported as f64);
+    renderer.item(&format!(
+        "{rate:.3} bugs reported per day{} ({} total, median {:.1})",
+        change(rate, previous_rate, 3),
+        stats.bugs_reported,
+        stats.median_per_day.bugs_reported
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.merged_merge_requests_opened as f64);
+    renderer.item(&format!(
+        "{rate:.3} pull/merge requests opened per day{} ({} total, median {:.1})",
+        change(rate, previous_rate, 3),
+        stats.merged_merge_requests_opened,
+        stats.median_per_day.merged_merge_requests_opened
+    ))?;
+    let notes = |s: &IndividualStats| {
+        ratio(
+            s.merge_request_notes as f64,
+            s.merged_merge_requests_opened as f64,
         )
-        .as_bytes(),
-    )?;
-    out.write_all(b"</ul>\n")?;
+    };
+    renderer.item(&format!(
+        "{:5.2} comments per merge request{} ({} total)",
+        notes(stats),
+        change(
+            notes(stats),
+            previous.map(|(previous, _)| notes(previous)),
+            2
+        ),
+        stats.merge_request_notes
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.lines_contributed);
+    renderer.item(&format!(
+        "{rate:5.2} lines of code contributed per day{} ({:.0} total, {:.0} in tests)",
+        change(rate, previous_rate, 2),
+        stats.lines_contributed,
+        stats.test_lines_contributed
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.lines_removed);
+    renderer.item(&format!(
+        "{rate:5.2} lines of code removed per day{} ({:.0} total)",
+        change(rate, previous_rate, 2),
+        stats.lines_removed
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.reviews_given as f64);
+    renderer.item(&format!(
+        "{rate:.3} reviews submitted per day{} ({} total, median {:.1})",
+        change(rate, previous_rate, 3),
+        stats.reviews_given,
+        stats.median_per_day.reviews_given
+    ))?;
+    let (rate, previous_rate) = per_day(|s| s.issue_comments as f64);
+    renderer.item(&format!(
+        "{rate:.3} issue comments per day{} ({} total)",
+        change(rate, previous_rate, 3),
+        stats.issue_comments
+    ))?;
+    if !stats.per_repo.is_empty() {
+        write_repo_breakdown(renderer, &stats.per_repo, links)?;
+    }
+    renderer.end_list()?;
     Ok(())
 }
 
-fn print_unknown_emails(
-    out: &mut dyn Write,
-    total_loc: &HashMap<String, usize>,
-    email_map: &BTreeMap<String, String>,
-) -> Result<bool> {
-    let mut buf = Vec::<u8>::new();
-    for (email, loc) in total_loc {
-        if email_map.contains_key(email) {
-            continue;
+/// Writes the statistics of a user in each repository, named as in `links`.
+fn write_repo_breakdown(
+    renderer: &mut dyn Renderer,
+    per_repo: &BTreeMap<String, IndividualStats>,
+    links: &BTreeMap<String, RepoLink>,
+) -> Result<()> {
+    renderer.item("By repository")?;
+    renderer.begin_list()?;
+    for (repo, stats) in per_repo {
+        let repo = links
+            .get(repo)
+            .and_then(|link| link.display_name.as_ref())
+            .unwrap_or(repo);
+        renderer.item(&format!(
+            "{repo}: {:.1} issues completed, {} issues opened, {} bugs reported, \
+             {} pull requests merged, {} reviews, {} issue comments, {:.0} lines",
+            stats.issues_completed,
+            stats.issues_opened,
+            stats.bugs_reported,
+            stats.merged_merge_requests_opened,
+            stats.reviews_given,
+            stats.issue_comments,
+            stats.lines_contributed
+        ))?;
+    }
+    renderer.end_list()?;
+    Ok(())
+}
+
+/// Ranks the usernames in `individual_stats` with a nonzero value of the
+/// leaderboard metric, in descending order of the value and then by username,
+/// returning those at the top.
+fn leaderboard(
+    individual_stats: &BTreeMap<String, IndividualStats>,
+    leaderboard: Leaderboard,
+) -> Vec<(&str, f64)> {
+    let mut ranking = individual_stats
+        .iter()
+        .map(|(username, stats)| (username.as_str(), leaderboard.metric.value(stats)))
+        .filter(|(_, value)| *value > 0.)
+        .collect::<Vec<_>>();
+    ranking.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(Ordering::Equal)
+            .then(a.0.cmp(b.0))
+    });
+    ranking.truncate(leaderboard.size);
+    ranking
+}
+
+fn write_leaderboard_section(
+    renderer: &mut dyn Renderer,
+    report: &Report,
+    leaders: &[(&str, f64)],
+) -> Result<()> {
+    renderer.heading(&format!(
+        "Top {} by {} in the Past {} Days",
+        leaders.len(),
+        report.leaderboard.metric.description(),
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    for (username, value) in leaders {
+        renderer.item(&format!("{username}: {value:.0}"))?;
+    }
+    renderer.end_list()?;
+    Ok(())
+}
+
+/// Formats the change from `previous` to `current` as " (+N)" or " (-N)"
+/// with `precision` decimal places, or nothing if there is no `previous`.
+fn change(current: f64, previous: Option<f64>, precision: usize) -> String {
+    previous.map_or_else(String::new, |previous| {
+        format!(" ({:+.*})", precision, current - previous)
+    })
+}
+
+fn write_release_section(renderer: &mut dyn Renderer, report: &Report) -> Result<()> {
+    renderer.heading(&format!(
+        "Releases in the Past {} Days",
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    for (repo, tags) in &report.releases {
+        renderer.item(repo)?;
+        renderer.begin_list()?;
+        for tag in tags {
+            renderer.item(&format!("{} on {}", tag.name, report.date(&tag.date)))?;
         }
-        buf.write_all(format!("<li>{email}: {loc} lines contributed\n").as_bytes())?;
+        renderer.end_list()?;
+    }
+    renderer.end_list()?;
+    Ok(())
+}
+
+fn write_team_section(renderer: &mut dyn Renderer, report: &Report) -> Result<()> {
+    renderer.heading(&format!(
+        "Team Statistics for the Past {} Days",
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    for (team, stats) in &report.team_stats {
+        renderer.item(team)?;
+        renderer.begin_list()?;
+        write_sums(renderer, stats)?;
+        renderer.end_list()?;
+    }
+    renderer.end_list()?;
+    Ok(())
+}
+
+fn write_totals_section(renderer: &mut dyn Renderer, report: &Report) -> Result<()> {
+    renderer.heading(&format!(
+        "Team Totals for the Past {} Days",
+        report.lookback_days
+    ))?;
+    renderer.begin_list()?;
+    write_sums(renderer, &report.totals)?;
+    renderer.item(&format!(
+        "{} issues created and {} completed in the past {}",
+        report.weekly.created,
+        report.weekly.completed,
+        period(report.recent_days).to_lowercase()
+    ))?;
+    if !report.inactive_repos.is_empty() {
+        renderer.item(&format!(
+            "No activity in {}",
+            report.inactive_repos.join(", ")
+        ))?;
+    }
+    renderer.end_list()?;
+    if !report.daily_issues.is_empty() {
+        renderer.chart(&issue_chart(&report.daily_issues))?;
     }
-    if buf.is_empty() {
+    Ok(())
+}
+
+/// Writes the counts in `stats` as list items.
+fn write_sums(renderer: &mut dyn Renderer, stats: &IndividualStats) -> Result<()> {
+    renderer.item(&format!("{:.1} issues completed", stats.issues_completed))?;
+    renderer.item(&format!("{} issues (non-bug) opened", stats.issues_opened))?;
+    renderer.item(&format!("{} bugs reported", stats.bugs_reported))?;
+    renderer.item(&format!(
+        "{} pull/merge requests opened",
+        stats.merged_merge_requests_opened
+    ))?;
+    renderer.item(&format!("{} reviews submitted", stats.reviews_given))?;
+    renderer.item(&format!("{} issue comments", stats.issue_comments))?;
+    renderer.item(&format!(
+        "{:.0} lines of code contributed",
+        stats.lines_contributed
+    ))?;
+    renderer.item(&format!("{:.0} lines of code removed", stats.lines_removed))?;
+    Ok(())
+}
+
+/// Divides `numerator` by `denominator`, treating an empty denominator as
+/// zero activity rather than producing `NaN` or infinity.
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0. {
+        0.
+    } else {
+        numerator / denominator
+    }
+}
+
+fn print_unknown_emails(
+    renderer: &mut dyn Renderer,
+    unknown_emails: &[(String, f64)],
+) -> Result<bool> {
+    if unknown_emails.is_empty() {
         return Ok(false);
     }
-    out.write_all(b"\n<h2>Other emails in commits</h2>\n<ul>\n")?;
-    out.write_all(&buf)?;
-    out.write_all(b"</ul>\n")?;
+    renderer.heading("Other emails in commits")?;
+    renderer.begin_list()?;
+    for (email, loc) in unknown_emails {
+        renderer.item(&format!("{email}: {loc:.0} lines contributed"))?;
+    }
+    renderer.end_list()?;
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use regex::RegexSet;
+
+    use super::*;
+
+    #[test]
+    fn links_use_repository_owner() {
+        let created_at = DateTime::parse_from_rfc3339("2024-01-25T00:00:00Z").unwrap();
+        let pull_requests = [
+            github::PullRequest {
+                title: "Add widgets".to_string(),
+                number: 7,
+                repo: "acme/widget".to_string(),
+                reviewers: Vec::new(),
+                assignees: Vec::new(),
+                is_draft: false,
+                created_at,
+                updated_at: created_at,
+            },
+            github::PullRequest {
+                title: "Fix metrics".to_string(),
+                number: 8,
+                repo: "pbmetric".to_string(),
+                reviewers: Vec::new(),
+                assignees: Vec::new(),
+                is_draft: false,
+                created_at,
+                updated_at: created_at,
+            },
+        ];
+        let mut out = Vec::new();
+        write_pull_request_section(&mut Html::new(&mut out), &pull_requests, &BTreeMap::new())
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            r#"<a href="https://github.com/acme/widget/pull/7">acme/widget#7</a> Add widgets"#
+        ));
+        assert!(out.contains(
+            r#"<a href="https://github.com/petabi/pbmetric/pull/8">pbmetric#8</a> Fix metrics"#
+        ));
+        assert!(!out.contains("petabi/acme"));
+    }
+
+    #[test]
+    fn links_use_configured_html_url() {
+        let github = toml::from_str::<GithubConfig>(
+            r#"
+            repositories = ["acme/widget", "pbmetric"]
+            account = {}
+            [repo_links."acme/widget"]
+            display_name = "Widget"
+            html_url = "https://git.example.com/mirrors/widget/"
+            [repo_links.pbmetric]
+            html_url = "https://ghe.example.com/petabi/pbmetric"
+            "#,
+        )
+        .unwrap();
+        let links = github.repo_links();
+        let created_at = DateTime::parse_from_rfc3339("2024-01-25T00:00:00Z").unwrap();
+        let pull_requests = [github::PullRequest {
+            title: "Add widgets".to_string(),
+            number: 7,
+            repo: "acme/widget".to_string(),
+            reviewers: Vec::new(),
+            assignees: Vec::new(),
+            is_draft: false,
+            created_at,
+            updated_at: created_at,
+        }];
+        let issues = [github::Issue {
+            title: "Metrics are off".to_string(),
+            number: 9,
+            repo: "pbmetric".to_string(),
+            assignees: Vec::new(),
+            labels: Vec::new(),
+            updated_at: created_at,
+        }];
+        let mut out = Vec::new();
+        let mut renderer = Html::new(&mut out);
+        write_pull_request_section(&mut renderer, &pull_requests, &links).unwrap();
+        write_issues_section(&mut renderer, &issues, 24, &links).unwrap();
+        drop(renderer);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            r#"<a href="https://git.example.com/mirrors/widget/pull/7">Widget#7</a> Add widgets"#
+        ));
+        assert!(out.contains(
+            r#"<a href="https://ghe.example.com/petabi/pbmetric/issues/9">pbmetric#9</a>"#
+        ));
+        assert!(!out.contains("https://github.com"));
+    }
+
+    #[test]
+    fn overriding_exclude_defaults() {
+        let exclude = [r"\.svg$".to_string()];
+
+        let patterns = RegexSet::new(exclude_patterns(&exclude, false)).unwrap();
+        assert!(patterns.is_match("Cargo.lock"));
+        assert!(patterns.is_match("logo.svg"));
+
+        let patterns = RegexSet::new(exclude_patterns(&exclude, true)).unwrap();
+        assert!(!patterns.is_match("Cargo.lock"));
+        assert!(patterns.is_match("logo.svg"));
+    }
+
+    #[test]
+    fn vendored_directories_are_excluded_by_default() {
+        let patterns = RegexSet::new(exclude_patterns(&[], false)).unwrap();
+        assert!(patterns.is_match("node_modules/react/index.js"));
+        assert!(patterns.is_match("web/node_modules/react/index.js"));
+        assert!(patterns.is_match("vendor/github.com/pkg/errors/errors.go"));
+        assert!(patterns.is_match("third_party/zlib/zlib.h"));
+        assert!(patterns.is_match("dist/"));
+        assert!(!patterns.is_match("src/distance.rs"));
+        assert!(!patterns.is_match("src/vendor.rs"));
+    }
+
+    #[test]
+    fn individual_stat_without_activity_window() {
+        let asof = Utc::now();
+        let stats = IndividualStats {
+            issues_opened: 3,
+            merge_request_notes: 4,
+            ..IndividualStats::default()
+        };
+
+        let mut out = Vec::new();
+        print_individual_stat(
+            &mut Html::new(&mut out),
+            "alice",
+            &stats,
+            None,
+            &asof,
+            &asof,
+            Tz::UTC,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("NaN"));
+        assert!(!out.contains("inf"));
+        assert!(out.contains("<li> 0.00 comments per merge request (4 total)\n"));
+        assert!(out.contains("<li>0.000 issues (non-bug) opened per day (3 total, median 0.0)\n"));
+    }
+
+    #[test]
+    fn individual_stat_shows_totals() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let since = asof - Duration::try_days(30).unwrap();
+        let stats = IndividualStats {
+            bugs_reported: 1,
+            issues_completed: 6.,
+            issues_opened: 3,
+            merged_merge_requests_opened: 2,
+            merge_request_notes: 5,
+            reviews_given: 4,
+            issue_comments: 9,
+            lines_contributed: 1500.4,
+            test_lines_contributed: 300.,
+            lines_removed: 120.,
+            median_per_day: crate::issue::DailyMedians {
+                issues_completed: 0.5,
+                ..crate::issue::DailyMedians::default()
+            },
+            per_repo: BTreeMap::new(),
+        };
+
+        let mut out = Vec::new();
+        let mut renderer = Markdown::new(&mut out);
+        renderer.begin_list().unwrap();
+        print_individual_stat(
+            &mut renderer,
+            "alice",
+            &stats,
+            None,
+            &since,
+            &asof,
+            Tz::UTC,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        renderer.end_list().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+- alice
+  - 0.200 issues completed per day (6.0 total, median 0.5)
+  - 0.100 issues (non-bug) opened per day (3 total, median 0.0)
+  - 0.033 bugs reported per day (1 total, median 0.0)
+  - 0.067 pull/merge requests opened per day (2 total, median 0.0)
+  -  2.50 comments per merge request (5 total)
+  - 50.01 lines of code contributed per day (1500 total, 300 in tests)
+  -  4.00 lines of code removed per day (120 total)
+  - 0.133 reviews submitted per day (4 total, median 0.0)
+  - 0.300 issue comments per day (9 total)
+
+"
+        );
+    }
+
+    fn production(lines: f64) -> LineCount {
+        LineCount {
+            production: lines,
+            ..LineCount::default()
+        }
+    }
+
+    fn timestamp(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn sample_report() -> Report {
+        sample_report_with(&ReportConfig::default())
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn sample_report_with(config: &ReportConfig) -> Report {
+        let metadata =
+            |author: &str, labels: &[&str], created_at, closed_at: Option<&str>| IssueMetadata {
+                author: Some(author.to_string()),
+                labels: labels.iter().map(ToString::to_string).collect(),
+                assignees: vec!["alice-gh".to_string(), "bob-gh".to_string()],
+                created_at: DateTime::parse_from_rfc3339(created_at).unwrap(),
+                closed_at: closed_at.map(|t| DateTime::parse_from_rfc3339(t).unwrap()),
+                repo: "pbmetric".to_string(),
+                number: 1,
+                title: String::new(),
+                reactions: 0,
+            };
+        let github_data = GithubData {
+            open_pull_requests: vec![
+                github::PullRequest {
+                    title: "Add widgets".to_string(),
+                    number: 7,
+                    repo: "acme/widget".to_string(),
+                    reviewers: vec!["bob-gh".to_string()],
+                    assignees: vec!["alice-gh".to_string()],
+                    is_draft: false,
+                    created_at: DateTime::parse_from_rfc3339("2024-01-25T00:00:00Z").unwrap(),
+                    updated_at: DateTime::parse_from_rfc3339("2024-01-27T00:00:00Z").unwrap(),
+                },
+                github::PullRequest {
+                    title: "[WIP] Rewrite everything".to_string(),
+                    number: 8,
+                    repo: "pbmetric".to_string(),
+                    reviewers: Vec::new(),
+                    assignees: Vec::new(),
+                    is_draft: false,
+                    created_at: DateTime::parse_from_rfc3339("2024-01-26T00:00:00Z").unwrap(),
+                    updated_at: DateTime::parse_from_rfc3339("2024-01-26T00:00:00Z").unwrap(),
+                },
+                github::PullRequest {
+                    title: "Remove widgets".to_string(),
+                    number: 10,
+                    repo: "acme/widget".to_string(),
+                    reviewers: vec!["bob-gh".to_string()],
+                    assignees: Vec::new(),
+                    is_draft: true,
+                    created_at: DateTime::parse_from_rfc3339("2024-01-26T00:00:00Z").unwrap(),
+                    updated_at: DateTime::parse_from_rfc3339("2024-01-26T00:00:00Z").unwrap(),
+                },
+                github::PullRequest {
+                    title: "Add gadgets".to_string(),
+                    number: 9,
+                    repo: "acme/widget".to_string(),
+                    reviewers: vec!["bob-gh".to_string()],
+                    assignees: Vec::new(),
+                    is_draft: false,
+                    created_at: DateTime::parse_from_rfc3339("2024-02-02T00:00:00Z").unwrap(),
+                    updated_at: DateTime::parse_from_rfc3339("2024-02-02T00:00:00Z").unwrap(),
+                },
+            ],
+            assigned_issues: vec![
+                github::Issue {
+                    title: "Flaky test".to_string(),
+                    number: 3,
+                    repo: "pbmetric".to_string(),
+                    assignees: vec!["carol-gh".to_string()],
+                    labels: vec!["bug".to_string(), "priority".to_string()],
+                    updated_at: DateTime::parse_from_rfc3339("2024-01-29T18:00:00Z").unwrap(),
+                },
+                github::Issue {
+                    title: "Update dependencies".to_string(),
+                    number: 4,
+                    repo: "pbmetric".to_string(),
+                    assignees: vec!["alice-gh".to_string()],
+                    labels: vec!["priority".to_string(), "wontfix".to_string()],
+                    updated_at: DateTime::parse_from_rfc3339("2024-01-30T18:00:00Z").unwrap(),
+                },
+            ],
+            issue_metadata: vec![
+                IssueMetadata {
+                    number: 12,
+                    title: "Crash on an empty repository".to_string(),
+                    reactions: 3,
+                    ..metadata("alice-gh", &["bug"], "2024-01-29T00:00:00Z", None)
+                },
+                metadata(
+                    "bob-gh",
+                    &[],
+                    "2024-01-10T00:00:00Z",
+                    Some("2024-01-30T00:00:00Z"),
+                ),
+            ],
+            merged_pull_requests: vec![
+                github::MergedPullRequest {
+                    author: "alice-gh".to_string(),
+                    repo: "pbmetric".to_string(),
+                    merged_at: DateTime::parse_from_rfc3339("2024-01-12T00:00:00Z").unwrap(),
+                    comments: 4,
+                },
+                github::MergedPullRequest {
+                    author: "alice-gh".to_string(),
+                    repo: "pbmetric".to_string(),
+                    merged_at: DateTime::parse_from_rfc3339("2024-01-20T00:00:00Z").unwrap(),
+                    comments: 2,
+                },
+            ],
+            reviews: vec![github::Review {
+                author: "bob-gh".to_string(),
+                repo: "pbmetric".to_string(),
+                submitted_at: DateTime::parse_from_rfc3339("2024-01-26T00:00:00Z").unwrap(),
+            }],
+            issue_comments: vec![github::IssueComment {
+                author: "bob-gh".to_string(),
+                repo: "pbmetric".to_string(),
+                created_at: DateTime::parse_from_rfc3339("2024-01-27T00:00:00Z").unwrap(),
+            }],
+        };
+        let total_loc = HashMap::from([
+            (
+                "alice@petabi.com".to_string(),
+                LineCount {
+                    production: 30.,
+                    test: 15.,
+                    removed: 0.,
+                },
+            ),
+            ("bot@example.com".to_string(), production(10.)),
+        ]);
+        let account_map = HashMap::from([
+            ("alice-gh".to_string(), "alice".to_string()),
+            ("bob-gh".to_string(), "bob".to_string()),
+        ]);
+        let email_map = BTreeMap::from([("alice@petabi.com".to_string(), "alice".to_string())]);
+        let asof = timestamp("2024-01-31T00:00:00Z");
+        let since = asof - Duration::try_days(90).unwrap();
+        let mut report = Report::new(
+            config,
+            github_data,
+            total_loc,
+            &account_map,
+            &email_map,
+            &since,
+            &asof,
+        );
+        report.releases = BTreeMap::from([(
+            "pbmetric".to_string(),
+            vec![Tag {
+                name: "0.3.0".to_string(),
+                date: timestamp("2024-01-20T00:00:00Z"),
+            }],
+        )]);
+        report
+    }
+
+    #[test]
+    fn epoch_bounds_lookback_window() {
+        let asof = timestamp("2024-01-31T00:00:00Z");
+        assert_eq!(
+            report_since(&asof, None, 30),
+            timestamp("2024-01-01T00:00:00Z")
+        );
+        let epoch = timestamp("2024-01-15T00:00:00Z");
+        assert_eq!(report_since(&asof, Some(&epoch), 30), epoch);
+        let epoch = timestamp("2023-01-01T00:00:00Z");
+        assert_eq!(
+            report_since(&asof, Some(&epoch), 14),
+            timestamp("2024-01-17T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn recent_window_selects_recent_changes() {
+        let report = sample_report();
+        assert_eq!(report.weekly.created, 1);
+        assert_eq!(report.weekly.completed, 1);
+
+        let report = sample_report_with(&ReportConfig {
+            recent_days: 1,
+            ..ReportConfig::default()
+        });
+        assert_eq!(report.weekly.created, 0);
+        assert_eq!(report.weekly.completed, 0);
+
+        let report = sample_report_with(&ReportConfig {
+            recent_days: 30,
+            ..ReportConfig::default()
+        });
+        assert_eq!(report.weekly.created, 2);
+        assert_eq!(report.weekly.completed, 1);
+
+        let mut out = Vec::new();
+        write_weekly_section(
+            &mut Markdown::new(&mut out),
+            &report.weekly,
+            30,
+            None,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("## Changes in the Past 30 Days\n"));
+        assert!(out.contains("  - By label\n    - bug: 1\n    - unlabeled: 1\n"));
+    }
+
+    #[test]
+    fn pull_requests_created_after_asof_are_excluded() {
+        let report = sample_report();
+        let numbers = report
+            .pull_requests
+            .iter()
+            .map(|pr| pr.number)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, [7]);
+    }
+
+    #[test]
+    fn draft_pull_requests_are_excluded() {
+        let report = sample_report();
+        assert!(report.pull_requests.iter().any(|pr| pr.number == 7));
+        assert!(!report.pull_requests.iter().any(|pr| pr.number == 10));
+    }
+
+    #[test]
+    fn wip_prefixes_are_configurable() {
+        assert!(is_wip("WIP: Add widgets", &["wip:".to_string()]));
+        assert!(!is_wip("Add widgets", &["wip:".to_string()]));
+        assert!(!is_wip("WIP: Add widgets", &["[WIP]".to_string()]));
+
+        let numbers = |wip_prefixes: &[&str]| {
+            sample_report_with(&ReportConfig {
+                wip_prefixes: wip_prefixes.iter().map(ToString::to_string).collect(),
+                ..ReportConfig::default()
+            })
+            .pull_requests
+            .iter()
+            .map(|pr| pr.number)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(&[]), [7, 8]);
+        assert_eq!(numbers(&["[wip]", "Draft:"]), [7]);
+    }
+
+    #[test]
+    fn stale_pull_requests_are_listed_separately() {
+        let report = sample_report();
+        assert_eq!(report.pull_requests.len(), 1);
+        assert_eq!(report.stale_pull_requests.len(), 1);
+        assert_eq!(report.stale_pull_requests[0].number, 7);
+
+        let report = sample_report_with(&ReportConfig {
+            stale_pull_request_hours: 24 * 5,
+            ..ReportConfig::default()
+        });
+        assert_eq!(report.pull_requests.len(), 1);
+        assert!(report.stale_pull_requests.is_empty());
+    }
+
+    #[test]
+    fn stale_threshold_selects_stale_issues() {
+        let report = sample_report();
+        assert_eq!(report.stale_issues.len(), 1);
+        assert_eq!(report.stale_issues[0].number, 3);
+
+        let report = sample_report_with(&ReportConfig {
+            stale_hours: 48,
+            ..ReportConfig::default()
+        });
+        assert!(report.stale_issues.is_empty());
+
+        let report = sample_report_with(&ReportConfig {
+            stale_hours: 72,
+            ..ReportConfig::default()
+        });
+        let mut out = Vec::new();
+        write_issues_section(
+            &mut Markdown::new(&mut out),
+            &report.stale_issues,
+            72,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("## Assigned Issues with No Update in Past 3 Days\n"));
+    }
+
+    #[test]
+    fn stale_issues_are_filtered_by_label() {
+        let stale_numbers = |include_labels: &[&str], exclude_labels: &[&str]| {
+            sample_report_with(&ReportConfig {
+                stale_hours: 1,
+                include_labels: include_labels.iter().map(ToString::to_string).collect(),
+                exclude_labels: exclude_labels.iter().map(ToString::to_string).collect(),
+                ..ReportConfig::default()
+            })
+            .stale_issues
+            .iter()
+            .map(|issue| issue.number)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(stale_numbers(&[], &[]), [3, 4]);
+        assert_eq!(stale_numbers(&["priority"], &[]), [3, 4]);
+        assert_eq!(stale_numbers(&["priority"], &["wontfix"]), [3]);
+        assert_eq!(stale_numbers(&[], &["bug"]), [4]);
+        assert!(stale_numbers(&["chore"], &[]).is_empty());
+    }
+
+    #[test]
+    fn email_map_matches_emails_in_any_case() {
+        let mut individual_stats = BTreeMap::new();
+        let total_loc = HashMap::from([
+            ("jane@corp.com".to_string(), production(10.)),
+            ("bot@corp.com".to_string(), production(5.)),
+        ]);
+        let email_map = BTreeMap::from([(" Jane@Corp.com".to_string(), "jane".to_string())]);
+
+        let unknown_emails = add_lines_contributed(&mut individual_stats, total_loc, &email_map);
+        assert!((individual_stats["jane"].lines_contributed - 10.).abs() < f64::EPSILON);
+        assert_eq!(unknown_emails, [("bot@corp.com".to_string(), 5.)]);
+    }
+
+    #[test]
+    fn unknown_emails_are_sorted_by_lines_and_email() {
+        let total_loc = || {
+            ["c", "a", "e", "b", "d", "f", "g", "h"]
+                .into_iter()
+                .zip([3., 5., 1., 3., 5., 2., 3., 0.])
+                .map(|(user, lines)| (format!("{user}@example.com"), production(lines)))
+                .collect::<HashMap<_, _>>()
+        };
+        let expected = [
+            ("a@example.com".to_string(), 5.),
+            ("d@example.com".to_string(), 5.),
+            ("b@example.com".to_string(), 3.),
+            ("c@example.com".to_string(), 3.),
+            ("g@example.com".to_string(), 3.),
+            ("f@example.com".to_string(), 2.),
+            ("e@example.com".to_string(), 1.),
+            ("h@example.com".to_string(), 0.),
+        ];
+        for _ in 0..10 {
+            let unknown_emails =
+                add_lines_contributed(&mut BTreeMap::new(), total_loc(), &BTreeMap::new());
+            assert_eq!(unknown_emails, expected);
+        }
+    }
+
+    #[test]
+    fn exact_email_takes_precedence_over_wildcard() {
+        let mut individual_stats = BTreeMap::new();
+        let total_loc = HashMap::from([
+            ("jane@petabi.com".to_string(), production(10.)),
+            ("contractor@petabi.com".to_string(), production(7.)),
+            ("intern@petabi.com".to_string(), production(3.)),
+            ("jane@example.com".to_string(), production(5.)),
+        ]);
+        let email_map = BTreeMap::from([
+            ("jane@petabi.com".to_string(), "jane".to_string()),
+            ("*@petabi.com".to_string(), "petabi".to_string()),
+        ]);
+
+        let unknown_emails = add_lines_contributed(&mut individual_stats, total_loc, &email_map);
+        assert!((individual_stats["jane"].lines_contributed - 10.).abs() < f64::EPSILON);
+        assert!((individual_stats["petabi"].lines_contributed - 10.).abs() < f64::EPSILON);
+        assert_eq!(unknown_emails, [("jane@example.com".to_string(), 5.)]);
+    }
+
+    #[test]
+    fn html_report_matches_golden_output() {
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Html, &sample_report()).unwrap();
+        let expected = include_str!("../tests/data/report.html").replace(
+            "{version}",
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn html_table_of_contents_links_to_sections() {
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Html, &sample_report()).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        let links = regex::Regex::new(r##"<a href="#([^"]+)">"##)
+            .unwrap()
+            .captures_iter(&html)
+            .map(|c| c[1].to_string())
+            .collect::<Vec<_>>();
+        let ids = regex::Regex::new(r#"<section id="([^"]+)">"#)
+            .unwrap()
+            .captures_iter(&html)
+            .map(|c| c[1].to_string())
+            .collect::<Vec<_>>();
+        assert!(links.contains(&"individual-statistics-for-the-past-90-days".to_string()));
+        assert_eq!(links, ids);
+    }
+
+    /// Asserts that every tag in `html`, other than void elements like
+    /// `<meta>`, is closed in the reverse order of opening.
+    fn assert_balanced(html: &str) {
+        let tag = regex::Regex::new(r"<(/?)([a-z0-9]+)[^>]*>").unwrap();
+        let mut open = Vec::new();
+        for c in tag.captures_iter(html) {
+            let name = &c[2];
+            if name == "meta" || name == "br" {
+                continue;
+            }
+            if c[1].is_empty() {
+                open.push(name.to_string());
+            } else {
+                assert_eq!(open.pop().as_deref(), Some(name), "at {}", &c[0]);
+            }
+        }
+        assert!(open.is_empty(), "unclosed: {open:?}");
+    }
+
+    #[test]
+    fn html_tags_are_balanced() {
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Html, &sample_report()).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(!html.contains("pre>"));
+        assert_balanced(&html);
+
+        let report = sample_report_with(&ReportConfig {
+            recent_days: 30,
+            ..ReportConfig::default()
+        });
+        let mut out = Vec::new();
+        write_weekly_section(
+            &mut Html::new(&mut out),
+            &report.weekly,
+            30,
+            None,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_balanced(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn only_report_users_are_listed_individually() {
+        let everyone = sample_report();
+        let report = sample_report_with(&ReportConfig {
+            report_users: vec!["bob".to_string()],
+            ..ReportConfig::default()
+        });
+        assert_eq!(report.individual_stats.keys().collect::<Vec<_>>(), ["bob"]);
+        assert_eq!(report.totals, everyone.totals);
+        assert_eq!(report.unknown_emails, everyone.unknown_emails);
+
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Markdown, &report).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let (_, individual) = out.split_once("## Individual Statistics").unwrap();
+        assert!(individual.contains("\n- bob\n"));
+        assert!(!individual.contains("\n- alice\n"));
+        assert!(!out.contains("Lines Contributed in the Past"));
+        assert!(out.contains("45 lines of code contributed"));
+    }
+
+    #[test]
+    fn inactive_users_are_hidden() {
+        let report = sample_report_with(&ReportConfig {
+            min_activity: 10.,
+            ..ReportConfig::default()
+        });
+        assert!(report.individual_stats["bob"].activity() < 10.);
+        assert!(report.individual_stats["alice"].activity() >= 10.);
+
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Markdown, &report).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let (_, individual) = out.split_once("## Individual Statistics").unwrap();
+        assert!(individual.contains("\n- alice\n"));
+        assert!(!individual.contains("\n- bob\n"));
+        assert!(individual.contains("\n- 1 user with less activity hidden\n"));
+    }
+
+    #[test]
+    fn users_are_sorted_by_the_chosen_key() {
+        let stats = |lines: f64, issues_completed: f64, prs: usize| IndividualStats {
+            lines_contributed: lines,
+            issues_completed,
+            merged_merge_requests_opened: prs,
+            ..IndividualStats::default()
+        };
+        let individual_stats = BTreeMap::from([
+            ("alice".to_string(), stats(10., 1., 3)),
+            ("bob".to_string(), stats(30., 0.5, 3)),
+            ("carol".to_string(), stats(20., 2., 1)),
+            ("dave".to_string(), stats(30., 0., 0)),
+        ]);
+        let order = |sort_by: SortBy| {
+            sort_by
+                .sorted(&individual_stats)
+                .into_iter()
+                .map(|(username, _)| username.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(SortBy::Username), ["alice", "bob", "carol", "dave"]);
+        assert_eq!(order(SortBy::Lines), ["bob", "dave", "carol", "alice"]);
+        assert_eq!(
+            order(SortBy::IssuesCompleted),
+            ["carol", "alice", "bob", "dave"]
+        );
+        assert_eq!(order(SortBy::Prs), ["alice", "bob", "carol", "dave"]);
+    }
+
+    #[test]
+    fn totals_are_sums_of_individual_stats() {
+        let report = sample_report();
+        assert_eq!(
+            report.unknown_emails,
+            [("bot@example.com".to_string(), 10.)]
+        );
+        let sum = |count: fn(&IndividualStats) -> usize| {
+            report.individual_stats.values().map(count).sum::<usize>()
+        };
+        let totals = &report.totals;
+        let completed = report
+            .individual_stats
+            .values()
+            .map(|s| s.issues_completed)
+            .sum::<f64>();
+        assert!((totals.issues_completed - completed).abs() < f64::EPSILON);
+        assert_eq!(totals.issues_opened, sum(|s| s.issues_opened));
+        assert_eq!(totals.bugs_reported, sum(|s| s.bugs_reported));
+        assert_eq!(
+            totals.merged_merge_requests_opened,
+            sum(|s| s.merged_merge_requests_opened)
+        );
+        assert_eq!(totals.reviews_given, sum(|s| s.reviews_given));
+        assert!((totals.lines_contributed - 45.).abs() < f64::EPSILON);
+
+        let mut out = Vec::new();
+        write_totals_section(&mut Markdown::new(&mut out), &report).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("## Team Totals for the Past 90 Days\n"));
+        assert!(out.contains("- 45 lines of code contributed\n"));
+        assert!(out.contains("- 1 issues created and 1 completed in the past week\n"));
+    }
+
+    #[test]
+    fn leaderboard_ranks_top_contributors() {
+        let stats = |lines_contributed, issues_completed| IndividualStats {
+            issues_completed,
+            lines_contributed,
+            ..IndividualStats::default()
+        };
+        let individual_stats = BTreeMap::from([
+            ("alice".to_string(), stats(120., 2.)),
+            ("bob".to_string(), stats(300., 2.)),
+            ("carol".to_string(), stats(120., 5.)),
+            ("dave".to_string(), stats(0., 0.)),
+        ]);
+
+        let ranking = leaderboard(&individual_stats, Leaderboard::default());
+        assert_eq!(ranking, [("bob", 300.), ("alice", 120.), ("carol", 120.)]);
+
+        let ranking = leaderboard(
+            &individual_stats,
+            Leaderboard {
+                metric: LeaderboardMetric::IssuesCompleted,
+                size: 2,
+            },
+        );
+        assert_eq!(ranking, [("carol", 5.), ("alice", 2.)]);
+
+        let ranking = leaderboard(
+            &individual_stats,
+            Leaderboard {
+                size: 0,
+                ..Leaderboard::default()
+            },
+        );
+        assert!(ranking.is_empty());
+    }
+
+    #[test]
+    fn changes_from_previous_snapshot_are_shown() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = crate::history::History::new(dir.path().join("history.sqlite"));
+        let mut report = sample_report();
+        let mut earlier = Snapshot::new(&report);
+        earlier.asof -= Duration::try_days(7).unwrap();
+        earlier.since -= Duration::try_days(7).unwrap();
+        earlier.issues_created = report.weekly.created + 2;
+        let alice = earlier.individual_stats.get_mut("alice").unwrap();
+        alice.issues_completed = 0.;
+        alice.merged_merge_requests_opened = 1;
+        history.record(&earlier).unwrap();
+        history.record(&Snapshot::new(&report)).unwrap();
+
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Markdown, &report).unwrap();
+        let without = String::from_utf8(out).unwrap();
+        assert!(!without.contains("(+"));
+
+        report.previous = history.previous(&report.asof).unwrap();
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Markdown, &report).unwrap();
+        let with = String::from_utf8(out).unwrap();
+        assert!(with.contains(&format!("Created: {} (-2)\n", report.weekly.created)));
+        assert!(with.contains("0.006 issues completed per day (+0.006) (0.5 total, median 0.0)"));
+        assert!(with.contains("0.022 pull/merge requests opened per day (+0.011)"));
+        assert!(with.contains("0.000 reviews submitted per day (+0.000)"));
+        assert!(with.contains(" 3.00 comments per merge request (-3.00)"));
+    }
+
+    #[test]
+    fn csv_has_a_row_per_user() {
+        let mut out = Vec::new();
+        crate::csv::write_csv(&mut out, &sample_report()).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let rows = csv.split_terminator("\r\n").collect::<Vec<_>>();
+        assert_eq!(
+            rows[0],
+            "username,bugs_reported,issues_completed,issues_opened,pull_requests_merged,\
+             pull_request_comments,reviews_given,issue_comments,lines_contributed,\
+             test_lines_contributed,lines_removed,\
+             median_issues_completed,median_issues_opened,median_bugs_reported,\
+             median_pull_requests_merged,median_reviews_given"
+        );
+        assert_eq!(rows[1], "alice,1,0.5,0,2,6,0,0,45,15,0,0,0,0,0,0");
+        assert_eq!(rows.len(), 1 + sample_report().individual_stats.len());
+    }
+
+    #[test]
+    fn prometheus_metrics_are_well_formed() {
+        let report = sample_report();
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Prometheus, &report).unwrap();
+        let metrics = String::from_utf8(out).unwrap();
+
+        let sample = regex::Regex::new(
+            r#"^([a-zA-Z_:][a-zA-Z0-9_:]*)(\{[a-zA-Z_][a-zA-Z0-9_]*="(\\.|[^"\\\n])*"\})? (\S+)$"#,
+        )
+        .unwrap();
+        let mut declared = Vec::new();
+        for line in metrics.lines() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                assert!(help.split_once(' ').is_some(), "{line}");
+            } else if let Some(family) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = family.split_once(' ').unwrap();
+                assert_eq!(kind, "gauge");
+                declared.push(name);
+            } else {
+                let caps = sample.captures(line).unwrap_or_else(|| panic!("{line}"));
+                assert_eq!(declared.last(), Some(&&caps[1]), "{line}");
+                caps[4].parse::<f64>().unwrap();
+            }
+        }
+
+        assert!(metrics.contains(&format!(
+            "\npbmetric_open_pull_requests {}\n",
+            report.pull_requests.len()
+        )));
+        assert!(metrics.contains("\npbmetric_pull_requests_merged{user=\"alice\"} 2\n"));
+        assert!(metrics.contains("\npbmetric_lines_contributed{user=\"alice\"} 45\n"));
+    }
+
+    #[test]
+    fn dates_follow_the_configured_timezone() {
+        let report = sample_report_with(&ReportConfig {
+            timezone: chrono_tz::America::Los_Angeles,
+            ..ReportConfig::default()
+        });
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Markdown, &report).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("0.3.0 on 2024-01-19"));
+
+        // The past week begins at midnight in the timezone.
+        let asof = timestamp("2024-01-31T00:00:00Z");
+        assert_eq!(
+            start_of_day_before(&asof, 7, Tz::UTC),
+            timestamp("2024-01-24T00:00:00Z")
+        );
+        assert_eq!(
+            start_of_day_before(&asof, 7, chrono_tz::Asia::Seoul),
+            timestamp("2024-01-23T15:00:00Z")
+        );
+        assert_eq!(
+            local_days(&asof, &timestamp("2024-01-31T16:00:00Z"), Tz::UTC),
+            0
+        );
+        assert_eq!(
+            local_days(
+                &asof,
+                &timestamp("2024-01-31T16:00:00Z"),
+                chrono_tz::Asia::Seoul
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn json_report_contains_individual_stats() {
+        let asof = DateTime::parse_from_rfc3339("2024-01-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let report = Report {
+            since: asof - Duration::try_days(90).unwrap(),
+            asof,
+            timezone: Tz::UTC,
+            lookback_days: 90,
+            recent_days: 7,
+            stale_hours: 24,
+            stale_pull_request_hours: 72,
+            pull_requests: Vec::new(),
+            stale_pull_requests: Vec::new(),
+            stale_issues: Vec::new(),
+            weekly: WeeklyChanges::default(),
+            time_to_close: None,
+            time_to_close_by: BTreeMap::new(),
+            daily_issues: Vec::new(),
+            team_stats: BTreeMap::new(),
+            totals: IndividualStats::default(),
+            individual_stats: BTreeMap::from([(
+                "alice".to_string(),
+                IndividualStats {
+                    issues_completed: 3.,
+                    merged_merge_requests_opened: 2,
+                    lines_contributed: 120.,
+                    ..IndividualStats::default()
+                },
+            )]),
+            leaderboard: Leaderboard::default(),
+            min_activity: 0.,
+            sort_by: SortBy::default(),
+            unknown_emails: vec![("bot@example.com".to_string(), 5.)],
+            releases: BTreeMap::new(),
+            inactive_repos: Vec::new(),
+            repo_links: BTreeMap::new(),
+            previous: None,
+        };
+
+        let mut out = Vec::new();
+        write_report(&mut out, Format::Json, &report).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let alice = &json["individual_stats"]["alice"];
+        assert_eq!(alice["issues_completed"], 3.);
+        assert_eq!(alice["issues_opened"], 0);
+        assert_eq!(alice["bugs_reported"], 0);
+        assert_eq!(alice["merged_merge_requests_opened"], 2);
+        assert_eq!(alice["merge_request_notes"], 0);
+        assert_eq!(alice["lines_contributed"], 120.);
+        assert_eq!(json["unknown_emails"][0][0], "bot@example.com");
+    }
+
+    #[test]
+    fn stats_are_broken_down_by_repository() {
+        let merged_at = DateTime::parse_from_rfc3339("2024-01-20T00:00:00Z").unwrap();
+        let pr = |repo: &str| github::MergedPullRequest {
+            author: "alice-gh".to_string(),
+            repo: repo.to_string(),
+            merged_at,
+            comments: 1,
+        };
+        let github_data = || GithubData {
+            open_pull_requests: Vec::new(),
+            assigned_issues: Vec::new(),
+            issue_metadata: Vec::new(),
+            merged_pull_requests: vec![pr("pbmetric"), pr("petabi/pbmetric"), pr("petabi/review")],
+            reviews: vec![github::Review {
+                author: "alice-gh".to_string(),
+                repo: "review".to_string(),
+                submitted_at: merged_at,
+            }],
+            issue_comments: Vec::new(),
+        };
+        let account_map = HashMap::from([("alice-gh".to_string(), "alice".to_string())]);
+        let asof = timestamp("2024-01-31T00:00:00Z");
+        let since = timestamp("2024-01-01T00:00:00Z");
+
+        let report = Report::new(
+            &ReportConfig::default(),
+            github_data(),
+            HashMap::new(),
+            &account_map,
+            &BTreeMap::new(),
+            &since,
+            &asof,
+        );
+        assert!(report.individual_stats["alice"].per_repo.is_empty());
+
+        let report = Report::new(
+            &ReportConfig {
+                per_repo: true,
+                ..ReportConfig::default()
+            },
+            github_data(),
+            HashMap::new(),
+            &account_map,
+            &BTreeMap::new(),
+            &since,
+            &asof,
+        );
+        let alice = &report.individual_stats["alice"];
+        assert_eq!(alice.merged_merge_requests_opened, 3);
+        assert_eq!(
+            alice.per_repo.keys().collect::<Vec<_>>(),
+            ["petabi/pbmetric", "petabi/review"]
+        );
+        assert_eq!(
+            alice.per_repo["petabi/pbmetric"].merged_merge_requests_opened,
+            2
+        );
+        assert_eq!(alice.per_repo["petabi/pbmetric"].reviews_given, 0);
+        assert_eq!(
+            alice.per_repo["petabi/review"].merged_merge_requests_opened,
+            1
+        );
+        assert_eq!(alice.per_repo["petabi/review"].reviews_given, 1);
+
+        let mut out = Vec::new();
+        let mut renderer = Markdown::new(&mut out);
+        renderer.begin_list().unwrap();
+        print_individual_stat(
+            &mut renderer,
+            "alice",
+            alice,
+            None,
+            &since,
+            &asof,
+            Tz::UTC,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        renderer.end_list().unwrap();
+        assert!(String::from_utf8(out).unwrap().contains(
+            "  - By repository\n    - petabi/pbmetric: 0.0 issues completed, 0 issues opened, \
+             0 bugs reported, 2 pull requests merged, 0 reviews, 0 issue comments, 0 lines\n"
+        ));
+    }
+
+    #[test]
+    fn checkouts_are_named_after_their_github_repositories() {
+        assert_eq!(
+            full_name_of("metric", "https://github.com/petabi/pbmetric.git"),
+            "petabi/pbmetric"
+        );
+        assert_eq!(
+            full_name_of("metric", "git@github.com:petabi/pbmetric"),
+            "petabi/pbmetric"
+        );
+        assert_eq!(
+            full_name_of("pbmetric", "https://gitlab.com/petabi/pbmetric.git"),
+            "petabi/pbmetric"
+        );
+        assert_eq!(full_name_of("acme/tool", "/srv/git/tool"), "acme/tool");
+    }
+
+    #[test]
+    fn unknown_emails_show_whole_lines() {
+        let mut out = Vec::new();
+        print_unknown_emails(
+            &mut Markdown::new(&mut out),
+            &[("x@y".to_string(), 2. / 3.), ("z@y".to_string(), 1. / 3.)],
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("- x@y: 1 lines contributed\n"));
+        assert!(out.contains("- z@y: 0 lines contributed\n"));
+    }
+
+    #[test]
+    fn scan_errors_are_returned() {
+        let root = tempfile::TempDir::new().unwrap();
+        let checkout = root.path().join("pbmetric");
+        std::fs::create_dir(&checkout).unwrap();
+        std::fs::write(checkout.join("lib.rs"), "fn a() {}\n").unwrap();
+        for args in [
+            &["init", "-q"][..],
+            &["add", "-A"],
+            &[
+                "-c",
+                "user.name=alice",
+                "-c",
+                "user.email=alice@example.com",
+                "-c",
+                "commit.gpgsign=false",
+                "commit",
+                "-q",
+                "-m",
+                "Add lib.rs",
+            ],
+        ] {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(&checkout)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+        let repos = toml::from_str::<BTreeMap<String, Repo>>(
+            r#"pbmetric = { url = "https://github.com/petabi/pbmetric.git" }"#,
+        )
+        .unwrap();
+        let asof = Utc::now();
+        let since = asof - Duration::try_days(30).unwrap();
+
+        let err = agenda(
+            &ReportConfig::default(),
+            &GithubConfig::default(),
+            &github::Client::new(&github::Tokens::from("token".to_string())),
+            root.path(),
+            &repos,
+            &["(".to_string()],
+            &BTreeMap::new(),
+            &since,
+            &asof,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("cannot scan pbmetric: "));
+
+        std::fs::remove_dir_all(checkout.join(".git")).unwrap();
+        let err = agenda(
+            &ReportConfig::default(),
+            &GithubConfig::default(),
+            &github::Client::new(&github::Tokens::from("token".to_string())),
+            root.path(),
+            &repos,
+            &[],
+            &BTreeMap::new(),
+            &since,
+            &asof,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("cannot scan pbmetric: "));
+    }
+
+    #[test]
+    fn selected_repos_restrict_the_configuration() {
+        let mut github = toml::from_str::<GithubConfig>(
+            r#"
+            repositories = ["pbmetric", "petabi/review", "acme/widget"]
+            account = {}
+            "#,
+        )
+        .unwrap();
+        let mut repos = toml::from_str::<BTreeMap<String, Repo>>(
+            r#"
+            metric = { url = "https://github.com/petabi/pbmetric.git" }
+            review = { url = "https://github.com/petabi/review.git" }
+            tool = { url = "/srv/git/tool" }
+            "#,
+        )
+        .unwrap();
+
+        let names = ["petabi/pbmetric".to_string(), "tool".to_string()];
+        select_repos(&names, &mut github, &mut repos).unwrap();
+        assert_eq!(github.repositories(), ["pbmetric"]);
+        assert_eq!(repos.keys().collect::<Vec<_>>(), ["metric", "tool"]);
+
+        let err = select_repos(&["widget".to_string()], &mut github, &mut repos).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "repository \"widget\" is not in the configuration"
+        );
+    }
+}
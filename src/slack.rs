@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::render::Markdown;
+use crate::report::{render_report, Report};
+
+#[derive(Deserialize)]
+pub struct SlackConfig {
+    /// The URL of an incoming webhook for the channel to post to.
+    webhook_url: String,
+    /// Whether failing to post is only a warning.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    text: &'a str,
+}
+
+/// Posts `report` to the channel of the incoming webhook in `config`.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or Slack rejects it.
+pub fn post(config: &SlackConfig, report: &Report) -> Result<()> {
+    let mut text = Vec::new();
+    render_report(&mut Markdown::slack(&mut text), report)?;
+    let text = String::from_utf8(text)?;
+
+    let res = reqwest::blocking::Client::new()
+        .post(&config.webhook_url)
+        .json(&Message { text: &text })
+        .send()?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(webhook_error(status, &res.text().unwrap_or_default()));
+    }
+    Ok(())
+}
+
+/// Builds an error describing a rejected webhook request. Slack explains the
+/// reason, such as `invalid_token` or `channel_not_found`, in a short body.
+fn webhook_error(status: StatusCode, body: &str) -> anyhow::Error {
+    anyhow!("Slack responded with {status}: {}", body.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_errors_include_reason() {
+        let err = webhook_error(StatusCode::NOT_FOUND, "channel_not_found\n").to_string();
+        assert_eq!(err, "Slack responded with 404 Not Found: channel_not_found");
+    }
+
+    #[test]
+    fn message_payload() {
+        let payload = serde_json::to_string(&Message { text: "*Hello*" }).unwrap();
+        assert_eq!(payload, r#"{"text":"*Hello*"}"#);
+    }
+}
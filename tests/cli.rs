@@ -0,0 +1,431 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+const CONFIG: &str = r#"
+[mail]
+server = "smtp.invalid"
+username = "pbmetric@example.com"
+password = "secret"
+recipient = "team@example.com"
+
+[github]
+token = "token"
+repositories = []
+account = {}
+
+[email_map]
+
+[repos]
+"#;
+
+/// Runs pbmetric with its configuration and cache directories under `home`.
+fn pbmetric(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pbmetric"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .output()
+        .unwrap()
+}
+
+fn home_with_config() -> TempDir {
+    let home = TempDir::new().unwrap();
+    fs::create_dir_all(config_path(home.path()).parent().unwrap()).unwrap();
+    write_config(home.path(), "");
+    home
+}
+
+fn config_path(home: &Path) -> PathBuf {
+    home.join("config")
+        .join(env!("CARGO_PKG_NAME"))
+        .join("config.toml")
+}
+
+/// Writes the configuration under `home`, with `extra` appended to `CONFIG`.
+fn write_config(home: &Path, extra: &str) {
+    fs::write(config_path(home), format!("{CONFIG}{extra}")).unwrap();
+}
+
+/// Creates a git repository at `path` with a single commit of `lib.rs` by
+/// `email`, made at `date` if given or else now.
+fn commit_repo(path: &Path, email: &str, date: Option<&str>) {
+    fs::create_dir_all(path).unwrap();
+    fs::write(path.join("lib.rs"), "fn a() {}\n").unwrap();
+    let email = format!("user.email={email}");
+    for args in [
+        &["init", "-q", "-b", "main"][..],
+        &["add", "-A"],
+        &[
+            "-c",
+            "user.name=dev",
+            "-c",
+            &email,
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "-q",
+            "-m",
+            "Add lib.rs",
+        ],
+    ] {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(path).args(args);
+        if let Some(date) = date {
+            cmd.env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date);
+        }
+        assert!(cmd.status().unwrap().success());
+    }
+}
+
+#[test]
+fn output_to_stdout_skips_mail() {
+    let home = home_with_config();
+    let output = pbmetric(home.path(), &["--output", "-"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("<html><head>"));
+    assert!(stdout.ends_with("</body></html>"));
+}
+
+#[test]
+fn output_to_file() {
+    let home = home_with_config();
+    let path = home.path().join("report.md");
+    let output = pbmetric(
+        home.path(),
+        &["--format", "markdown", "--output", path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let report = fs::read_to_string(path).unwrap();
+    assert!(report.starts_with("## Changes in the Past Week\n"));
+}
+
+#[test]
+fn output_csv_is_written() {
+    let home = home_with_config();
+    let path = home.path().join("stats.csv");
+    let output = pbmetric(
+        home.path(),
+        &["--output", "-", "--output-csv", path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    let csv = fs::read_to_string(path).unwrap();
+    assert!(csv.starts_with("username,bugs_reported,issues_completed,"));
+}
+
+#[test]
+fn dry_run_prints_report_and_summary() {
+    let home = home_with_config();
+    let output = pbmetric(home.path(), &["--dry-run"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("<html><head>"));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Would send \"Project Snapshot "));
+    assert!(stderr.contains("to team@example.com via smtp.invalid"));
+}
+
+#[test]
+fn validate_accepts_good_config() {
+    let home = home_with_config();
+    let output = pbmetric(home.path(), &["validate"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with(": OK\n"));
+}
+
+#[test]
+fn validate_reports_bad_config() {
+    let home = home_with_config();
+    let path = config_path(home.path());
+    fs::write(
+        &path,
+        CONFIG.replace("repositories = []", "repositories = 3"),
+    )
+    .unwrap();
+    let output = pbmetric(home.path(), &["validate"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("repositories"));
+
+    fs::write(&path, format!("exclude = [\"(\"]\n{CONFIG}")).unwrap();
+    let output = pbmetric(home.path(), &["validate"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("invalid exclude pattern"));
+}
+
+#[test]
+fn output_ignores_invalid_mail_addresses() {
+    let home = home_with_config();
+    let path = config_path(home.path());
+    fs::write(&path, CONFIG.replace("team@example.com", "team@")).unwrap();
+    let output = pbmetric(home.path(), &["--output", "-"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = pbmetric(home.path(), &["validate"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("invalid recipient address"));
+}
+
+#[test]
+fn report_subcommand_accepts_report_flags() {
+    let home = home_with_config();
+    let output = pbmetric(
+        home.path(),
+        &[
+            "report",
+            "--offline",
+            "--format",
+            "markdown",
+            "--output",
+            "-",
+        ],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("## Changes in the Past Week\n"));
+}
+
+#[test]
+fn list_repos_prints_clone_paths() {
+    let home = home_with_config();
+    write_config(
+        home.path(),
+        "pbmetric = { url = \"https://github.com/petabi/pbmetric.git\" }\n",
+    );
+    let output = pbmetric(home.path(), &["list-repos"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("pbmetric\thttps://github.com/petabi/pbmetric.git\t"));
+    assert!(stdout.trim_end().ends_with("pbmetric"));
+}
+
+#[test]
+fn since_last_starts_from_previous_report() {
+    let home = home_with_config();
+    let state = home
+        .path()
+        .join("data")
+        .join(env!("CARGO_PKG_NAME"))
+        .join("last_report");
+    let args = [
+        "--since-last",
+        "--asof",
+        "2024-02-01T00:00:00Z",
+        "--format",
+        "json",
+        "--output",
+        "-",
+    ];
+
+    let output = pbmetric(home.path(), &args);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["since"], "2023-11-03T00:00:00Z");
+    assert_eq!(
+        fs::read_to_string(&state).unwrap().trim(),
+        "2024-02-01T00:00:00+00:00"
+    );
+
+    fs::write(&state, "2024-01-20T00:00:00Z\n").unwrap();
+    let output = pbmetric(home.path(), &args);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["since"], "2024-01-20T00:00:00Z");
+}
+
+#[test]
+fn dry_run_output_is_not_recorded() {
+    let home = home_with_config();
+    let output = pbmetric(home.path(), &["--dry-run", "--output", "-"]);
+    assert!(output.status.success());
+    assert!(!home
+        .path()
+        .join("data")
+        .join(env!("CARGO_PKG_NAME"))
+        .join("last_report")
+        .exists());
+}
+
+#[test]
+fn since_and_until_set_the_window() {
+    let home = home_with_config();
+    let output = pbmetric(
+        home.path(),
+        &[
+            "--asof",
+            "2024-03-01T00:00:00Z",
+            "--epoch",
+            "2024-01-25T00:00:00Z",
+            "--since",
+            "2024-01-10T00:00:00Z",
+            "--until",
+            "2024-02-01T00:00:00Z",
+            "--format",
+            "json",
+            "--output",
+            "-",
+        ],
+    );
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["since"], "2024-01-10T00:00:00Z");
+    assert_eq!(report["asof"], "2024-02-01T00:00:00Z");
+    assert_eq!(report["lookback_days"], 22);
+
+    let output = pbmetric(
+        home.path(),
+        &[
+            "--since",
+            "2024-02-01T00:00:00Z",
+            "--until",
+            "2024-01-10T00:00:00Z",
+            "--output",
+            "-",
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("must start before it ends"));
+
+    let output = pbmetric(home.path(), &["--since", "yesterday", "--output", "-"]);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn configured_git_is_run() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = home_with_config();
+    let log = home.path().join("git.log");
+    let git = home.path().join("git-wrapper");
+    fs::write(
+        &git,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\nexec git \"$@\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&git, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = pbmetric(
+        home.path(),
+        &["--git", git.to_str().unwrap(), "--output", "-"],
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&log).unwrap(), "--version\n");
+
+    let missing = home.path().join("no-such-git");
+    let output = pbmetric(
+        home.path(),
+        &["--git", missing.to_str().unwrap(), "--output", "-"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("cannot run"));
+}
+
+#[test]
+fn progress_is_hidden_without_a_terminal() {
+    let home = home_with_config();
+    let origin = home.path().join("origin");
+    commit_repo(&origin, "alice@example.com", None);
+    write_config(
+        home.path(),
+        &format!("origin = {{ url = \"{}\" }}\n", origin.display()),
+    );
+
+    let output = pbmetric(home.path(), &["--format", "json", "--output", "-"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["unknown_emails"][0][0], "alice@example.com");
+    assert!(!String::from_utf8(output.stderr).unwrap().contains(" files"));
+}
+
+#[test]
+fn repo_flag_restricts_the_scanned_repos() {
+    let home = home_with_config();
+    let mut repos = Vec::new();
+    for name in ["alpha", "beta"] {
+        let origin = home.path().join(name);
+        commit_repo(&origin, &format!("{name}@example.com"), None);
+        repos.push(format!("{name} = {{ url = \"{}\" }}\n", origin.display()));
+    }
+    write_config(home.path(), &repos.concat());
+
+    let output = pbmetric(
+        home.path(),
+        &["--repo", "beta", "--format", "json", "--output", "-"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let emails = report["unknown_emails"].as_array().unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0][0], "beta@example.com");
+
+    let output = pbmetric(home.path(), &["--repos", "alpha,gamma", "--output", "-"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("repository \"gamma\" is not in the configuration"));
+}
+
+#[test]
+fn repos_without_commits_in_the_window_are_noted() {
+    let home = home_with_config();
+    let origin = home.path().join("quiet");
+    commit_repo(&origin, "alice@example.com", Some("2020-01-01T00:00:00Z"));
+    write_config(
+        home.path(),
+        &format!("quiet = {{ url = \"{}\" }}\n", origin.display()),
+    );
+
+    let output = pbmetric(home.path(), &["--format", "json", "--output", "-"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["inactive_repos"], serde_json::json!(["quiet"]));
+    assert_eq!(report["unknown_emails"], serde_json::json!([]));
+
+    let output = pbmetric(home.path(), &["--format", "markdown", "--output", "-"]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("- No activity in quiet\n"));
+}